@@ -102,6 +102,180 @@ pub fn serialized_size_bytes<T: Serialize>(obj: &T) -> usize {
     bincode::serialized_size(obj).unwrap() as usize
 }
 
+pub fn to_binary<T: Serialize>(obj: &T) -> Vec<u8> {
+    bincode::serialize(obj).unwrap()
+}
+
+pub fn from_binary<T: DeserializeOwned>(raw: &[u8]) -> Result<T, Error> {
+    bincode::deserialize(raw).map_err(|err| Error::new(ErrorKind::Other, err))
+}
+
+// Like to_binary, but separately encodes each serialized field of a struct. Concatenating the
+// pieces gives the same bytes as to_binary, since bincode doesn't store anything else for structs.
+pub fn to_binary_fields<T: Serialize>(obj: &T) -> Result<Vec<Vec<u8>>, Error> {
+    let mut splitter = FieldSplitter { fields: Vec::new() };
+    obj.serialize(&mut splitter)
+        .map_err(|err| Error::new(ErrorKind::Other, err))?;
+    Ok(splitter.fields)
+}
+
+struct FieldSplitter {
+    fields: Vec<Vec<u8>>,
+}
+
+fn not_a_struct<T>() -> Result<T, bincode::Error> {
+    Err(serde::ser::Error::custom(
+        "to_binary_fields only handles structs",
+    ))
+}
+
+impl<'a> Serializer for &'a mut FieldSplitter {
+    type Ok = ();
+    type Error = bincode::Error;
+    type SerializeSeq = serde::ser::Impossible<(), bincode::Error>;
+    type SerializeTuple = serde::ser::Impossible<(), bincode::Error>;
+    type SerializeTupleStruct = serde::ser::Impossible<(), bincode::Error>;
+    type SerializeTupleVariant = serde::ser::Impossible<(), bincode::Error>;
+    type SerializeMap = serde::ser::Impossible<(), bincode::Error>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = serde::ser::Impossible<(), bincode::Error>;
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, bincode::Error> {
+        Ok(self)
+    }
+
+    fn serialize_bool(self, _: bool) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_i8(self, _: i8) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_i16(self, _: i16) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_i32(self, _: i32) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_i64(self, _: i64) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_u8(self, _: u8) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_u16(self, _: u16) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_u32(self, _: u32) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_u64(self, _: u64) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_f32(self, _: f32) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_f64(self, _: f64) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_char(self, _: char) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_str(self, _: &str) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_bytes(self, _: &[u8]) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_none(self) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_unit(self) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+    ) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, bincode::Error> {
+        not_a_struct()
+    }
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, bincode::Error> {
+        not_a_struct()
+    }
+}
+
+impl<'a> serde::ser::SerializeStruct for &'a mut FieldSplitter {
+    type Ok = ();
+    type Error = bincode::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), bincode::Error> {
+        self.fields.push(bincode::serialize(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), bincode::Error> {
+        Ok(())
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 pub fn write_binary<T: Serialize>(path: String, obj: &T) {
     if let Err(err) = maybe_write_binary(&path, obj) {
@@ -415,3 +589,31 @@ pub fn basename(path: &str) -> String {
 pub fn file_exists(path: String) -> bool {
     Path::new(&path).exists()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Example {
+        x: usize,
+        #[serde(skip_serializing)]
+        _skipped: usize,
+        names: Vec<String>,
+    }
+
+    #[test]
+    fn test_to_binary_fields() {
+        let obj = Example {
+            x: 42,
+            _skipped: 3,
+            names: vec!["a".to_string(), "bc".to_string()],
+        };
+        let fields = to_binary_fields(&obj).unwrap();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0], to_binary(&42_usize));
+        assert_eq!(fields.concat(), to_binary(&obj));
+
+        assert!(to_binary_fields(&vec![1, 2, 3]).is_err());
+    }
+}
@@ -16,9 +16,10 @@ pub use crate::collections::{
 pub use crate::error::Error;
 pub use crate::io::{
     basename, deserialize_btreemap, deserialize_multimap, file_exists, find_next_file,
-    find_prev_file, list_all_objects, list_dir, load_all_objects, maybe_read_binary,
+    find_prev_file, from_binary, list_all_objects, list_dir, load_all_objects, maybe_read_binary,
     maybe_read_json, read_binary, read_json, serialize_btreemap, serialize_multimap,
-    serialized_size_bytes, slurp_file, to_json, write_binary, write_json, FileWithProgress,
+    serialized_size_bytes, slurp_file, to_binary, to_binary_fields, to_json, write_binary,
+    write_json, FileWithProgress,
 };
 pub use crate::logs::Warn;
 pub use crate::random::{fork_rng, WeightedUsizeChoice};
@@ -130,6 +131,10 @@ pub fn path_popdat() -> String {
     format!("../data/input/seattle/popdat.bin")
 }
 
+pub fn path_census(map_name: &str) -> String {
+    format!("../data/input/census/{}.geojson", map_name)
+}
+
 pub fn path_raw_map(map_name: &str) -> String {
     format!("../data/input/raw_maps/{}.bin", map_name)
 }
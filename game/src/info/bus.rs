@@ -20,6 +20,14 @@ pub fn stop(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusStopID)
         header_btns(ctx),
     ]));
     rows.push(format!("On {}", app.primary.map.get_parent(id.sidewalk).get_name()).draw_text(ctx));
+    rows.push(
+        format!(
+            "{} people here",
+            sim.get_pedestrians_at_transit_stop(id, &app.primary.map)
+                .len()
+        )
+        .draw_text(ctx),
+    );
 
     let all_arrivals = &sim.get_analytics().bus_arrivals;
     for r in app.primary.map.get_routes_serving_stop(id) {
@@ -276,7 +276,7 @@ impl InfoPanel {
                     // Some objects are much wider/taller than others
                     let multiplier = match id {
                         ID::Car(c) => {
-                            if c.1 == VehicleType::Bike {
+                            if c.1.is_bike() {
                                 3.0
                             } else {
                                 0.75
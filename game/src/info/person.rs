@@ -274,7 +274,7 @@ pub fn bio(
 
     let mut has_bike = false;
     for v in &person.vehicles {
-        if v.vehicle_type == VehicleType::Bike {
+        if v.vehicle_type.is_bike() {
             has_bike = true;
         } else {
             if app.primary.sim.lookup_parked_car(v.id).is_some() {
@@ -445,12 +445,14 @@ pub fn parked_car(
 
     // TODO how long idle, prev trips, next trips, etc
 
-    let p = app.primary.sim.get_owner_of_car(id).unwrap();
-    rows.push(Btn::text_bg2(format!("Owned by {}", p)).build_def(ctx, None));
-    details.hyperlinks.insert(
-        format!("Owned by {}", p),
-        Tab::PersonTrips(p, BTreeMap::new()),
-    );
+    // Ride-hailing drivers aren't simulated as people
+    if let Some(p) = app.primary.sim.get_owner_of_car(id) {
+        rows.push(Btn::text_bg2(format!("Owned by {}", p)).build_def(ctx, None));
+        details.hyperlinks.insert(
+            format!("Owned by {}", p),
+            Tab::PersonTrips(p, BTreeMap::new()),
+        );
+    }
 
     if let Some(p) = app.primary.sim.lookup_parked_car(id) {
         match p.spot {
@@ -509,13 +511,13 @@ fn header(
                         Some("../data/system/assets/meters/pedestrian.svg"),
                     ),
                     AgentID::Car(c) => match c.1 {
-                        VehicleType::Car => {
+                        VehicleType::Car | VehicleType::DeliveryTruck => {
                             ("driving", Some("../data/system/assets/meters/car.svg"))
                         }
-                        VehicleType::Bike => {
+                        VehicleType::Bike | VehicleType::EBike | VehicleType::CargoBike => {
                             ("biking", Some("../data/system/assets/meters/bike.svg"))
                         }
-                        VehicleType::Bus => unreachable!(),
+                        VehicleType::Bus | VehicleType::SchoolBus => unreachable!(),
                     },
                     AgentID::BusPassenger(_, _) => {
                         ("riding a bus", Some("../data/system/assets/meters/bus.svg"))
@@ -59,9 +59,9 @@ pub fn ongoing(
     let activity = match agent {
         AgentID::Pedestrian(_) => "walking",
         AgentID::Car(c) => match c.1 {
-            VehicleType::Car => "driving",
-            VehicleType::Bike => "biking",
-            VehicleType::Bus => unreachable!(),
+            VehicleType::Car | VehicleType::DeliveryTruck => "driving",
+            VehicleType::Bike | VehicleType::EBike | VehicleType::CargoBike => "biking",
+            VehicleType::Bus | VehicleType::SchoolBus => unreachable!(),
         },
         AgentID::BusPassenger(_, _) => "riding the bus",
     };
@@ -192,7 +192,7 @@ fn thick_line_from_angle(
 }
 
 fn zoomed_color_car(input: &DrawCarInput, cs: &ColorScheme) -> Color {
-    if input.id.1 == VehicleType::Bus {
+    if input.id.1 == VehicleType::Bus || input.id.1 == VehicleType::SchoolBus {
         cs.bus_body
     } else {
         match input.status {
@@ -506,9 +506,11 @@ impl AgentColorScheme {
 
     fn color(&self, agent: &UnzoomedAgent) -> Option<Color> {
         let category = match agent.vehicle_type {
-            Some(VehicleType::Car) => "Car".to_string(),
-            Some(VehicleType::Bike) => "Bike".to_string(),
-            Some(VehicleType::Bus) => "Bus".to_string(),
+            Some(VehicleType::Car) | Some(VehicleType::DeliveryTruck) => "Car".to_string(),
+            Some(VehicleType::Bike) | Some(VehicleType::EBike) | Some(VehicleType::CargoBike) => {
+                "Bike".to_string()
+            }
+            Some(VehicleType::Bus) | Some(VehicleType::SchoolBus) => "Bus".to_string(),
             None => "Pedestrian".to_string(),
         };
         for (name, color, enabled) in &self.rows {
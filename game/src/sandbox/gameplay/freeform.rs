@@ -142,6 +142,12 @@ pub fn make_change_traffic(btn: ScreenRectangle, current: String) -> Box<dyn Sta
                         list.push(Choice::new(name.clone(), name));
                     }
                 }
+                if abstutil::file_exists(abstutil::path_census(app.primary.map.get_name())) {
+                    list.push(
+                        Choice::new("trips generated from census data", "census".to_string())
+                            .tooltip("Everybody commutes from home to somewhere else and back."),
+                    );
+                }
                 list.push(
                     Choice::new("random unrealistic trips", "random".to_string()).tooltip(
                         "Lots of trips will start at midnight, but not constantly appear through \
@@ -514,7 +520,7 @@ pub fn spawn_agents_around(i: IntersectionID, app: &mut App) {
                     Scenario::rand_ped_speed(&mut rng),
                     vec![vehicle_spec.clone()],
                 );
-                spawner.schedule_trip(
+                if let Err(err) = spawner.schedule_trip(
                     person,
                     now,
                     TripSpec::VehicleAppearing {
@@ -532,11 +538,13 @@ pub fn spawn_agents_around(i: IntersectionID, app: &mut App) {
                     TripEndpoint::Border(lane.src_i, None),
                     false,
                     map,
-                );
+                ) {
+                    println!("Not spawning a trip: {}", err);
+                }
             }
         } else if lane.is_sidewalk() {
             for _ in 0..5 {
-                spawner.schedule_trip(
+                if let Err(err) = spawner.schedule_trip(
                     sim.random_person(Scenario::rand_ped_speed(&mut rng), Vec::new()),
                     now,
                     TripSpec::JustWalking {
@@ -553,7 +561,9 @@ pub fn spawn_agents_around(i: IntersectionID, app: &mut App) {
                     TripEndpoint::Border(lane.src_i, None),
                     false,
                     map,
-                );
+                ) {
+                    println!("Not spawning a trip: {}", err);
+                }
             }
         }
     }
@@ -114,6 +114,15 @@ impl GameplayMode {
                 ScenarioGenerator::small_run(map)
             })
             .generate(map, &mut rng, &mut Timer::new("generate scenario"))
+        } else if name == "census" {
+            let path = abstutil::path_census(map.get_name());
+            match sim::load_population_from_census(path.clone(), map, &mut rng, timer) {
+                Ok(people) => sim::census_scenario("census", &people, map, &mut rng),
+                Err(err) => {
+                    println!("\n\nCouldn't load census data from {}: {}", path, err);
+                    std::process::exit(1);
+                }
+            }
         } else {
             let path = abstutil::path_scenario(map.get_name(), &name);
             let mut scenario = match abstutil::maybe_read_binary(path.clone(), timer) {
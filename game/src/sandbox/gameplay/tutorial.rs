@@ -1370,7 +1370,7 @@ pub fn execute(_: &mut EventCtx, app: &mut App, id: ID, action: String) -> Trans
                         ],
                     )
                 }
-            } else if c.1 == VehicleType::Bike {
+            } else if c.1.is_bike() {
                 msg(
                     "That's a bike",
                     vec![
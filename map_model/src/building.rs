@@ -14,6 +14,14 @@ impl fmt::Display for BuildingID {
     }
 }
 
+// There's no land use data, so buildings with amenities count as commercial and everything else as
+// residential.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum BuildingType {
+    Residential,
+    Commercial,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FrontPath {
     pub sidewalk: Position,
@@ -66,4 +74,18 @@ impl Building {
             None
         }
     }
+
+    pub fn is_fuel_station(&self) -> bool {
+        self.amenities
+            .iter()
+            .any(|(_, a)| a == "fuel" || a == "charging_station")
+    }
+
+    pub fn bldg_type(&self) -> BuildingType {
+        if self.amenities.is_empty() {
+            BuildingType::Residential
+        } else {
+            BuildingType::Commercial
+        }
+    }
 }
@@ -1,16 +1,35 @@
-use crate::{LaneID, Map, PathConstraints};
+use crate::{
+    BuildingID, IntersectionID, LaneID, LaneType, Map, Path, PathConstraints, PathRequest,
+    PathStep, Position, RoadID,
+};
 use abstutil::Timer;
+use geom::Distance;
 use petgraph::graphmap::DiGraphMap;
-use std::collections::{HashSet, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::ops::Add;
 
 // SCC = strongly connected component
 
 // Returns (relevant lanes in main component, disconnected relevant lanes)
 pub fn find_scc(map: &Map, constraints: PathConstraints) -> (HashSet<LaneID>, HashSet<LaneID>) {
+    find_scc_without(map, constraints, &HashSet::new())
+}
+
+// Same as find_scc, but pretends some lanes are closed. The closed lanes aren't returned in either
+// set.
+pub fn find_scc_without(
+    map: &Map,
+    constraints: PathConstraints,
+    closed: &HashSet<LaneID>,
+) -> (HashSet<LaneID>, HashSet<LaneID>) {
     let mut graph = DiGraphMap::new();
     for turn in map.all_turns().values() {
         if constraints.can_use(map.get_l(turn.id.src), map)
             && constraints.can_use(map.get_l(turn.id.dst), map)
+            && !closed.contains(&turn.id.src)
+            && !closed.contains(&turn.id.dst)
         {
             graph.add_edge(turn.id.src, turn.id.dst, 1);
         }
@@ -29,7 +48,10 @@ pub fn find_scc(map: &Map, constraints: PathConstraints) -> (HashSet<LaneID>, Ha
         .all_lanes()
         .iter()
         .filter_map(|l| {
-            if constraints.can_use(l, map) && !largest_group.contains(&l.id) {
+            if constraints.can_use(l, map)
+                && !largest_group.contains(&l.id)
+                && !closed.contains(&l.id)
+            {
                 Some(l.id)
             } else {
                 None
@@ -39,6 +61,314 @@ pub fn find_scc(map: &Map, constraints: PathConstraints) -> (HashSet<LaneID>, Ha
     (largest_group, disconnected)
 }
 
+// The fraction of (ordered) pairs of distinct buildings where the first can reach the second using
+// only bike lanes, starting and ending on a bike lane along each building's road. Considers every
+// pair, so there's no need to sample.
+pub fn bike_lane_network_connectivity(map: &Map) -> f64 {
+    let n = map.all_buildings().len();
+    if n < 2 {
+        return 0.0;
+    }
+
+    let mut graph = DiGraphMap::new();
+    for l in map.all_lanes() {
+        if l.lane_type == LaneType::Biking {
+            graph.add_node(l.id);
+        }
+    }
+    for turn in map.all_turns().values() {
+        if map.get_l(turn.id.src).lane_type == LaneType::Biking
+            && map.get_l(turn.id.dst).lane_type == LaneType::Biking
+        {
+            graph.add_edge(turn.id.src, turn.id.dst, 1);
+        }
+    }
+    let mut bldgs_per_lane: HashMap<LaneID, usize> = HashMap::new();
+    for b in map.all_buildings() {
+        if let Ok(l) = map.find_closest_lane(b.sidewalk(), vec![LaneType::Biking]) {
+            *bldgs_per_lane.entry(l).or_insert(0) += 1;
+        }
+    }
+
+    // Every lane in a strongly connected component reaches the same lanes, so only search once per
+    // component.
+    let mut component_per_lane: HashMap<LaneID, usize> = HashMap::new();
+    for (idx, component) in petgraph::algo::kosaraju_scc(&graph).into_iter().enumerate() {
+        for l in component {
+            component_per_lane.insert(l, idx);
+        }
+    }
+    let mut reachable_bldgs_per_component: HashMap<usize, usize> = HashMap::new();
+    let mut connected_pairs = 0;
+    for (l, num_bldgs) in &bldgs_per_lane {
+        let reachable = *reachable_bldgs_per_component
+            .entry(component_per_lane[l])
+            .or_insert_with(|| {
+                let (reached, _) = dijkstra(
+                    vec![(*l, 0)],
+                    |l1, _| graph.neighbors(l1).map(|l2| (l2, 1)).collect(),
+                    |_, _| false,
+                );
+                reached
+                    .keys()
+                    .map(|l2| bldgs_per_lane.get(l2).cloned().unwrap_or(0))
+                    .sum()
+            });
+        // The search includes the start lane, so don't count each building reaching itself.
+        connected_pairs += num_bldgs * (reachable - 1);
+    }
+    (connected_pairs as f64) / ((n * (n - 1)) as f64)
+}
+
+// Roads that can't be reached from any border by following roads sharing an intersection. Nothing
+// can spawn from off-map onto these.
+pub fn roads_unreachable_from_borders(map: &Map) -> Vec<RoadID> {
+    let mut visited: HashSet<RoadID> = HashSet::new();
+    let mut queue: VecDeque<RoadID> = VecDeque::new();
+    for i in map.all_intersections() {
+        if i.is_border() {
+            for r in &i.roads {
+                if visited.insert(*r) {
+                    queue.push_back(*r);
+                }
+            }
+        }
+    }
+    while let Some(current) = queue.pop_front() {
+        for next in map.get_next_roads(current) {
+            if visited.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    map.all_roads()
+        .iter()
+        .filter(|r| !visited.contains(&r.id))
+        .map(|r| r.id)
+        .collect()
+}
+
+// Groups roads into strongly connected components, where turns (for any mode) connect the roads
+// of their lanes. Roads without any turns are their own component.
+pub fn road_scc(map: &Map) -> Vec<Vec<RoadID>> {
+    let mut graph = DiGraphMap::new();
+    for r in map.all_roads() {
+        graph.add_node(r.id);
+    }
+    for turn in map.all_turns().values() {
+        graph.add_edge(
+            map.get_l(turn.id.src).parent,
+            map.get_l(turn.id.dst).parent,
+            1,
+        );
+    }
+    petgraph::algo::tarjan_scc(&graph)
+}
+
+// Plain Dijkstra over any graph, for queries that close or avoid parts of the map. It doesn't use
+// the Pathfinder, so nothing has to be recalculated, but it's slow. Starts from some nodes with an
+// initial cost each. next(node, cost) returns the edges leaving a node and their costs. Stops once
+// done(node, cost) is true for the next node settled. Returns the best cost found for every node
+// and the node each one was reached from.
+pub fn dijkstra<N, C, F, D>(
+    starts: Vec<(N, C)>,
+    mut next: F,
+    mut done: D,
+) -> (HashMap<N, C>, HashMap<N, N>)
+where
+    N: Copy + Eq + Hash + Ord,
+    C: Copy + Ord + Add<Output = C>,
+    F: FnMut(N, C) -> Vec<(N, C)>,
+    D: FnMut(N, C) -> bool,
+{
+    let mut best: HashMap<N, C> = HashMap::new();
+    let mut backrefs: HashMap<N, N> = HashMap::new();
+    let mut queue: BinaryHeap<(Reverse<C>, N)> = BinaryHeap::new();
+    for (node, cost) in starts {
+        if best.get(&node).map(|c| cost < *c).unwrap_or(true) {
+            best.insert(node, cost);
+            queue.push((Reverse(cost), node));
+        }
+    }
+
+    while let Some((Reverse(cost), current)) = queue.pop() {
+        if cost > best[&current] {
+            continue;
+        }
+        if done(current, cost) {
+            break;
+        }
+        for (node, edge) in next(current, cost) {
+            let next_cost = cost + edge;
+            if best.get(&node).map(|c| next_cost < *c).unwrap_or(true) {
+                best.insert(node, next_cost);
+                backrefs.insert(node, current);
+                queue.push((Reverse(next_cost), node));
+            }
+        }
+    }
+    (best, backrefs)
+}
+
+// The lanes reachable from the end of one lane, and the distance to the end of each.
+fn next_lanes(map: &Map, l: LaneID, constraints: PathConstraints) -> Vec<(LaneID, Distance)> {
+    map.get_turns_for(l, constraints)
+        .into_iter()
+        .map(|t| (t.id.dst, t.geom.length() + map.get_l(t.id.dst).length()))
+        .collect()
+}
+
+// Length of the shortest path from the start of one lane to the end of another, pretending some
+// roads are closed.
+pub fn shortest_distance_without(
+    map: &Map,
+    constraints: PathConstraints,
+    start: LaneID,
+    end: LaneID,
+    closed: &HashSet<RoadID>,
+) -> Option<Distance> {
+    if closed.contains(&map.get_l(start).parent) || closed.contains(&map.get_l(end).parent) {
+        return None;
+    }
+    let (best, _) = dijkstra(
+        vec![(start, map.get_l(start).length())],
+        |l, _| {
+            next_lanes(map, l, constraints)
+                .into_iter()
+                .filter(|(next, _)| !closed.contains(&map.get_l(*next).parent))
+                .collect()
+        },
+        |l, _| l == end,
+    );
+    best.get(&end).cloned()
+}
+
+// The shortest distance to the end of every lane reachable from some starting lanes. Each start
+// has an initial distance, usually its own length.
+pub fn lane_distances(
+    map: &Map,
+    constraints: PathConstraints,
+    starts: Vec<(LaneID, Distance)>,
+) -> HashMap<LaneID, Distance> {
+    dijkstra(starts, |l, _| next_lanes(map, l, constraints), |_, _| false).0
+}
+
+// Like Map::pathfind, but never enters some lanes.
+pub fn pathfind_avoiding_lanes(
+    map: &Map,
+    req: PathRequest,
+    avoid: &HashSet<LaneID>,
+) -> Option<Path> {
+    let start = req.start.lane();
+    let end = req.end.lane();
+    if avoid.contains(&start) || avoid.contains(&end) {
+        return None;
+    }
+    let (best, backrefs) = dijkstra(
+        vec![(start, map.get_l(start).length())],
+        |l, _| {
+            next_lanes(map, l, req.constraints)
+                .into_iter()
+                .filter(|(next, _)| !avoid.contains(next))
+                .collect()
+        },
+        |l, _| l == end,
+    );
+    if !best.contains_key(&end) {
+        return None;
+    }
+
+    let mut steps = vec![PathStep::Lane(end)];
+    let mut at = end;
+    while let Some(prev) = backrefs.get(&at) {
+        let turn = map
+            .get_turns_for(*prev, req.constraints)
+            .into_iter()
+            .filter(|t| t.id.dst == at)
+            .min_by_key(|t| t.geom.length())
+            .unwrap();
+        steps.push(PathStep::Turn(turn.id));
+        steps.push(PathStep::Lane(*prev));
+        at = *prev;
+    }
+    steps.reverse();
+    Some(Path::new(map, steps, req.end.dist_along()))
+}
+
+// Every building within some walking distance of a position on a sidewalk.
+pub fn walking_isochrone(map: &Map, start: Position, max_dist: Distance) -> HashSet<BuildingID> {
+    let dists = walking_distances(map, start, max_dist);
+    map.all_buildings()
+        .iter()
+        .filter(|b| {
+            walking_distance_to(map, &dists, start, b.front_path.sidewalk)
+                .map(|d| d <= max_dist)
+                .unwrap_or(false)
+        })
+        .map(|b| b.id)
+        .collect()
+}
+
+// The walking distance from a position on a sidewalk to every intersection within max_dist.
+// TODO Crossing the street is free.
+pub fn walking_distances(
+    map: &Map,
+    start: Position,
+    max_dist: Distance,
+) -> HashMap<IntersectionID, Distance> {
+    let start_lane = map.get_l(start.lane());
+    dijkstra(
+        vec![
+            (start_lane.src_i, start.dist_along()),
+            (start_lane.dst_i, start_lane.length() - start.dist_along()),
+        ],
+        |current, dist| {
+            let i = map.get_i(current);
+            i.incoming_lanes
+                .iter()
+                .chain(i.outgoing_lanes.iter())
+                .map(|l| map.get_l(*l))
+                .filter(|lane| lane.is_sidewalk() && dist + lane.length() <= max_dist)
+                .map(|lane| {
+                    let next = if lane.src_i == current {
+                        lane.dst_i
+                    } else {
+                        lane.src_i
+                    };
+                    (next, lane.length())
+                })
+                .collect()
+        },
+        |_, _| false,
+    )
+    .0
+}
+
+// Using the result of walking_distances from start, how far is it to walk to another position on
+// a sidewalk? None if it's farther than the max_dist used there.
+pub fn walking_distance_to(
+    map: &Map,
+    dists: &HashMap<IntersectionID, Distance>,
+    start: Position,
+    pos: Position,
+) -> Option<Distance> {
+    let lane = map.get_l(pos.lane());
+    let mut dist = vec![
+        dists.get(&lane.src_i).map(|d| *d + pos.dist_along()),
+        dists
+            .get(&lane.dst_i)
+            .map(|d| *d + lane.length() - pos.dist_along()),
+    ]
+    .into_iter()
+    .flatten()
+    .min();
+    if pos.lane() == start.lane() {
+        let direct = (pos.dist_along() - start.dist_along()).abs();
+        dist = Some(dist.map(|d| d.min(direct)).unwrap_or(direct));
+    }
+    dist
+}
+
 // Returns list of (driving lane, redirect here instead for parking)
 //
 // It's a bit weird to never attempt parking on roads not part of the largest SCC of the graph.
@@ -93,3 +423,63 @@ fn bidi_flood(map: &Map, start: LaneID, largest_group: &HashSet<LaneID>) -> Opti
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::RawMap;
+    use crate::RoadSpec;
+
+    fn grid_with_bike_lanes(fwd: Vec<LaneType>, back: Vec<LaneType>) -> Map {
+        Map::create_from_raw(
+            RawMap::synthetic_grid(4, 4, &RoadSpec { fwd, back }),
+            true,
+            &mut Timer::throwaway(),
+        )
+    }
+
+    #[test]
+    fn test_bike_lane_network_connectivity() {
+        use LaneType::{Biking, Driving, Sidewalk};
+
+        let map = grid_with_bike_lanes(
+            vec![Driving, Biking, Sidewalk],
+            vec![Driving, Biking, Sidewalk],
+        );
+        assert_eq!(bike_lane_network_connectivity(&map), 1.0);
+        assert_eq!(map.get_bike_lane_network_connectivity(), 1.0);
+
+        let map = grid_with_bike_lanes(vec![Driving, Sidewalk], vec![Driving, Sidewalk]);
+        assert_eq!(bike_lane_network_connectivity(&map), 0.0);
+
+        // One-way bike lanes heading away from the top-left corner. No two lanes reach each other,
+        // but buildings near the top-left can still reach the ones further along.
+        let map = grid_with_bike_lanes(vec![Driving, Biking, Sidewalk], vec![Driving, Sidewalk]);
+        let pct = bike_lane_network_connectivity(&map);
+        assert!(pct > 0.0 && pct < 1.0);
+    }
+
+    #[test]
+    fn test_dijkstra() {
+        // 0 -> 1 -> 3 is cheaper than 0 -> 2 -> 3
+        let edges: HashMap<usize, Vec<(usize, usize)>> = vec![
+            (0, vec![(1, 1), (2, 1)]),
+            (1, vec![(3, 1)]),
+            (2, vec![(3, 5)]),
+            (3, vec![(4, 1)]),
+        ]
+        .into_iter()
+        .collect();
+        let next = |n: usize, _| edges.get(&n).cloned().unwrap_or_else(Vec::new);
+
+        let (best, backrefs) = dijkstra(vec![(0, 0)], next, |_, _| false);
+        assert_eq!(best[&3], 2);
+        assert_eq!(best[&4], 3);
+        assert_eq!(backrefs[&3], 1);
+
+        // Stopping early leaves later nodes unsettled
+        let (best, _) = dijkstra(vec![(0, 0)], next, |n, _| n == 3);
+        assert_eq!(best[&3], 2);
+        assert!(!best.contains_key(&4));
+    }
+}
@@ -20,7 +20,7 @@ mod turn;
 mod zone;
 
 pub use crate::area::{Area, AreaID, AreaType};
-pub use crate::building::{Building, BuildingID, FrontPath, OffstreetParking};
+pub use crate::building::{Building, BuildingID, BuildingType, FrontPath, OffstreetParking};
 pub use crate::bus_stop::{BusRoute, BusRouteID, BusStop, BusStopID};
 pub use crate::city::City;
 pub use crate::edits::{
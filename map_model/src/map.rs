@@ -1,4 +1,4 @@
-use crate::pathfind::Pathfinder;
+use crate::pathfind::{k_shortest_walking_paths, Pathfinder};
 use crate::raw::{DrivingSide, OriginalIntersection, OriginalRoad, RawMap};
 use crate::{
     connectivity, make, osm, Area, AreaID, Building, BuildingID, BusRoute, BusRouteID, BusStop,
@@ -11,6 +11,10 @@ use abstutil::{deserialize_btreemap, serialize_btreemap, Error, Timer, Warn};
 use geom::{Angle, Bounds, Distance, GPSBounds, Line, PolyLine, Polygon, Pt2D, Speed};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::sync::Mutex;
+
+// How many building pairs to check when estimating the network diameter
+const NUM_PATH_LENGTH_SAMPLES: usize = 200;
 
 #[derive(Serialize, Deserialize)]
 pub struct Map {
@@ -50,6 +54,12 @@ pub struct Map {
     name: String,
     #[serde(skip_serializing, skip_deserializing)]
     edits: MapEdits,
+    // Pairs of turns in each intersection that physically cross, filled in lazily
+    #[serde(skip_serializing, skip_deserializing)]
+    turn_conflicts: Mutex<BTreeMap<IntersectionID, BTreeSet<(TurnID, TurnID)>>>,
+    // Filled in lazily, and reset by edits
+    #[serde(skip_serializing, skip_deserializing)]
+    bike_lane_connectivity: Mutex<Option<f64>>,
 }
 
 impl Map {
@@ -146,6 +156,8 @@ impl Map {
             city_name: "blank city".to_string(),
             name: "blank".to_string(),
             edits: MapEdits::new(),
+            turn_conflicts: Mutex::new(BTreeMap::new()),
+            bike_lane_connectivity: Mutex::new(None),
         }
     }
 
@@ -280,6 +292,14 @@ impl Map {
         &self.buildings
     }
 
+    pub fn get_buildings_in_polygon(&self, polygon: &Polygon) -> Vec<BuildingID> {
+        self.buildings
+            .iter()
+            .filter(|b| polygon.contains_pt(b.label_center))
+            .map(|b| b.id)
+            .collect()
+    }
+
     pub fn all_areas(&self) -> &Vec<Area> {
         &self.areas
     }
@@ -386,6 +406,27 @@ impl Map {
         self.get_i(id).turns.iter().map(move |t| self.get_t(*t))
     }
 
+    // Every pair of turns in the intersection whose paths physically cross, in both orders.
+    pub fn get_turn_conflicts(&self, id: IntersectionID) -> BTreeSet<(TurnID, TurnID)> {
+        let mut cache = self.turn_conflicts.lock().unwrap();
+        cache
+            .entry(id)
+            .or_insert_with(|| {
+                let turns: Vec<&Turn> = self.get_turns_in_intersection(id).collect();
+                let mut conflicts = BTreeSet::new();
+                for t1 in &turns {
+                    for t2 in &turns {
+                        if t1.id < t2.id && t1.conflicts_with(t2) {
+                            conflicts.insert((t1.id, t2.id));
+                            conflicts.insert((t2.id, t1.id));
+                        }
+                    }
+                }
+                conflicts
+            })
+            .clone()
+    }
+
     // The turns may belong to two different intersections!
     pub fn get_turns_from_lane(&self, l: LaneID) -> Vec<&Turn> {
         let lane = self.get_l(l);
@@ -570,6 +611,21 @@ impl Map {
         abstutil::write_binary(abstutil::path_map(&self.name), self);
     }
 
+    // See connectivity::bike_lane_network_connectivity. This searches from every bike lane, so the
+    // result is cached until the map is edited.
+    pub fn get_bike_lane_network_connectivity(&self) -> f64 {
+        *self
+            .bike_lane_connectivity
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| connectivity::bike_lane_network_connectivity(self))
+    }
+
+    // Not cached; this is meant for debugging map imports.
+    pub fn find_strongly_connected_components(&self) -> Vec<Vec<RoadID>> {
+        connectivity::road_scc(self)
+    }
+
     pub fn find_closest_lane(&self, from: LaneID, types: Vec<LaneType>) -> Result<LaneID, Error> {
         self.get_parent(from).find_closest_lane(from, types)
     }
@@ -681,6 +737,107 @@ impl Map {
         self.pathfinder.as_ref().unwrap().pathfind(req, self)
     }
 
+    // Up to k different walking paths, shortest first
+    pub fn pathfind_walking_alternatives(&self, req: &PathRequest, k: usize) -> Vec<Path> {
+        k_shortest_walking_paths(req, k, self)
+    }
+
+    pub fn pathfind_avoiding_lanes(
+        &self,
+        req: PathRequest,
+        avoid: &HashSet<LaneID>,
+    ) -> Option<Path> {
+        connectivity::pathfind_avoiding_lanes(self, req, avoid)
+    }
+
+    // The longest driving distance between a sample of building pairs, approximating the
+    // diameter of the road network. None if no pairs are connected.
+    pub fn get_road_network_diameter(&self, timer: &mut Timer) -> Option<Distance> {
+        self.sample_path_lengths(NUM_PATH_LENGTH_SAMPLES, timer)
+            .into_iter()
+            .max()
+    }
+
+    pub fn get_average_path_length(&self, n_samples: usize, timer: &mut Timer) -> Option<Distance> {
+        let lengths = self.sample_path_lengths(n_samples, timer);
+        if lengths.is_empty() {
+            return None;
+        }
+        let n = lengths.len() as f64;
+        Some(lengths.into_iter().fold(Distance::ZERO, |sum, d| sum + d) / n)
+    }
+
+    // Walking distance between pairs of buildings divided by the straight-line distance,
+    // averaged over up to n_samples pairs. 1.0 means every route is perfectly straight.
+    pub fn mean_pedestrian_directness(&self, n_samples: usize, timer: &mut Timer) -> Option<f64> {
+        let requests: Vec<PathRequest> = self
+            .sample_bldg_pairs(n_samples)
+            .into_iter()
+            .map(|(b1, b2)| PathRequest {
+                start: self.get_b(b1).front_path.sidewalk,
+                end: self.get_b(b2).front_path.sidewalk,
+                constraints: PathConstraints::Pedestrian,
+            })
+            .collect();
+        let ratios: Vec<f64> = timer
+            .parallelize("sample walking directness", requests, |req| {
+                let euclidean = req.start.pt(self).dist_to(req.end.pt(self));
+                if euclidean == Distance::ZERO {
+                    return None;
+                }
+                let path = self.pathfind(req.clone())?;
+                let walked = path.trace(self, req.start.dist_along(), None)?.length();
+                Some(walked / euclidean)
+            })
+            .into_iter()
+            .flatten()
+            .collect();
+        if ratios.is_empty() {
+            return None;
+        }
+        Some(ratios.iter().sum::<f64>() / (ratios.len() as f64))
+    }
+
+    // Up to n_samples pairs of buildings. Pairs are spread evenly through the list of buildings
+    // instead of picked randomly, so the result is deterministic.
+    fn sample_bldg_pairs(&self, n_samples: usize) -> Vec<(BuildingID, BuildingID)> {
+        let num_bldgs = self.buildings.len();
+        if num_bldgs == 0 {
+            return Vec::new();
+        }
+        (0..n_samples)
+            .map(|i| {
+                let b1 = BuildingID((i * num_bldgs / n_samples) % num_bldgs);
+                let b2 = BuildingID((b1.0 + num_bldgs / 2 + i) % num_bldgs);
+                (b1, b2)
+            })
+            .collect()
+    }
+
+    // Driving distances between pairs of buildings. Unreachable pairs are skipped.
+    fn sample_path_lengths(&self, n_samples: usize, timer: &mut Timer) -> Vec<Distance> {
+        let requests: Vec<PathRequest> = self
+            .sample_bldg_pairs(n_samples)
+            .into_iter()
+            .map(|(b1, b2)| {
+                let l1 = self.find_driving_lane_near_building(b1);
+                let l2 = self.find_driving_lane_near_building(b2);
+                PathRequest {
+                    start: Position::new(l1, Distance::ZERO),
+                    end: Position::new(l2, self.get_l(l2).length()),
+                    constraints: PathConstraints::Car,
+                }
+            })
+            .collect();
+        timer
+            .parallelize("sample path lengths", requests, |req| {
+                self.pathfind(req).map(|path| path.total_length())
+            })
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
     pub fn should_use_transit(
         &self,
         start: Position,
@@ -874,6 +1031,20 @@ impl Map {
         new_edits.update_derived(self);
         self.edits = new_edits;
         self.pathfinder_dirty = true;
+        {
+            let cache = self.turn_conflicts.get_mut().unwrap();
+            for t in effects
+                .deleted_turns
+                .iter()
+                .chain(effects.added_turns.iter())
+            {
+                cache.remove(&t.parent);
+            }
+            for i in &effects.changed_intersections {
+                cache.remove(i);
+            }
+        }
+        *self.bike_lane_connectivity.get_mut().unwrap() = None;
         (
             // TODO We just care about contraflow roads here
             effects.changed_roads,
@@ -940,6 +1111,8 @@ fn make_half_map(
         city_name: raw.city_name.clone(),
         name: raw.name.clone(),
         edits: MapEdits::new(),
+        turn_conflicts: Mutex::new(BTreeMap::new()),
+        bike_lane_connectivity: Mutex::new(None),
     };
 
     let road_id_mapping: BTreeMap<OriginalRoad, RoadID> = initial_map
@@ -7,7 +7,10 @@ mod walking;
 pub use self::driving::cost;
 use self::driving::VehiclePathfinder;
 use self::walking::SidewalkPathfinder;
-pub use self::walking::{one_step_walking_path, walking_cost, walking_path_to_steps, WalkingNode};
+pub use self::walking::{
+    k_shortest_walking_paths, one_step_walking_path, walking_cost, walking_path_to_steps,
+    WalkingNode,
+};
 use crate::{
     osm, BusRouteID, BusStopID, Intersection, Lane, LaneID, LaneType, Map, Position, Traversable,
     TurnID, Zone,
@@ -160,6 +163,10 @@ impl Path {
         self.crossed_so_far
     }
 
+    pub fn end_dist(&self) -> Distance {
+        self.end_dist
+    }
+
     pub fn total_length(&self) -> Distance {
         self.total_length
     }
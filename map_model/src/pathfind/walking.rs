@@ -1,12 +1,14 @@
 use crate::pathfind::driving::VehiclePathfinder;
 use crate::pathfind::node_map::{deserialize_nodemap, NodeMap};
 use crate::{
-    BusRouteID, BusStopID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Position,
+    connectivity, BusRouteID, BusStopID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
+    Position,
 };
 use fast_paths::{deserialize_32, serialize_32, FastGraph, InputGraph, PathCalculator};
 use geom::{Distance, Speed};
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
 use thread_local::ThreadLocal;
 
 #[derive(Serialize, Deserialize)]
@@ -301,3 +303,119 @@ pub fn one_step_walking_path(req: &PathRequest, map: &Map) -> Path {
         )
     }
 }
+
+// Up to k of the shortest walking paths, shortest first. The alternatives come from avoiding each
+// sidewalk along the shortest path in turn, so they may be much longer. Bus stops aren't used.
+pub fn k_shortest_walking_paths(req: &PathRequest, k: usize, map: &Map) -> Vec<Path> {
+    if req.start.lane() == req.end.lane() {
+        return vec![one_step_walking_path(req, map)];
+    }
+    let shortest = match shortest_walking_path_avoiding(req, &BTreeSet::new(), map) {
+        Some(p) => p,
+        None => {
+            return Vec::new();
+        }
+    };
+    let mut candidates: Vec<Path> = Vec::new();
+    for step in shortest.get_steps() {
+        if let PathStep::Lane(l) | PathStep::ContraflowLane(l) = step {
+            if *l == req.start.lane() || *l == req.end.lane() {
+                continue;
+            }
+            let mut avoid = BTreeSet::new();
+            avoid.insert(*l);
+            if let Some(p) = shortest_walking_path_avoiding(req, &avoid, map) {
+                if candidates.iter().all(|c| c.get_steps() != p.get_steps()) {
+                    candidates.push(p);
+                }
+            }
+        }
+    }
+    candidates.sort_by_key(|p| p.total_length());
+
+    let mut paths = vec![shortest];
+    paths.extend(candidates.into_iter().take(k.saturating_sub(1)));
+    paths
+}
+
+fn shortest_walking_path_avoiding(
+    req: &PathRequest,
+    avoid: &BTreeSet<LaneID>,
+    map: &Map,
+) -> Option<Path> {
+    let start = map.get_l(req.start.lane());
+    let end = map.get_l(req.end.lane());
+
+    // Which endpoint of the last sidewalk to reach the end position from, and the total distance
+    let finish: Cell<Option<(WalkingNode, Distance)>> = Cell::new(None);
+    let (_, backrefs) = connectivity::dijkstra(
+        vec![
+            (
+                WalkingNode::SidewalkEndpoint(start.id, false),
+                req.start.dist_along(),
+            ),
+            (
+                WalkingNode::SidewalkEndpoint(start.id, true),
+                start.length() - req.start.dist_along(),
+            ),
+        ],
+        |node, dist| {
+            let (l, endpt) = match node {
+                WalkingNode::SidewalkEndpoint(l, endpt) => (l, endpt),
+                WalkingNode::RideBus(_) => unreachable!(),
+            };
+            let lane = map.get_l(l);
+
+            let mut next_steps = Vec::new();
+            if l == end.id {
+                let total = dist
+                    + if endpt {
+                        end.length() - req.end.dist_along()
+                    } else {
+                        req.end.dist_along()
+                    };
+                if finish.get().map(|(_, d)| total < d).unwrap_or(true) {
+                    finish.set(Some((node, total)));
+                }
+            } else if l != start.id {
+                next_steps.push((WalkingNode::SidewalkEndpoint(l, !endpt), lane.length()));
+            }
+            let i = if endpt { lane.dst_i } else { lane.src_i };
+            for turn in map.get_turns_from_lane(l) {
+                let dst = map.get_l(turn.id.dst);
+                if turn.id.parent == i
+                    && turn.between_sidewalks()
+                    && !avoid.contains(&dst.id)
+                    && !map.get_r(dst.parent).is_private()
+                {
+                    next_steps.push((
+                        WalkingNode::SidewalkEndpoint(dst.id, dst.dst_i == i),
+                        turn.geom.length(),
+                    ));
+                }
+            }
+            next_steps
+        },
+        |_, dist| {
+            finish
+                .get()
+                .map(|(_, total)| dist >= total)
+                .unwrap_or(false)
+        },
+    );
+
+    let mut nodes = vec![finish.get()?.0];
+    while let Some(prev) = backrefs.get(nodes.last().unwrap()) {
+        nodes.push(*prev);
+    }
+    nodes.reverse();
+    if nodes.len() == 1 {
+        // Walking past the start of the end lane and back
+        return None;
+    }
+    Some(Path::new(
+        map,
+        walking_path_to_steps(nodes, map),
+        req.end.dist_along(),
+    ))
+}
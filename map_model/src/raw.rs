@@ -104,6 +104,99 @@ impl RawMap {
         }
     }
 
+    // A grid of identical roads, 100m apart, with one building in the middle of each block. Handy
+    // for tests. Intersection (col, row) has OSM node ID row * cols + col + 1.
+    pub fn synthetic_grid(cols: usize, rows: usize, spec: &RoadSpec) -> RawMap {
+        assert!(cols >= 2 && rows >= 2);
+        let mut map = RawMap::blank("synthetic", "grid");
+        let block = 100.0;
+        let node = |col: usize, row: usize| OriginalIntersection {
+            osm_node_id: (row * cols + col + 1) as i64,
+        };
+        for col in 0..cols {
+            for row in 0..rows {
+                map.intersections.insert(
+                    node(col, row),
+                    RawIntersection {
+                        point: Pt2D::new(
+                            block / 2.0 + block * (col as f64),
+                            block / 2.0 + block * (row as f64),
+                        ),
+                        intersection_type: IntersectionType::StopSign,
+                        elevation: Distance::ZERO,
+                    },
+                );
+            }
+        }
+
+        let mut pairs = Vec::new();
+        for col in 0..cols {
+            for row in 0..rows {
+                if col + 1 < cols {
+                    pairs.push((node(col, row), node(col + 1, row)));
+                }
+                if row + 1 < rows {
+                    pairs.push((node(col, row), node(col, row + 1)));
+                }
+            }
+        }
+        for (idx, (i1, i2)) in pairs.into_iter().enumerate() {
+            let id = OriginalRoad {
+                osm_way_id: (idx + 1) as i64,
+                i1,
+                i2,
+            };
+            let mut osm_tags = BTreeMap::new();
+            osm_tags.insert(osm::SYNTHETIC.to_string(), "true".to_string());
+            osm_tags.insert(osm::SYNTHETIC_LANES.to_string(), spec.to_string());
+            osm_tags.insert(osm::ENDPT_FWD.to_string(), "true".to_string());
+            osm_tags.insert(osm::ENDPT_BACK.to_string(), "true".to_string());
+            osm_tags.insert(osm::OSM_WAY_ID.to_string(), id.osm_way_id.to_string());
+            osm_tags.insert(osm::NAME.to_string(), format!("Street {}", id.osm_way_id));
+            osm_tags.insert(osm::MAXSPEED.to_string(), "25 mph".to_string());
+            map.roads.insert(
+                id,
+                RawRoad {
+                    center_points: vec![map.intersections[&i1].point, map.intersections[&i2].point],
+                    osm_tags,
+                    turn_restrictions: Vec::new(),
+                    complicated_turn_restrictions: Vec::new(),
+                },
+            );
+        }
+
+        for col in 0..cols - 1 {
+            for row in 0..rows - 1 {
+                let id = OriginalBuilding {
+                    osm_way_id: (map.roads.len() + row * cols + col + 1) as i64,
+                };
+                map.buildings.insert(
+                    id,
+                    RawBuilding {
+                        polygon: Polygon::rectangle_centered(
+                            Pt2D::new(block * ((col + 1) as f64), block * ((row + 1) as f64)),
+                            Distance::meters(30.0),
+                            Distance::meters(30.0),
+                        ),
+                        osm_tags: BTreeMap::new(),
+                        public_garage_name: None,
+                        num_parking_spots: 0,
+                        amenities: BTreeSet::new(),
+                    },
+                );
+            }
+        }
+
+        let max = Pt2D::new(block * (cols as f64), block * (rows as f64));
+        map.boundary_polygon = Polygon::rectangle(max.x(), max.y());
+        map.gps_bounds = GPSBounds::new();
+        map.gps_bounds
+            .update(Pt2D::new(0.0, 0.0).forcibly_to_gps(&GPSBounds::seattle_bounds()));
+        map.gps_bounds
+            .update(max.forcibly_to_gps(&GPSBounds::seattle_bounds()));
+        map
+    }
+
     // TODO Might be better to maintain this instead of doing a search everytime.
     pub fn roads_per_intersection(&self, i: OriginalIntersection) -> Vec<OriginalRoad> {
         let mut results = Vec::new();
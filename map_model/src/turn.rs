@@ -1,4 +1,5 @@
-use crate::{DirectedRoadID, IntersectionID, LaneID, Map};
+use crate::raw::RestrictionType;
+use crate::{DirectedRoadID, IntersectionID, LaneID, Map, PathConstraints};
 use abstutil::MultiMap;
 use geom::{Angle, Distance, PolyLine, Pt2D};
 use serde::{Deserialize, Serialize};
@@ -109,6 +110,41 @@ impl Turn {
     pub fn between_sidewalks(&self) -> bool {
         self.turn_type == TurnType::SharedSidewalkCorner || self.turn_type == TurnType::Crosswalk
     }
+
+    // Can something with these constraints legally make this turn? Turns breaking lane markings or
+    // turn restrictions are usually never created, but map edits can change the lanes afterwards.
+    pub fn legal_for(&self, constraints: PathConstraints, map: &Map) -> bool {
+        if self.between_sidewalks() {
+            return constraints == PathConstraints::Pedestrian;
+        }
+        let src = map.get_l(self.id.src);
+        let dst = map.get_l(self.id.dst);
+        if !constraints.can_use(src, map) || !constraints.can_use(dst, map) {
+            return false;
+        }
+
+        if let Some(mut types) = src.get_turn_restrictions(map.get_r(src.parent)) {
+            if !types.any(|turn_type| turn_type == self.turn_type) {
+                return false;
+            }
+        }
+
+        let intersection_roads = &map.get_i(self.id.parent).roads;
+        for (restriction, to) in &map.get_r(src.parent).turn_restrictions {
+            // The restriction only applies to one direction of the road.
+            if !intersection_roads.contains(to) {
+                continue;
+            }
+            let legal = match restriction {
+                RestrictionType::BanTurns => dst.parent != *to,
+                RestrictionType::OnlyAllowTurns => dst.parent == *to,
+            };
+            if !legal {
+                return false;
+            }
+        }
+        true
+    }
 }
 
 // One road usually has 4 crosswalks, each a singleton TurnGroup. We need all of the information
@@ -300,3 +336,42 @@ fn turn_group_geom(
     }
     PolyLine::new(pts)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(src: usize, dst: usize, turn_type: TurnType, pts: Vec<(f64, f64)>) -> Turn {
+        Turn {
+            id: TurnID {
+                parent: IntersectionID(0),
+                src: LaneID(src),
+                dst: LaneID(dst),
+            },
+            turn_type,
+            geom: PolyLine::new(pts.into_iter().map(|(x, y)| Pt2D::new(x, y)).collect()),
+            other_crosswalk_ids: BTreeSet::new(),
+        }
+    }
+
+    #[test]
+    fn test_four_way_conflicts() {
+        // A four-way intersection centered at (10, 10). Southbound traffic goes straight through,
+        // while westbound traffic turns left to head south too.
+        let straight = turn(0, 1, TurnType::Straight, vec![(8.0, 20.0), (8.0, 0.0)]);
+        let left = turn(
+            2,
+            3,
+            TurnType::Left,
+            vec![(20.0, 12.0), (12.0, 12.0), (6.0, 0.0)],
+        );
+        // Northbound traffic turning right doesn't cross either of them.
+        let right = turn(4, 5, TurnType::Right, vec![(12.0, 0.0), (20.0, 8.0)]);
+
+        assert!(straight.conflicts_with(&left));
+        assert!(left.conflicts_with(&straight));
+        assert!(!right.conflicts_with(&straight));
+        assert!(!right.conflicts_with(&left));
+        assert!(!straight.conflicts_with(&straight));
+    }
+}
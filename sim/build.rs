@@ -0,0 +1,4 @@
+fn main() {
+    prost_build::compile_protos(&["src/agent_positions.proto"], &["src/"])
+        .expect("Failed to generate agent_positions.proto types");
+}
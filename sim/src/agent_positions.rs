@@ -0,0 +1,38 @@
+// Protocol Buffers types generated from agent_positions.proto by build.rs
+
+use crate::{AgentID, TripMode};
+use geom::{Pt2D, Time};
+
+include!(concat!(env!("OUT_DIR"), "/abstreet.rs"));
+
+impl AgentPositions {
+    pub(crate) fn new(time: Time) -> AgentPositions {
+        AgentPositions {
+            time: (time - Time::START_OF_DAY).inner_seconds(),
+            agents: Vec::new(),
+        }
+    }
+}
+
+impl AgentPosition {
+    pub(crate) fn new(agent: AgentID, pt: Pt2D) -> AgentPosition {
+        let (agent_type, id) = match agent {
+            AgentID::Car(c) => (AgentType::Car, c.0),
+            AgentID::Pedestrian(p) => (AgentType::Pedestrian, p.0),
+            AgentID::BusPassenger(p, _) => (AgentType::BusPassenger, p.0),
+        };
+        let mode = match TripMode::from_agent(agent) {
+            TripMode::Walk => AgentMode::Walk,
+            TripMode::Bike => AgentMode::Bike,
+            TripMode::Transit => AgentMode::Transit,
+            TripMode::Drive => AgentMode::Drive,
+        };
+        AgentPosition {
+            agent_type: agent_type as i32,
+            id: id as u64,
+            x: pt.x(),
+            y: pt.y(),
+            mode: mode as i32,
+        }
+    }
+}
@@ -1,22 +1,43 @@
-use crate::{AlertLocation, CarID, Event, ParkingSpot, TripID, TripMode, TripPhaseType};
+use crate::{
+    AgentID, AlertLocation, CarID, Event, ParkingSpot, PedestrianID, TripID, TripMode,
+    TripPhaseType, VehicleType,
+};
 use abstutil::Counter;
 use geom::{Distance, Duration, Histogram, Time};
 use map_model::{
-    BusRouteID, BusStopID, IntersectionID, LaneID, Map, ParkingLotID, Path, PathRequest, RoadID,
-    Traversable, TurnGroupID,
+    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, ParkingLotID, Path,
+    PathRequest, Position, RoadID, Traversable, TurnGroupID, TurnID,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Analytics {
     pub road_thruput: TimeSeriesCount<RoadID>,
     pub intersection_thruput: TimeSeriesCount<IntersectionID>,
+    pub turning_movements: TmcCounter,
+    // Per car (not bus or bike), how much it's been used
+    pub car_usage: BTreeMap<CarID, CarSharingStats>,
 
     // Unlike everything else in Analytics, this is just for a moment in time.
     pub demand: BTreeMap<TurnGroupID, usize>,
     pub bus_arrivals: Vec<(Time, CarID, BusRouteID, BusStopID)>,
     pub bus_passengers_waiting: Vec<(Time, BusStopID, BusRouteID)>,
+    // Per stop, when somebody boarded and how long they waited first
+    pub bus_boardings: BTreeMap<BusStopID, Vec<(Time, Duration)>>,
+    pub bus_alightings: BTreeMap<BusStopID, Vec<Time>>,
+    waiting_for_bus_since: BTreeMap<TripID, Time>,
+    // When a full bus left somebody behind
+    pub bus_overcrowding: Vec<(Time, CarID)>,
+    pub turn_violations: Vec<(Time, CarID, TurnID)>,
+    // (crosswalk, agent starting a turn, agent waiting for a conflicting turn)
+    pub crosswalk_conflicts: Vec<(Time, TurnID, AgentID, AgentID)>,
+    // From Sim::record_collision
+    pub collisions: Vec<(Time, CarID, CarID, Position)>,
+    // When each agent found a new path
+    pub reroutes: BTreeMap<AgentID, Vec<Time>>,
+    // When each car switched lanes to avoid a queue
+    pub lane_changes: BTreeMap<CarID, Vec<Time>>,
     pub started_trips: BTreeMap<TripID, Time>,
     // TODO Hack: No TripMode means aborted
     // Finish time, ID, mode (or None as aborted), trip duration
@@ -27,6 +48,30 @@ pub struct Analytics {
     // Per parking lane or lot, when does a spot become filled (true) or free (false)
     pub parking_lane_changes: BTreeMap<LaneID, Vec<(Time, bool)>>,
     pub parking_lot_changes: BTreeMap<ParkingLotID, Vec<(Time, bool)>>,
+    // Per curbside lane, when a parked car or bus at a stop left and how long it stayed
+    pub curbside_dwell_times: BTreeMap<LaneID, Vec<(Time, Duration)>>,
+    pub curbside_conflicts: BTreeMap<LaneID, Vec<Time>>,
+    // Who's at the curb right now, and since when
+    curbside_occupants: BTreeMap<CarID, (LaneID, Time)>,
+    // Per fuel or charging station, when a car arrived there
+    pub fuel_station_visits: BTreeMap<BuildingID, Vec<Time>>,
+    // Each distinct route walking trips took, with the trips that took it
+    pub walking_routes: BTreeMap<Vec<LaneID>, Vec<TripID>>,
+    // The sidewalks each unfinished trip has walked along so far
+    walking_routes_in_progress: BTreeMap<TripID, Vec<LaneID>>,
+    // Per pedestrian and road, how long they've spent walking along it and how far
+    pub pedestrian_time_per_road: BTreeMap<PedestrianID, BTreeMap<RoadID, (Duration, Distance)>>,
+    // kg of CO2-equivalent emitted on behalf of each trip. Bus emissions are split between the
+    // passengers on board.
+    pub trip_emissions: BTreeMap<TripID, f64>,
+    // kg of CO2-equivalent emitted by vehicles on each road, at the speed they drove there
+    pub road_emissions: BTreeMap<RoadID, f64>,
+    // How far each trip has gone so far, by any mode
+    // TODO Turns and crosswalks aren't counted.
+    pub trip_distances: BTreeMap<TripID, Distance>,
+    // Who's riding each bus right now
+    bus_riders: BTreeMap<CarID, BTreeSet<TripID>>,
+    riding_bus: BTreeMap<TripID, CarID>,
     pub(crate) alerts: Vec<(Time, AlertLocation, String)>,
 
     // After we restore from a savestate, don't record anything. This is only going to make sense
@@ -40,15 +85,38 @@ impl Analytics {
         Analytics {
             road_thruput: TimeSeriesCount::new(),
             intersection_thruput: TimeSeriesCount::new(),
+            turning_movements: TmcCounter::new(),
+            car_usage: BTreeMap::new(),
             demand: BTreeMap::new(),
             bus_arrivals: Vec::new(),
             bus_passengers_waiting: Vec::new(),
+            bus_boardings: BTreeMap::new(),
+            bus_alightings: BTreeMap::new(),
+            waiting_for_bus_since: BTreeMap::new(),
+            bus_overcrowding: Vec::new(),
+            turn_violations: Vec::new(),
+            crosswalk_conflicts: Vec::new(),
+            collisions: Vec::new(),
+            reroutes: BTreeMap::new(),
+            lane_changes: BTreeMap::new(),
             started_trips: BTreeMap::new(),
             finished_trips: Vec::new(),
             trip_log: Vec::new(),
             intersection_delays: BTreeMap::new(),
             parking_lane_changes: BTreeMap::new(),
             parking_lot_changes: BTreeMap::new(),
+            curbside_dwell_times: BTreeMap::new(),
+            curbside_conflicts: BTreeMap::new(),
+            curbside_occupants: BTreeMap::new(),
+            fuel_station_visits: BTreeMap::new(),
+            walking_routes: BTreeMap::new(),
+            walking_routes_in_progress: BTreeMap::new(),
+            pedestrian_time_per_road: BTreeMap::new(),
+            trip_emissions: BTreeMap::new(),
+            road_emissions: BTreeMap::new(),
+            trip_distances: BTreeMap::new(),
+            bus_riders: BTreeMap::new(),
+            riding_bus: BTreeMap::new(),
             alerts: Vec::new(),
             record_anything: true,
         }
@@ -82,10 +150,147 @@ impl Analytics {
             _ => {}
         }
 
+        // Turning movement counts
+        if let Event::AgentFinishedTurn(_, t) = ev {
+            self.turning_movements.record(time, t);
+        }
+
+        // Car usage
+        match ev {
+            Event::CarReachedParkingSpot(car, _) if car.1 == VehicleType::Car => {
+                let stats = self
+                    .car_usage
+                    .entry(car)
+                    .or_insert_with(|| CarSharingStats::new(time));
+                if !stats.parked {
+                    stats.total_active_time += time - stats.last_change;
+                }
+                stats.parked = true;
+                stats.last_change = time;
+            }
+            Event::CarLeftParkingSpot(car, _) if car.1 == VehicleType::Car => {
+                let stats = self
+                    .car_usage
+                    .entry(car)
+                    .or_insert_with(|| CarSharingStats::new(time));
+                if stats.parked {
+                    stats.total_idle_time += time - stats.last_change;
+                }
+                stats.parked = false;
+                stats.last_change = time;
+                stats.trips_served += 1;
+            }
+            Event::AgentEntersTraversable(AgentID::Car(car), Traversable::Lane(l))
+                if car.1 == VehicleType::Car =>
+            {
+                self.car_usage
+                    .entry(car)
+                    .or_insert_with(|| CarSharingStats::new(time))
+                    .distance += map.get_l(l).length();
+            }
+            _ => {}
+        }
+
         // Bus arrivals
         if let Event::BusArrivedAtStop(bus, route, stop) = ev {
             self.bus_arrivals.push((time, bus, route, stop));
         }
+        if let Event::BusPassedFullCapacity(bus, _) = ev {
+            self.bus_overcrowding.push((time, bus));
+        }
+        if let Event::TurnViolation(car, t) = ev {
+            self.turn_violations.push((time, car, t));
+        }
+        if let Event::CrosswalkConflict(t, a1, a2) = ev {
+            self.crosswalk_conflicts.push((time, t, a1, a2));
+        }
+        if let Event::CollisionDetected(car1, car2, pos) = ev {
+            self.collisions.push((time, car1, car2, pos));
+        }
+        if let Event::CarReplanned(car, _) = ev {
+            self.reroutes
+                .entry(AgentID::Car(car))
+                .or_insert_with(Vec::new)
+                .push(time);
+        }
+        if let Event::LaneChanged(car, _, _) = ev {
+            self.lane_changes
+                .entry(car)
+                .or_insert_with(Vec::new)
+                .push(time);
+        }
+        if let Event::FuelStationVisited(_, b) = ev {
+            self.fuel_station_visits
+                .entry(b)
+                .or_insert_with(Vec::new)
+                .push(time);
+        }
+
+        // Pedestrian routes
+        match ev {
+            Event::PedestrianLeavesSidewalk(ped, trip, l, dist, dt) => {
+                self.walking_routes_in_progress
+                    .entry(trip)
+                    .or_insert_with(Vec::new)
+                    .push(l);
+                let entry = self
+                    .pedestrian_time_per_road
+                    .entry(ped)
+                    .or_insert_with(BTreeMap::new)
+                    .entry(map.get_l(l).parent)
+                    .or_insert((Duration::ZERO, Distance::ZERO));
+                entry.0 += dt;
+                entry.1 += dist;
+                *self.trip_distances.entry(trip).or_insert(Distance::ZERO) += dist;
+            }
+            Event::TripFinished { trip, mode, .. } => {
+                if let Some(route) = self.walking_routes_in_progress.remove(&trip) {
+                    if mode == TripMode::Walk {
+                        self.walking_routes
+                            .entry(route)
+                            .or_insert_with(Vec::new)
+                            .push(trip);
+                    }
+                }
+            }
+            Event::TripAborted(trip) => {
+                self.walking_routes_in_progress.remove(&trip);
+            }
+            _ => {}
+        }
+
+        // Emissions and distance
+        match ev {
+            Event::VehicleLeftRoad(car, maybe_trip, r, dist, dt) => {
+                let kg = vehicle_emissions(car.1, dist, dt);
+                *self.road_emissions.entry(r).or_insert(0.0) += kg;
+                if let Some(trip) = maybe_trip {
+                    *self.trip_emissions.entry(trip).or_insert(0.0) += kg;
+                    *self.trip_distances.entry(trip).or_insert(Distance::ZERO) += dist;
+                } else if let Some(riders) = self.bus_riders.get(&car) {
+                    for trip in riders {
+                        *self.trip_emissions.entry(*trip).or_insert(0.0) +=
+                            kg / (riders.len() as f64);
+                        *self.trip_distances.entry(*trip).or_insert(Distance::ZERO) += dist;
+                    }
+                }
+            }
+            Event::TripPhaseStarting(trip, _, _, _)
+            | Event::TripFinished { trip, .. }
+            | Event::TripAborted(trip) => {
+                if let Some(bus) = self.riding_bus.remove(&trip) {
+                    self.bus_riders.get_mut(&bus).unwrap().remove(&trip);
+                }
+                if let Event::TripPhaseStarting(_, _, _, TripPhaseType::RidingBus(_, _, bus)) = ev {
+                    self.riding_bus.insert(trip, bus);
+                    self.bus_riders
+                        .entry(bus)
+                        .or_insert_with(BTreeSet::new)
+                        .insert(trip);
+                }
+            }
+            _ => {}
+        }
 
         // Bus passengers
         if let Event::TripPhaseStarting(_, _, _, ref tpt) = ev {
@@ -94,6 +299,27 @@ impl Analytics {
             }
         }
 
+        match ev {
+            Event::TripPhaseStarting(id, _, _, TripPhaseType::WaitingForBus(_, _)) => {
+                self.waiting_for_bus_since.insert(id, time);
+            }
+            Event::TripPhaseStarting(id, _, _, TripPhaseType::RidingBus(_, stop, _)) => {
+                // If the bus was already there, there's no waiting phase
+                let wait = time - self.waiting_for_bus_since.remove(&id).unwrap_or(time);
+                self.bus_boardings
+                    .entry(stop)
+                    .or_insert_with(Vec::new)
+                    .push((time, wait));
+            }
+            Event::PassengerAlighted(_, _, stop) => {
+                self.bus_alightings
+                    .entry(stop)
+                    .or_insert_with(Vec::new)
+                    .push(time);
+            }
+            _ => {}
+        }
+
         // Started trips
         if let Event::TripPhaseStarting(id, _, _, _) = ev {
             self.started_trips.entry(id).or_insert(time);
@@ -150,6 +376,34 @@ impl Analytics {
             }
         }
 
+        // Curbside activity
+        match ev {
+            Event::CarReachedParkingSpot(car, ParkingSpot::Onstreet(l, _)) => {
+                self.curbside_occupants.insert(car, (l, time));
+            }
+            Event::BusArrivedAtStop(bus, _, stop) => {
+                self.curbside_occupants
+                    .insert(bus, (map.get_bs(stop).driving_pos.lane(), time));
+            }
+            Event::CarLeftParkingSpot(car, ParkingSpot::Onstreet(_, _))
+            | Event::BusDepartedFromStop(car, _, _) => {
+                // Cars parked before the simulation started don't have a known arrival time
+                if let Some((l, since)) = self.curbside_occupants.remove(&car) {
+                    self.curbside_dwell_times
+                        .entry(l)
+                        .or_insert_with(Vec::new)
+                        .push((time, time - since));
+                }
+            }
+            Event::CurbsideConflict(_, ParkingSpot::Onstreet(l, _)) => {
+                self.curbside_conflicts
+                    .entry(l)
+                    .or_insert_with(Vec::new)
+                    .push(time);
+            }
+            _ => {}
+        }
+
         // TODO Kinda hacky, but these all consume the event, so kinda bundle em.
         match ev {
             Event::TripPhaseStarting(id, _, maybe_req, phase_type) => {
@@ -319,6 +573,57 @@ impl Analytics {
         delays_to_stop
     }
 
+    // When did a bus arrive at a stop within some window of the previous bus on the same route?
+    // (route, stop, time of the second arrival)
+    pub fn bus_bunching(&self, now: Time, window: Duration) -> Vec<(BusRouteID, BusStopID, Time)> {
+        let mut last_arrival: BTreeMap<(BusRouteID, BusStopID), Time> = BTreeMap::new();
+        let mut results = Vec::new();
+        for (t, _, route, stop) in &self.bus_arrivals {
+            if *t > now {
+                break;
+            }
+            if let Some(prev) = last_arrival.insert((*route, *stop), *t) {
+                if *t - prev <= window {
+                    results.push((*route, *stop, *t));
+                }
+            }
+        }
+        results
+    }
+
+    // Time between consecutive arrivals of this route at each of its stops, for every stop
+    pub fn bus_headways(&self, now: Time, r: BusRouteID) -> Vec<Duration> {
+        let mut last_arrival: BTreeMap<BusStopID, Time> = BTreeMap::new();
+        let mut results = Vec::new();
+        for (t, _, route, stop) in &self.bus_arrivals {
+            if *t > now {
+                break;
+            }
+            if *route != r {
+                continue;
+            }
+            if let Some(prev) = last_arrival.insert(*stop, *t) {
+                results.push(*t - prev);
+            }
+        }
+        results
+    }
+
+    // Which buses were full and left somebody behind within some window before now? Returns the
+    // most recent time for each bus.
+    pub fn bus_overcrowding(&self, now: Time, window: Duration) -> BTreeMap<CarID, Time> {
+        let mut results = BTreeMap::new();
+        for (t, bus) in &self.bus_overcrowding {
+            if *t > now {
+                break;
+            }
+            if now - *t <= window {
+                results.insert(*bus, *t);
+            }
+        }
+        results
+    }
+
     // At some moment in time, what's the distribution of passengers waiting for a route like?
     pub fn bus_passenger_delays(
         &self,
@@ -676,3 +981,240 @@ impl Window {
         self.times.len()
     }
 }
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CarSharingStats {
+    pub total_active_time: Duration,
+    pub total_idle_time: Duration,
+    // How many times the car left a parking spot
+    pub trips_served: usize,
+    pub distance: Distance,
+
+    // When the car last parked or started moving
+    last_change: Time,
+    parked: bool,
+}
+
+impl CarSharingStats {
+    // Cars first seen anywhere but a parking spot are moving.
+    fn new(time: Time) -> CarSharingStats {
+        CarSharingStats {
+            total_active_time: Duration::ZERO,
+            total_idle_time: Duration::ZERO,
+            trips_served: 0,
+            distance: Distance::ZERO,
+            last_change: time,
+            parked: false,
+        }
+    }
+
+    // Include the time since the car last parked or unparked.
+    pub fn as_of(&self, now: Time) -> CarSharingStats {
+        let mut stats = self.clone();
+        if stats.parked {
+            stats.total_idle_time += now - stats.last_change;
+        } else {
+            stats.total_active_time += now - stats.last_change;
+        }
+        stats.last_change = now;
+        stats
+    }
+}
+
+// Turning movement counts: every time any agent finishes a turn
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TmcCounter {
+    pub counts: BTreeMap<IntersectionID, BTreeMap<TurnID, Vec<Time>>>,
+}
+
+impl TmcCounter {
+    fn new() -> TmcCounter {
+        TmcCounter {
+            counts: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, time: Time, t: TurnID) {
+        self.counts
+            .entry(t.parent)
+            .or_insert_with(BTreeMap::new)
+            .entry(t)
+            .or_insert_with(Vec::new)
+            .push(time);
+    }
+
+    // Per turn, how many agents finished it in the window ending now?
+    pub fn counts_in_window(
+        &self,
+        i: IntersectionID,
+        now: Time,
+        window: Duration,
+    ) -> BTreeMap<TurnID, usize> {
+        let mut results = BTreeMap::new();
+        if let Some(turns) = self.counts.get(&i) {
+            for (t, times) in turns {
+                results.insert(
+                    *t,
+                    times
+                        .iter()
+                        .filter(|time| **time <= now && now - **time <= window)
+                        .count(),
+                );
+            }
+        }
+        results
+    }
+
+    // Per turn, the counts during the busiest hour of the intersection so far
+    pub fn peak_hour(&self, i: IntersectionID) -> BTreeMap<TurnID, usize> {
+        let mut all_times: Vec<Time> = match self.counts.get(&i) {
+            Some(turns) => turns.values().flatten().cloned().collect(),
+            None => {
+                return BTreeMap::new();
+            }
+        };
+        all_times.sort();
+
+        // Slide an hour-long window over all the times, starting at each one
+        let hour = Duration::hours(1);
+        let mut best_start = all_times[0];
+        let mut best_count = 0;
+        let mut end_idx = 0;
+        for (start_idx, start) in all_times.iter().enumerate() {
+            while end_idx < all_times.len() && all_times[end_idx] - *start <= hour {
+                end_idx += 1;
+            }
+            if end_idx - start_idx > best_count {
+                best_count = end_idx - start_idx;
+                best_start = *start;
+            }
+        }
+        self.counts_in_window(i, best_start + hour, hour)
+    }
+}
+
+// kg of CO2-equivalent emitted per meter driven by each kind of vehicle. E-bikes count the
+// electricity used to charge them.
+// TODO Rough averages. vehicle_emissions adjusts these for speed, but not acceleration or idling.
+fn emissions_per_meter(vehicle_type: VehicleType) -> f64 {
+    match vehicle_type {
+        VehicleType::Car => 0.00017,
+        VehicleType::DeliveryTruck => 0.0006,
+        VehicleType::Bus | VehicleType::SchoolBus => 0.0013,
+        VehicleType::EBike => 0.000005,
+        VehicleType::Bike | VehicleType::CargoBike => 0.0,
+    }
+}
+
+// (km/h, grams of CO2-equivalent per km) for an average car. Emissions are highest in stop-and-go
+// traffic, lowest at moderate speeds, and rise again at high speeds.
+const EMISSION_SPEED_CURVE: [(f64, f64); 5] = [
+    (5.0, 450.0),
+    (20.0, 230.0),
+    (40.0, 170.0),
+    (60.0, 150.0),
+    (80.0, 165.0),
+];
+
+// Linearly interpolates EMISSION_SPEED_CURVE, holding the ends constant
+pub(crate) fn emissions_factor_at_speed(kmh: f64) -> f64 {
+    let (first_speed, first_factor) = EMISSION_SPEED_CURVE[0];
+    if kmh <= first_speed {
+        return first_factor;
+    }
+    for pair in EMISSION_SPEED_CURVE.windows(2) {
+        let ((s1, f1), (s2, f2)) = (pair[0], pair[1]);
+        if kmh <= s2 {
+            return f1 + (f2 - f1) * (kmh - s1) / (s2 - s1);
+        }
+    }
+    EMISSION_SPEED_CURVE[EMISSION_SPEED_CURVE.len() - 1].1
+}
+
+// kg of CO2-equivalent emitted by a vehicle driving some distance, taking some time. The
+// per-vehicle rates correspond to the car curve at some typical speed; scale them by how far off
+// the average speed is from that.
+fn vehicle_emissions(vehicle_type: VehicleType, dist: Distance, dt: Duration) -> f64 {
+    let mut kg = dist.inner_meters() * emissions_per_meter(vehicle_type);
+    if dt > Duration::ZERO {
+        let kmh = dist.inner_meters() / dt.inner_seconds() * 3.6;
+        kg *=
+            emissions_factor_at_speed(kmh) / (emissions_per_meter(VehicleType::Car) * 1_000_000.0);
+    }
+    kg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PersonID;
+
+    #[test]
+    fn test_trip_emissions() {
+        let map = Map::blank();
+        let mut analytics = Analytics::new();
+        let car = CarID(0, VehicleType::Car);
+        let bus = CarID(1, VehicleType::Bus);
+        let stop = BusStopID {
+            sidewalk: LaneID(0),
+            idx: 0,
+        };
+        let ride = TripPhaseType::RidingBus(BusRouteID(0), stop, bus);
+        let dist = Distance::meters(100.0);
+        // 36 km/h
+        let dt = Duration::seconds(10.0);
+
+        analytics.event(
+            Event::VehicleLeftRoad(car, Some(TripID(0)), RoadID(1), dist, dt),
+            Time::START_OF_DAY,
+            &map,
+        );
+        for trip in vec![TripID(1), TripID(2)] {
+            analytics.event(
+                Event::TripPhaseStarting(trip, PersonID(trip.0), None, ride),
+                Time::START_OF_DAY,
+                &map,
+            );
+        }
+        analytics.event(
+            Event::VehicleLeftRoad(bus, None, RoadID(1), dist, dt),
+            Time::START_OF_DAY,
+            &map,
+        );
+        // Once somebody gets off, they don't count anymore
+        analytics.event(
+            Event::TripPhaseStarting(TripID(2), PersonID(2), None, TripPhaseType::Walking),
+            Time::START_OF_DAY,
+            &map,
+        );
+        analytics.event(
+            Event::VehicleLeftRoad(bus, None, RoadID(1), dist, dt),
+            Time::START_OF_DAY,
+            &map,
+        );
+
+        let car_kg = vehicle_emissions(VehicleType::Car, dist, dt);
+        let bus_kg = vehicle_emissions(VehicleType::Bus, dist, dt);
+        assert!(car_kg > 0.0);
+        assert_eq!(analytics.trip_emissions[&TripID(0)], car_kg);
+        assert_eq!(analytics.trip_emissions[&TripID(1)], bus_kg / 2.0 + bus_kg);
+        assert_eq!(analytics.trip_emissions[&TripID(2)], bus_kg / 2.0);
+
+        assert_eq!(analytics.trip_distances[&TripID(0)], dist);
+        assert_eq!(analytics.trip_distances[&TripID(1)], dist * 2.0);
+        assert_eq!(analytics.trip_distances[&TripID(2)], dist);
+    }
+
+    #[test]
+    fn test_stop_and_go_emits_more() {
+        let dist = Distance::meters(100.0);
+        assert!(
+            vehicle_emissions(VehicleType::Car, dist, Duration::minutes(1))
+                > vehicle_emissions(VehicleType::Car, dist, Duration::seconds(10.0))
+        );
+        assert_eq!(
+            vehicle_emissions(VehicleType::Bike, dist, Duration::seconds(20.0)),
+            0.0
+        );
+    }
+}
@@ -1,9 +1,11 @@
 use crate::{
-    AgentID, CarID, OffMapLocation, ParkingSpot, PedestrianID, PersonID, TripID, TripMode,
+    AgentID, CarID, IncidentID, OffMapLocation, ParkingSpot, PedestrianID, PersonID, TripID,
+    TripMode,
 };
-use geom::Duration;
+use geom::{Distance, Duration};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, Path, PathRequest, Traversable,
+    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, Path, PathRequest, Position,
+    RoadID, Traversable, TurnID,
 };
 use serde::{Deserialize, Serialize};
 
@@ -14,8 +16,41 @@ use serde::{Deserialize, Serialize};
 pub enum Event {
     CarReachedParkingSpot(CarID, ParkingSpot),
     CarLeftParkingSpot(CarID, ParkingSpot),
+    // The spot the car was heading for got taken before it arrived
+    CurbsideConflict(CarID, ParkingSpot),
+    // The car found a new path to avoid this congested lane
+    CarReplanned(CarID, LaneID),
+    // Instead of the original lane on the next road, the car picked a less crowded one going the
+    // same way
+    LaneChanged(CarID, LaneID, LaneID),
+    // The agent replanned many times in a short period
+    RoutingOscillation(AgentID),
+    // The simulation doesn't model crashes, so these only come from Sim::record_collision.
+    CollisionDetected(CarID, CarID, Position),
+    // Stopped cars on the lane back up all the way to the upstream intersection. Only emitted if
+    // SimOptions::detect_queue_spillback is set.
+    QueueSpillback(LaneID, IntersectionID),
+    // The first agent started a turn crossing the path of the second, who was waiting at the same
+    // intersection. One of the turns is the crosswalk given; the other agent is a vehicle.
+    CrosswalkConflict(TurnID, AgentID, AgentID),
+    // The car started a turn it isn't allowed to make
+    TurnViolation(CarID, TurnID),
+    EnteredGarage(CarID),
+    ExitedGarage(CarID),
 
     BusArrivedAtStop(CarID, BusRouteID, BusStopID),
+    BusBunchingDetected(BusRouteID, BusStopID),
+    // A pedestrian couldn't board because the bus was full
+    BusPassedFullCapacity(CarID, PedestrianID),
+    // How long the bus has been waiting at the stop
+    BusStalled(CarID, BusStopID, Duration),
+    PassengerAlighted(PersonID, CarID, BusStopID),
+    RideHailPickup(PersonID, CarID),
+    RideHailDropoff(PersonID, CarID),
+    DRTPickup(PedestrianID, CarID),
+    DRTDropoff(PedestrianID, CarID),
+    StudentBoarded(PedestrianID, CarID),
+    StudentArrived(PedestrianID, BuildingID),
     BusDepartedFromStop(CarID, BusRouteID, BusStopID),
 
     PersonEntersBuilding(PersonID, BuildingID),
@@ -28,8 +63,24 @@ pub enum Event {
     PedReachedParkingSpot(PedestrianID, ParkingSpot),
 
     BikeStoppedAtSidewalk(CarID, LaneID),
+    DeliveryCompleted(CarID, BuildingID),
+    // The car parked for a trip to a building tagged as a fuel or charging station
+    FuelStationVisited(CarID, BuildingID),
+    CargoDelivered(CarID, BuildingID),
+    ParkAndRideCompleted(TripID),
+    SchoolZoneActive(BuildingID),
+    SchoolZoneInactive(BuildingID),
+    IncidentCleared(IncidentID),
 
     AgentEntersTraversable(AgentID, Traversable),
+    // The vehicle finished driving along one lane of the road. How far it drove there, and how
+    // long it took.
+    VehicleLeftRoad(CarID, Option<TripID>, RoadID, Distance, Duration),
+    // How far the pedestrian walked along the sidewalk, and how long it took
+    PedestrianLeavesSidewalk(PedestrianID, TripID, LaneID, Distance, Duration),
+    AgentFinishedTurn(AgentID, TurnID),
+    // The sidewalk the pedestrian started crossing from
+    PedestrianCrossed(PedestrianID, LaneID),
     IntersectionDelayMeasured(IntersectionID, Duration, TripMode),
 
     TripFinished {
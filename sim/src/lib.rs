@@ -1,3 +1,4 @@
+mod agent_positions;
 mod analytics;
 mod events;
 mod make;
@@ -10,21 +11,28 @@ mod sim;
 mod transit;
 mod trips;
 
-pub use self::analytics::{Analytics, TripPhase};
-pub(crate) use self::events::Event;
-pub use self::events::{AlertLocation, TripPhaseType};
+pub use self::agent_positions::{AgentMode, AgentPosition, AgentType};
+pub use self::analytics::{Analytics, CarSharingStats, TripPhase};
+pub use self::events::{AlertLocation, Event, TripPhaseType};
 pub use self::make::{
-    BorderSpawnOverTime, IndividTrip, OffMapLocation, OriginDestination, PersonSpec, Scenario,
+    census_scenario, load_population_from_census, BorderSpawnOverTime, CensusBlock, CensusPerson,
+    IndividTrip, ModeEndpoint, ModeSpec, OffMapLocation, OriginDestination, PersonSpec, Scenario,
     ScenarioGenerator, ScenarioModifier, SimFlags, SpawnOverTime, SpawnTrip, TripSpawner, TripSpec,
 };
 pub(crate) use self::mechanics::{
-    DrivingSimState, IntersectionSimState, ParkingSimState, WalkingSimState,
+    DrivingSimState, IntersectionSimState, ParkingSimState, SpeedOverrideSource, WalkingSimState,
 };
+pub use self::mechanics::{IntersectionLOS, ParkingGarage, PedestrianLOS};
 pub(crate) use self::pandemic::PandemicModel;
 pub(crate) use self::router::{ActionAtEnd, Router};
 pub(crate) use self::scheduler::{Command, Scheduler};
-pub use self::sim::{AgentProperties, AlertHandler, Sim, SimCallback, SimOptions};
-pub(crate) use self::transit::TransitSimState;
+pub use self::sim::{
+    AgentProperties, AlertHandler, BusStopDemand, CorridorStats, CurbsideStats, FloodImpact,
+    IncidentSeverity, NetworkFlowStats, ResilienceScore, RouteChoiceStats, SchoolZone,
+    SensitivityParam, Sim, SimCallback, SimOptions, SimStats, SnapshotDiff, StopSpacingStats,
+    TravelTimeDistribution, TripTimeComponents, WhatIfResult,
+};
+pub(crate) use self::transit::{curb_pos, TransitSimState};
 pub use self::trips::{Person, PersonState, TripResult};
 pub use self::trips::{TripEndpoint, TripMode};
 pub(crate) use self::trips::{TripLeg, TripManager};
@@ -48,10 +56,20 @@ pub const MIN_CAR_LENGTH: Distance = Distance::const_meters(4.5);
 pub const MAX_CAR_LENGTH: Distance = Distance::const_meters(6.5);
 // Note this is more than MAX_CAR_LENGTH
 pub const BUS_LENGTH: Distance = Distance::const_meters(12.5);
+// Still has to fit in one PARKING_SPOT_LENGTH
+pub const DELIVERY_TRUCK_LENGTH: Distance = Distance::const_meters(7.5);
+// A single delivery run can't visit more buildings than this
+pub const MAX_DAILY_DELIVERY_STOPS: usize = 20;
+pub const CARGO_BIKE_LENGTH: Distance = Distance::const_meters(2.5);
+// How many parcels fit in a cargo bike, and so how many stops one run can make
+pub const CARGO_BIKE_CAPACITY: usize = 8;
+pub const DEFAULT_DRT_FLEET_SIZE: usize = 5;
 
 // At all speeds (including at rest), cars must be at least this far apart, measured from front of
-// one car to the back of the other.
+// one car to the back of the other. Platoons are the exception.
 pub const FOLLOWING_DISTANCE: Distance = Distance::const_meters(1.0);
+// Cars in a platoon share braking, so they can follow much more closely
+pub const PLATOON_FOLLOWING_DISTANCE: Distance = Distance::const_meters(0.1);
 
 // The numeric ID must be globally unique, without considering VehicleType. VehicleType is bundled
 // for convenient debugging.
@@ -65,6 +83,10 @@ impl fmt::Display for CarID {
             VehicleType::Car => write!(f, "Car #{}", self.0),
             VehicleType::Bus => write!(f, "Bus #{}", self.0),
             VehicleType::Bike => write!(f, "Bike #{}", self.0),
+            VehicleType::EBike => write!(f, "E-bike #{}", self.0),
+            VehicleType::DeliveryTruck => write!(f, "Delivery truck #{}", self.0),
+            VehicleType::CargoBike => write!(f, "Cargo bike #{}", self.0),
+            VehicleType::SchoolBus => write!(f, "School bus #{}", self.0),
         }
     }
 }
@@ -122,6 +144,15 @@ impl fmt::Display for PersonID {
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct IncidentID(pub usize);
+
+impl fmt::Display for IncidentID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Incident #{}", self.0)
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct OrigPersonID(pub usize, pub usize);
 
@@ -130,6 +161,10 @@ pub enum VehicleType {
     Car,
     Bus,
     Bike,
+    EBike,
+    DeliveryTruck,
+    CargoBike,
+    SchoolBus,
 }
 
 impl fmt::Display for VehicleType {
@@ -138,6 +173,10 @@ impl fmt::Display for VehicleType {
             VehicleType::Car => write!(f, "car"),
             VehicleType::Bus => write!(f, "bus"),
             VehicleType::Bike => write!(f, "bike"),
+            VehicleType::EBike => write!(f, "e-bike"),
+            VehicleType::DeliveryTruck => write!(f, "delivery truck"),
+            VehicleType::CargoBike => write!(f, "cargo bike"),
+            VehicleType::SchoolBus => write!(f, "school bus"),
         }
     }
 }
@@ -145,9 +184,22 @@ impl fmt::Display for VehicleType {
 impl VehicleType {
     pub fn to_constraints(self) -> PathConstraints {
         match self {
-            VehicleType::Car => PathConstraints::Car,
+            // School buses don't get to use transit lanes
+            VehicleType::Car | VehicleType::DeliveryTruck | VehicleType::SchoolBus => {
+                PathConstraints::Car
+            }
             VehicleType::Bus => PathConstraints::Bus,
-            VehicleType::Bike => PathConstraints::Bike,
+            VehicleType::Bike | VehicleType::EBike | VehicleType::CargoBike => {
+                PathConstraints::Bike
+            }
+        }
+    }
+
+    // E-bikes and cargo bikes behave like bikes, except for their length and speed.
+    pub fn is_bike(self) -> bool {
+        match self {
+            VehicleType::Bike | VehicleType::EBike | VehicleType::CargoBike => true,
+            _ => false,
         }
     }
 }
@@ -240,7 +292,7 @@ impl DrivingGoal {
     pub(crate) fn make_router(&self, path: Path, map: &Map, vt: VehicleType) -> Option<Router> {
         match self {
             DrivingGoal::ParkNear(b) => {
-                if vt == VehicleType::Bike {
+                if vt.is_bike() {
                     // TODO Stop closer to the building?
                     let end = path.last_step().as_lane();
                     Router::bike_then_stop(path, map.get_l(end).length() / 2.0, map)
@@ -494,6 +546,8 @@ pub struct CreateCar {
     pub maybe_parked_car: Option<ParkedCar>,
     // None for buses
     pub trip_and_person: Option<(TripID, PersonID)>,
+    // Replan around congested lanes along the way
+    pub adaptive_routing: bool,
 }
 
 impl CreateCar {
@@ -512,6 +566,7 @@ impl CreateCar {
             start_dist: start_pos.dist_along(),
             maybe_parked_car: None,
             trip_and_person: Some((trip, person)),
+            adaptive_routing: false,
         }
     }
 
@@ -531,6 +586,7 @@ impl CreateCar {
             start_dist,
             maybe_parked_car: Some(parked_car),
             trip_and_person: Some((trip, person)),
+            adaptive_routing: false,
         }
     }
 }
@@ -0,0 +1,233 @@
+use crate::{DrivingGoal, IndividTrip, PersonID, PersonSpec, Scenario, SidewalkSpot, SpawnTrip};
+use abstutil::Timer;
+use geom::{Duration, GPSBounds, LonLat, Polygon, Pt2D, Time};
+use map_model::{BuildingID, Map};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_xorshift::XorShiftRng;
+use serde::Deserialize;
+
+// One census block group, already converted to map-space.
+pub struct CensusBlock {
+    pub boundary: Vec<Pt2D>,
+    pub population: usize,
+    pub mean_income: f64,
+    pub car_ownership_rate: f64,
+}
+
+// Somebody living on the map, derived from census data.
+#[derive(Clone, Debug)]
+pub struct CensusPerson {
+    pub home: BuildingID,
+    pub income: f64,
+    pub owns_car: bool,
+}
+
+// Just the parts of GeoJSON we need
+#[derive(Deserialize)]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    geometry: Geometry,
+    properties: Properties,
+}
+
+#[derive(Deserialize)]
+struct Geometry {
+    // Only polygons are supported; just use the outer ring.
+    coordinates: Vec<Vec<(f64, f64)>>,
+}
+
+#[derive(Deserialize)]
+struct Properties {
+    population: usize,
+    mean_income: f64,
+    car_ownership_rate: f64,
+}
+
+impl CensusBlock {
+    pub fn load_all(
+        path: String,
+        gps_bounds: &GPSBounds,
+        timer: &mut Timer,
+    ) -> Result<Vec<CensusBlock>, std::io::Error> {
+        let collection: FeatureCollection = abstutil::maybe_read_json(path, timer)?;
+        let mut blocks = Vec::new();
+        for f in collection.features {
+            if let Some(ring) = f.geometry.coordinates.into_iter().next() {
+                blocks.push(CensusBlock {
+                    boundary: ring
+                        .into_iter()
+                        .map(|(lon, lat)| {
+                            Pt2D::forcibly_from_gps(LonLat::new(lon, lat), gps_bounds)
+                        })
+                        .collect(),
+                    population: f.properties.population,
+                    mean_income: f.properties.mean_income,
+                    car_ownership_rate: f.properties.car_ownership_rate,
+                });
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+// Read census blocks from a GeoJSON file, then place people in the buildings of each block. The
+// map doesn't know how many floors a building has, so people are distributed proportionally to
+// building footprint area instead.
+pub fn load_population_from_census(
+    path: String,
+    map: &Map,
+    rng: &mut XorShiftRng,
+    timer: &mut Timer,
+) -> Result<Vec<CensusPerson>, std::io::Error> {
+    let mut people = Vec::new();
+    for block in CensusBlock::load_all(path, map.get_gps_bounds(), timer)? {
+        if block.boundary.len() < 3 {
+            continue;
+        }
+        let bldgs = map.get_buildings_in_polygon(&Polygon::new(&block.boundary));
+        if bldgs.is_empty() {
+            timer.warn(format!(
+                "Census block with {} people doesn't contain any buildings",
+                block.population
+            ));
+            continue;
+        }
+        let areas: Vec<f64> = bldgs.iter().map(|b| map.get_b(*b).polygon.area()).collect();
+        for (b, count) in bldgs.into_iter().zip(apportion(block.population, &areas)) {
+            for _ in 0..count {
+                people.push(CensusPerson {
+                    home: b,
+                    income: block.mean_income,
+                    owns_car: rng.gen_bool(block.car_ownership_rate.max(0.0).min(1.0)),
+                });
+            }
+        }
+    }
+    Ok(people)
+}
+
+// Split a population between buildings proportionally to their area. Round down for every
+// building, then give the leftovers to the largest remainders.
+fn apportion(population: usize, areas: &Vec<f64>) -> Vec<usize> {
+    let total_area: f64 = areas.iter().sum();
+    let mut counts = Vec::new();
+    let mut remainders = Vec::new();
+    for (idx, area) in areas.iter().enumerate() {
+        let exact = (population as f64) * area / total_area;
+        counts.push(exact.floor() as usize);
+        remainders.push((idx, exact - exact.floor()));
+    }
+    remainders.sort_by(|(_, r1), (_, r2)| r2.partial_cmp(r1).unwrap());
+    let leftover = population - counts.iter().sum::<usize>();
+    for (idx, _) in remainders.into_iter().cycle().take(leftover) {
+        counts[idx] += 1;
+    }
+    counts
+}
+
+// Everybody commutes from home to some other building in the morning and back in the evening.
+// People owning a car drive, so scenario instantiation seeds their car at home; everybody else
+// walks.
+pub fn census_scenario(
+    name: &str,
+    people: &Vec<CensusPerson>,
+    map: &Map,
+    rng: &mut XorShiftRng,
+) -> Scenario {
+    let mut scenario = Scenario::empty(map, name);
+    for person in people {
+        let work = map.all_buildings().choose(rng).unwrap().id;
+        if work == person.home {
+            continue;
+        }
+        let leave_home = Time::START_OF_DAY + Duration::hours(7) + rand_duration(rng);
+        let leave_work = Time::START_OF_DAY + Duration::hours(16) + rand_duration(rng);
+        let (to_work, to_home) = if person.owns_car {
+            (
+                SpawnTrip::UsingParkedCar(person.home, DrivingGoal::ParkNear(work)),
+                SpawnTrip::UsingParkedCar(work, DrivingGoal::ParkNear(person.home)),
+            )
+        } else {
+            let home = SidewalkSpot::building(person.home, map);
+            let work = SidewalkSpot::building(work, map);
+            (
+                SpawnTrip::JustWalking(home.clone(), work.clone()),
+                SpawnTrip::JustWalking(work, home),
+            )
+        };
+        scenario.people.push(PersonSpec {
+            id: PersonID(scenario.people.len()),
+            orig_id: None,
+            trips: vec![
+                IndividTrip {
+                    depart: leave_home,
+                    trip: to_work,
+                    cancelled: false,
+                },
+                IndividTrip {
+                    depart: leave_work,
+                    trip: to_home,
+                    cancelled: false,
+                },
+            ],
+        });
+    }
+    scenario
+}
+
+// Spread departures over a two hour window
+fn rand_duration(rng: &mut XorShiftRng) -> Duration {
+    Duration::seconds(rng.gen_range(0.0, 7200.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apportion() {
+        assert_eq!(apportion(10, &vec![1.0, 1.0]), vec![5, 5]);
+        // 7 * 1/3 = 2.33 and 7 * 2/3 = 4.67, so the leftover goes to the bigger remainder.
+        assert_eq!(apportion(7, &vec![1.0, 2.0]), vec![2, 5]);
+        assert_eq!(apportion(2, &vec![1.0, 1.0, 1.0]), vec![1, 1, 0]);
+        assert_eq!(apportion(0, &vec![3.0]), vec![0]);
+    }
+
+    #[test]
+    fn test_load_blocks() {
+        let path = std::env::temp_dir()
+            .join("test_census_blocks.geojson")
+            .to_str()
+            .unwrap()
+            .to_string();
+        std::fs::write(
+            &path,
+            r#"{"features": [
+                {
+                    "geometry": {"coordinates": [[[-122.3, 47.6], [-122.2, 47.6], [-122.2, 47.7]]]},
+                    "properties": {"population": 120, "mean_income": 50000.0, "car_ownership_rate": 0.5}
+                },
+                {
+                    "geometry": {"coordinates": []},
+                    "properties": {"population": 10, "mean_income": 0.0, "car_ownership_rate": 0.0}
+                }
+            ]}"#,
+        )
+        .unwrap();
+
+        let mut gps_bounds = GPSBounds::new();
+        gps_bounds.update(LonLat::new(-122.3, 47.6));
+        gps_bounds.update(LonLat::new(-122.2, 47.7));
+        let blocks = CensusBlock::load_all(path, &gps_bounds, &mut Timer::throwaway()).unwrap();
+        // The block without a boundary is skipped
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].boundary.len(), 3);
+        assert_eq!(blocks[0].population, 120);
+        assert_eq!(blocks[0].car_ownership_rate, 0.5);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{AlertHandler, Scenario, Sim, SimOptions};
 use abstutil::CmdArgs;
+use geom::Duration;
 use map_model::{Map, MapEdits};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
@@ -47,6 +48,18 @@ impl SimFlags {
                     })
                     .unwrap_or(AlertHandler::Print),
                 pathfinding_upfront: args.enabled("--pathfinding_upfront"),
+                pedestrian_route_choices: args
+                    .optional_parse("--pedestrian_route_choices", |s| s.parse())
+                    .unwrap_or(1),
+                detect_queue_spillback: args.enabled("--detect_queue_spillback"),
+                routing_oscillation: (
+                    args.optional_parse("--routing_oscillation_replans", |s| s.parse())
+                        .unwrap_or(3),
+                    args.optional_parse("--routing_oscillation_window", |s| {
+                        s.parse().map(Duration::seconds)
+                    })
+                    .unwrap_or(Duration::minutes(10)),
+                ),
             },
         }
     }
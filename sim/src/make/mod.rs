@@ -1,13 +1,15 @@
+mod census;
 mod generator;
 mod load;
 mod modifier;
 mod scenario;
 mod spawner;
 
+pub use self::census::{census_scenario, load_population_from_census, CensusBlock, CensusPerson};
 pub use self::generator::{
     BorderSpawnOverTime, OriginDestination, ScenarioGenerator, SpawnOverTime,
 };
 pub use self::load::SimFlags;
 pub use self::modifier::ScenarioModifier;
 pub use self::scenario::{IndividTrip, OffMapLocation, PersonSpec, Scenario, SpawnTrip};
-pub use self::spawner::{TripSpawner, TripSpec};
+pub use self::spawner::{ModeEndpoint, ModeSpec, TripSpawner, TripSpec};
@@ -1,12 +1,13 @@
 use crate::{
     CarID, DrivingGoal, OrigPersonID, ParkingSpot, PersonID, SidewalkPOI, SidewalkSpot, Sim,
     TripEndpoint, TripMode, TripSpec, Vehicle, VehicleSpec, VehicleType, BIKE_LENGTH,
-    MAX_CAR_LENGTH, MIN_CAR_LENGTH,
+    CARGO_BIKE_LENGTH, DELIVERY_TRUCK_LENGTH, MAX_CAR_LENGTH, MIN_CAR_LENGTH,
 };
 use abstutil::{prettyprint_usize, Counter, Timer};
 use geom::{Distance, Duration, LonLat, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, DirectedRoadID, Map, PathConstraints, Position, RoadID,
+    BuildingID, BusRouteID, BusStopID, DirectedRoadID, LaneID, Map, PathConstraints, Position,
+    RoadID,
 };
 use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
@@ -125,7 +126,16 @@ impl Scenario {
                     &mut tmp_rng,
                     map,
                 );
-                spawner.schedule_trip(person, t.depart, spec, t.trip.start(map), t.cancelled, map);
+                if let Err(err) = spawner.schedule_trip(
+                    person,
+                    t.depart,
+                    spec,
+                    t.trip.start(map),
+                    t.cancelled,
+                    map,
+                ) {
+                    panic!("{}", err);
+                }
             }
         }
 
@@ -175,6 +185,50 @@ impl Scenario {
         }
     }
 
+    // TODO Once grades affect speed, e-bikes should slow down less going uphill.
+    pub fn rand_e_bike(rng: &mut XorShiftRng) -> VehicleSpec {
+        let max_speed = Some(Scenario::rand_speed(
+            rng,
+            Speed::miles_per_hour(14.0),
+            Speed::miles_per_hour(17.0),
+        ));
+        VehicleSpec {
+            vehicle_type: VehicleType::EBike,
+            length: BIKE_LENGTH,
+            max_speed,
+        }
+    }
+
+    pub fn rand_cargo_bike(rng: &mut XorShiftRng) -> VehicleSpec {
+        let max_speed = Some(Scenario::rand_speed(
+            rng,
+            Speed::meters_per_second(3.5),
+            Speed::meters_per_second(4.5),
+        ));
+        VehicleSpec {
+            vehicle_type: VehicleType::CargoBike,
+            length: CARGO_BIKE_LENGTH,
+            max_speed,
+        }
+    }
+
+    pub fn delivery_truck() -> VehicleSpec {
+        VehicleSpec {
+            vehicle_type: VehicleType::DeliveryTruck,
+            length: DELIVERY_TRUCK_LENGTH,
+            max_speed: None,
+        }
+    }
+
+    // Demand-responsive minibuses don't need transit lanes, so they're just the longest cars
+    pub fn drt_minibus() -> VehicleSpec {
+        VehicleSpec {
+            vehicle_type: VehicleType::Car,
+            length: MAX_CAR_LENGTH,
+            max_speed: None,
+        }
+    }
+
     pub fn rand_dist(rng: &mut XorShiftRng, low: Distance, high: Distance) -> Distance {
         assert!(high > low);
         Distance::meters(rng.gen_range(low.inner_meters(), high.inner_meters()))
@@ -270,6 +324,13 @@ fn seed_parked_cars(
         }
     }
 
+    let garage_lanes: Vec<LaneID> = sim
+        .get_all_garages()
+        .iter()
+        .flat_map(|g| g.lanes.clone())
+        .collect();
+    let garage_walk_threshold = sim.get_garage_walk_threshold();
+
     timer.start_iter("seed parked cars", parked_cars.len());
     let mut ok = true;
     for (vehicle, b) in parked_cars {
@@ -277,7 +338,15 @@ fn seed_parked_cars(
         if !ok {
             continue;
         }
-        if let Some(spot) = find_spot_near_building(b, &mut open_spots_per_road, map, timer) {
+        if let Some(spot) = find_garage_spot_near_building(
+            b,
+            &garage_lanes,
+            garage_walk_threshold,
+            &mut open_spots_per_road,
+            map,
+        )
+        .or_else(|| find_spot_near_building(b, &mut open_spots_per_road, map, timer))
+        {
             sim.seed_parked_car(vehicle, spot);
         } else {
             timer.warn("Not enough room to seed parked cars.".to_string());
@@ -286,6 +355,33 @@ fn seed_parked_cars(
     }
 }
 
+// Prefer parking in a garage close to the building. Uses straight-line distance, not the real
+// walking distance.
+fn find_garage_spot_near_building(
+    b: BuildingID,
+    garage_lanes: &[LaneID],
+    threshold: Distance,
+    open_spots_per_road: &mut BTreeMap<RoadID, Vec<(ParkingSpot, Option<BuildingID>)>>,
+    map: &Map,
+) -> Option<ParkingSpot> {
+    let pt = map.get_b(b).label_center;
+    for l in garage_lanes {
+        let lane = map.get_l(*l);
+        if lane.lane_center_pts.middle().dist_to(pt) > threshold {
+            continue;
+        }
+        if let Some(spots) = open_spots_per_road.get_mut(&lane.parent) {
+            if let Some(idx) = spots.iter().position(|(spot, _)| match spot {
+                ParkingSpot::Onstreet(l2, _) => l2 == l,
+                _ => false,
+            }) {
+                return Some(spots.remove(idx).0);
+            }
+        }
+    }
+    None
+}
+
 // Pick a parking spot for this building. If the building's road has a free spot, use it. If not,
 // start BFSing out from the road in a deterministic way until finding a nearby road with an open
 // spot.
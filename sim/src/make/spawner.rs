@@ -1,9 +1,10 @@
 use crate::{
     CarID, Command, DrivingGoal, OffMapLocation, Person, PersonID, Scheduler, SidewalkSpot,
-    TripEndpoint, TripLeg, TripManager, TripMode, VehicleType, BIKE_LENGTH, MAX_CAR_LENGTH,
+    TripEndpoint, TripLeg, TripManager, TripMode, VehicleType, BIKE_LENGTH, CARGO_BIKE_CAPACITY,
+    MAX_CAR_LENGTH, MAX_DAILY_DELIVERY_STOPS,
 };
 use abstutil::Timer;
-use geom::{Duration, Time, EPSILON_DIST};
+use geom::{Distance, Duration, Time, EPSILON_DIST};
 use map_model::{
     BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints, PathRequest, Position,
 };
@@ -39,11 +40,26 @@ pub enum TripSpec {
         start: SidewalkSpot,
         goal: SidewalkSpot,
     },
+    // Drive a parked truck to each building in order, walking to the front door at every stop.
+    UsingDeliveryTruck {
+        // This must be a currently parked DeliveryTruck owned by the person.
+        truck: CarID,
+        start_bldg: BuildingID,
+        deliveries: Vec<BuildingID>,
+    },
     UsingBike {
         bike: CarID,
         start: SidewalkSpot,
         goal: DrivingGoal,
     },
+    // Ride a cargo bike to each building in order, walking to the door at every stop, then ride
+    // back to start_bldg.
+    UsingCargoBike {
+        // This must be a CargoBike owned by the person.
+        bike: CarID,
+        start_bldg: BuildingID,
+        deliveries: Vec<BuildingID>,
+    },
     UsingTransit {
         start: SidewalkSpot,
         goal: SidewalkSpot,
@@ -51,6 +67,30 @@ pub enum TripSpec {
         stop1: BusStopID,
         stop2: BusStopID,
     },
+    // Wait at a building for a ride-hailing driver, who drops them off at another building.
+    UsingRideHail {
+        start_bldg: BuildingID,
+        goal: BuildingID,
+    },
+    // Wait at a building for an on-demand minibus, which drops them off at another building.
+    UsingDemandResponsiveTransit {
+        start_bldg: BuildingID,
+        goal: BuildingID,
+    },
+    // Wait at a building for a school bus, which drops everybody off at the curb near the school.
+    // Then walk inside.
+    UsingSchoolBus {
+        start_bldg: BuildingID,
+        bus: CarID,
+        school: BuildingID,
+    },
+    // From TripSpawner::start_trip_using_multimodal_chain. The person starts by walking to the
+    // first leg.
+    MultimodalChain {
+        start_bldg: BuildingID,
+        legs: Vec<TripLeg>,
+        mode: TripMode,
+    },
     // Completely off-map trip. Don't really simulate much of it.
     Remote {
         from: OffMapLocation,
@@ -60,6 +100,36 @@ pub enum TripSpec {
     },
 }
 
+// One leg of a trip described by TripSpawner::start_trip_using_multimodal_chain
+#[derive(Clone, Debug)]
+pub struct ModeSpec {
+    pub mode: TripMode,
+    pub origin: ModeEndpoint,
+    pub destination: ModeEndpoint,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModeEndpoint {
+    Bldg(BuildingID),
+    BusStop(BusStopID),
+}
+
+impl ModeEndpoint {
+    fn sidewalk_spot(self, map: &Map) -> SidewalkSpot {
+        match self {
+            ModeEndpoint::Bldg(b) => SidewalkSpot::building(b, map),
+            ModeEndpoint::BusStop(bs) => SidewalkSpot::bus_stop(bs, map),
+        }
+    }
+
+    fn bldg(self) -> Result<BuildingID, String> {
+        match self {
+            ModeEndpoint::Bldg(b) => Ok(b),
+            ModeEndpoint::BusStop(bs) => Err(format!("Only transit can start or end at {}", bs)),
+        }
+    }
+}
+
 // This structure is created temporarily by a Scenario or to interactively spawn agents.
 pub struct TripSpawner {
     trips: Vec<(PersonID, Time, TripSpec, TripEndpoint, bool)>,
@@ -78,7 +148,7 @@ impl TripSpawner {
         trip_start: TripEndpoint,
         cancelled: bool,
         map: &Map,
-    ) {
+    ) -> Result<(), String> {
         // TODO We'll want to repeat this validation when we spawn stuff later for a second leg...
         match &spec {
             TripSpec::VehicleAppearing {
@@ -89,29 +159,29 @@ impl TripSpawner {
             } => {
                 let vehicle = person.get_vehicle(*use_vehicle);
                 if start_pos.dist_along() < vehicle.length {
-                    panic!(
+                    return Err(format!(
                         "Can't spawn a {:?} at {}; too close to the start",
                         vehicle.vehicle_type,
                         start_pos.dist_along()
-                    );
+                    ));
                 }
                 if start_pos.dist_along() >= map.get_l(start_pos.lane()).length() {
-                    panic!(
+                    return Err(format!(
                         "Can't spawn a {:?} at {}; {} isn't that long",
                         vehicle.vehicle_type,
                         start_pos.dist_along(),
                         start_pos.lane()
-                    );
+                    ));
                 }
                 match goal {
                     DrivingGoal::Border(_, end_lane, _) => {
                         if start_pos.lane() == *end_lane
                             && start_pos.dist_along() == map.get_l(*end_lane).length()
                         {
-                            panic!(
+                            return Err(format!(
                                 "Can't start a {:?} at the edge of a border already",
                                 vehicle.vehicle_type
-                            );
+                            ));
                         }
                     }
                     DrivingGoal::ParkNear(_) => {}
@@ -119,12 +189,75 @@ impl TripSpawner {
             }
             TripSpec::NoRoomToSpawn { .. } => {}
             TripSpec::UsingParkedCar { .. } => {}
+            TripSpec::UsingDeliveryTruck {
+                truck, deliveries, ..
+            } => {
+                if truck.1 != VehicleType::DeliveryTruck {
+                    return Err(format!("{} can't be used for deliveries", truck));
+                }
+                if deliveries.is_empty() || deliveries.len() > MAX_DAILY_DELIVERY_STOPS {
+                    return Err(format!(
+                        "A delivery run has to visit between 1 and {} buildings, not {}",
+                        MAX_DAILY_DELIVERY_STOPS,
+                        deliveries.len()
+                    ));
+                }
+            }
             TripSpec::JustWalking { start, goal, .. } => {
                 if start == goal {
-                    panic!(
+                    return Err(format!(
                         "A trip just walking from {:?} to {:?} doesn't make sense",
                         start, goal
-                    );
+                    ));
+                }
+            }
+            TripSpec::UsingCargoBike {
+                bike,
+                start_bldg,
+                deliveries,
+            } => {
+                if bike.1 != VehicleType::CargoBike {
+                    return Err(format!("{} can't be used for cargo deliveries", bike));
+                }
+                if deliveries.is_empty() || deliveries.len() > CARGO_BIKE_CAPACITY {
+                    return Err(format!(
+                        "A cargo bike can visit between 1 and {} buildings, not {}",
+                        CARGO_BIKE_CAPACITY,
+                        deliveries.len()
+                    ));
+                }
+                // Every stop has to have somewhere to lock up the bike, and consecutive stops
+                // can't share a sidewalk.
+                let mut stops = vec![*start_bldg];
+                stops.extend(deliveries.iter().cloned());
+                stops.push(*start_bldg);
+                for pair in stops.windows(2) {
+                    let sidewalk = map.get_b(pair[0]).sidewalk();
+                    if SidewalkSpot::bike_from_bike_rack(sidewalk, map).is_none() {
+                        return Err(format!(
+                            "Cargo bike can't start from {}; no bike rack nearby",
+                            sidewalk
+                        ));
+                    }
+                    if sidewalk == map.get_b(pair[1]).sidewalk() {
+                        return Err(format!(
+                            "Cargo bike can't ride from {} to {}; it's the same sidewalk!",
+                            pair[0], pair[1]
+                        ));
+                    }
+                }
+            }
+            TripSpec::UsingRideHail { start_bldg, goal }
+            | TripSpec::UsingDemandResponsiveTransit { start_bldg, goal } => {
+                if start_bldg == goal {
+                    return Err(format!("On-demand trip starts and ends at {}", start_bldg));
+                }
+            }
+            TripSpec::UsingSchoolBus {
+                start_bldg, school, ..
+            } => {
+                if start_bldg == school {
+                    return Err(format!("School bus trip starts and ends at {}", start_bldg));
                 }
             }
             TripSpec::UsingBike { start, goal, .. } => {
@@ -135,7 +268,7 @@ impl TripSpawner {
                         "Can't start biking from {}; no biking or driving lane nearby?",
                         start.sidewalk_pos.lane()
                     );
-                    return;
+                    return Ok(());
                 }
                 if let DrivingGoal::ParkNear(b) = goal {
                     let last_lane = goal.goal_pos(PathConstraints::Bike, map).lane();
@@ -149,7 +282,7 @@ impl TripSpawner {
                             "Can't fulfill {:?} for a bike trip; no sidewalk near {}",
                             goal, last_lane
                         );
-                        return;
+                        return Ok(());
                     }
                     // A bike trip going from one lane to the same lane should... just walk.
                     if start.sidewalk_pos.lane() == map.get_b(*b).sidewalk() {
@@ -167,16 +300,246 @@ impl TripSpawner {
                             trip_start,
                             cancelled,
                         ));
-                        return;
+                        return Ok(());
                     }
                 }
             }
             TripSpec::UsingTransit { .. } => {}
+            // Validated by start_trip_using_multimodal_chain
+            TripSpec::MultimodalChain { .. } => {}
             TripSpec::Remote { .. } => {}
         };
 
         self.trips
             .push((person.id, start_time, spec, trip_start, cancelled));
+        Ok(())
+    }
+
+    // Drive from origin_bldg to parking near transit_stop, then ride a bus serving that stop to
+    // whichever of its stops is closest to dest_bldg, then walk the rest of the way. Fails if no
+    // route from transit_stop gets any closer to the destination.
+    pub fn start_trip_using_park_and_ride(
+        &mut self,
+        person: &Person,
+        start_time: Time,
+        origin_bldg: BuildingID,
+        transit_stop: BusStopID,
+        dest_bldg: BuildingID,
+        map: &Map,
+    ) -> Result<(), String> {
+        let stop_pos = map.get_bs(transit_stop).sidewalk_pos;
+        let stop_pt = stop_pos.pt(map);
+        // The driving router will find the actual parking spot near this building.
+        let park_near = map
+            .get_l(stop_pos.lane())
+            .building_paths
+            .iter()
+            .min_by_key(|b| map.get_b(**b).front_path.sidewalk.pt(map).dist_to(stop_pt))
+            .cloned()
+            .or_else(|| {
+                map.all_buildings()
+                    .iter()
+                    .min_by_key(|b| b.front_path.sidewalk.pt(map).dist_to(stop_pt))
+                    .map(|b| b.id)
+            })
+            .ok_or_else(|| format!("No buildings near {} to park at", transit_stop))?;
+
+        // Only ride towards the destination
+        let dest_pt = map.get_b(dest_bldg).front_path.sidewalk.pt(map);
+        let mut best: Option<(BusStopID, Distance)> = None;
+        for route in map.get_routes_serving_stop(transit_stop) {
+            for stop in &route.stops {
+                if *stop == transit_stop {
+                    continue;
+                }
+                let dist = map.get_bs(*stop).sidewalk_pos.pt(map).dist_to(dest_pt);
+                if dist < stop_pt.dist_to(dest_pt) && best.map(|(_, d)| dist < d).unwrap_or(true) {
+                    best = Some((*stop, dist));
+                }
+            }
+        }
+        let (stop2, _) = best
+            .ok_or_else(|| format!("No route from {} heads towards {}", transit_stop, dest_bldg))?;
+
+        let stop1 = ModeEndpoint::BusStop(transit_stop);
+        let stop2 = ModeEndpoint::BusStop(stop2);
+        self.start_trip_using_multimodal_chain(
+            person,
+            start_time,
+            vec![
+                ModeSpec {
+                    mode: TripMode::Drive,
+                    origin: ModeEndpoint::Bldg(origin_bldg),
+                    destination: ModeEndpoint::Bldg(park_near),
+                },
+                ModeSpec {
+                    mode: TripMode::Walk,
+                    origin: ModeEndpoint::Bldg(park_near),
+                    destination: stop1,
+                },
+                ModeSpec {
+                    mode: TripMode::Transit,
+                    origin: stop1,
+                    destination: stop2,
+                },
+                ModeSpec {
+                    mode: TripMode::Walk,
+                    origin: stop2,
+                    destination: ModeEndpoint::Bldg(dest_bldg),
+                },
+            ],
+            map,
+        )
+    }
+
+    // Drive the person's parked delivery truck from origin_warehouse to each of the deliveries in
+    // order. At every stop, the driver parks, walks from the curb to the front door, then walks
+    // back to the truck.
+    pub fn start_trip_with_goods_delivery(
+        &mut self,
+        person: &Person,
+        start_time: Time,
+        origin_warehouse: BuildingID,
+        deliveries: Vec<BuildingID>,
+        map: &Map,
+    ) -> Result<(), String> {
+        let truck = person
+            .vehicles
+            .iter()
+            .find(|v| v.vehicle_type == VehicleType::DeliveryTruck)
+            .map(|v| v.id)
+            .ok_or_else(|| format!("{} doesn't own a delivery truck", person.id))?;
+        self.schedule_trip(
+            person,
+            start_time,
+            TripSpec::UsingDeliveryTruck {
+                truck,
+                start_bldg: origin_warehouse,
+                deliveries,
+            },
+            TripEndpoint::Bldg(origin_warehouse),
+            false,
+            map,
+        )
+    }
+
+    // Describe a trip as a sequence of legs. Each leg has to start where the previous one ended,
+    // and switching vehicles requires walking in between. Walking to and from a parked car or
+    // bike is implied by the driving or biking leg. Transit legs go between bus stops, and the
+    // trip has to start and end at buildings.
+    pub fn start_trip_using_multimodal_chain(
+        &mut self,
+        person: &Person,
+        start_time: Time,
+        legs: Vec<ModeSpec>,
+        map: &Map,
+    ) -> Result<(), String> {
+        if legs.is_empty() {
+            return Err("A trip needs at least one leg".to_string());
+        }
+        let start_bldg = legs[0].origin.bldg()?;
+        legs.last().unwrap().destination.bldg()?;
+        for leg in &legs {
+            if leg.origin == leg.destination {
+                return Err(format!("{:?} doesn't go anywhere", leg));
+            }
+        }
+        for pair in legs.windows(2) {
+            if pair[0].destination != pair[1].origin {
+                return Err(format!(
+                    "{:?} doesn't start where {:?} ends",
+                    pair[1], pair[0]
+                ));
+            }
+            if pair[0].mode != TripMode::Walk && pair[1].mode != TripMode::Walk {
+                return Err(format!(
+                    "Have to walk between {:?} and {:?}",
+                    pair[0].mode, pair[1].mode
+                ));
+            }
+            if let ModeEndpoint::BusStop(bs) = pair[0].destination {
+                if pair[0].mode != TripMode::Transit && pair[1].mode != TripMode::Transit {
+                    return Err(format!("Nobody gets on or off transit at {}", bs));
+                }
+            }
+        }
+
+        let vehicle = |vt: VehicleType| {
+            person
+                .vehicles
+                .iter()
+                .find(|v| v.vehicle_type == vt)
+                .map(|v| v.id)
+                .ok_or_else(|| format!("{} doesn't own a {:?}", person.id, vt))
+        };
+        let mut trip_legs = Vec::new();
+        for (idx, leg) in legs.iter().enumerate() {
+            match leg.mode {
+                TripMode::Walk => {
+                    trip_legs.push(TripLeg::Walk(leg.destination.sidewalk_spot(map)));
+                }
+                TripMode::Transit => match (leg.origin, leg.destination) {
+                    (ModeEndpoint::BusStop(stop1), ModeEndpoint::BusStop(stop2)) => {
+                        let route = map
+                            .get_routes_serving_stop(stop1)
+                            .into_iter()
+                            .find(|r| r.stops.contains(&stop2))
+                            .ok_or_else(|| format!("No route goes from {} to {}", stop1, stop2))?;
+                        trip_legs.push(TripLeg::RideBus(route.id, stop2));
+                    }
+                    _ => {
+                        return Err("Transit legs have to go between bus stops".to_string());
+                    }
+                },
+                TripMode::Drive | TripMode::Bike => {
+                    let (from, to) = (leg.origin.bldg()?, leg.destination.bldg()?);
+                    if leg.mode == TripMode::Drive {
+                        trip_legs.push(TripLeg::Walk(SidewalkSpot::deferred_parking_spot()));
+                        trip_legs.push(TripLeg::Drive(
+                            vehicle(VehicleType::Car)?,
+                            DrivingGoal::ParkNear(to),
+                        ));
+                    } else {
+                        let sidewalk = map.get_b(from).sidewalk();
+                        if sidewalk == map.get_b(to).sidewalk() {
+                            return Err(format!(
+                                "Can't bike from {} to {}; it's the same sidewalk",
+                                from, to
+                            ));
+                        }
+                        let bike_rack = SidewalkSpot::bike_from_bike_rack(sidewalk, map)
+                            .ok_or_else(|| format!("No bike rack near {}", from))?;
+                        trip_legs.push(TripLeg::Walk(bike_rack));
+                        trip_legs.push(TripLeg::Drive(
+                            vehicle(VehicleType::Bike).or_else(|_| vehicle(VehicleType::EBike))?,
+                            DrivingGoal::ParkNear(to),
+                        ));
+                    }
+                    // Otherwise the next leg says where to walk after parking
+                    if idx == legs.len() - 1 {
+                        trip_legs.push(TripLeg::Walk(SidewalkSpot::building(to, map)));
+                    }
+                }
+            }
+        }
+        // The trip counts as the most involved mode used
+        let mode = vec![TripMode::Transit, TripMode::Drive, TripMode::Bike]
+            .into_iter()
+            .find(|mode| legs.iter().any(|leg| leg.mode == *mode))
+            .unwrap_or(TripMode::Walk);
+
+        self.schedule_trip(
+            person,
+            start_time,
+            TripSpec::MultimodalChain {
+                start_bldg,
+                legs: trip_legs,
+                mode,
+            },
+            TripEndpoint::Bldg(start_bldg),
+            false,
+            map,
+        )
     }
 
     pub fn finalize(
@@ -232,7 +595,7 @@ impl TripSpawner {
                         person.id,
                         start_time,
                         trip_start,
-                        if use_vehicle.1 == VehicleType::Bike {
+                        if use_vehicle.1.is_bike() {
                             TripMode::Bike
                         } else {
                             TripMode::Drive
@@ -252,7 +615,7 @@ impl TripSpawner {
                         person.id,
                         start_time,
                         trip_start,
-                        if use_vehicle.1 == VehicleType::Bike {
+                        if use_vehicle.1.is_bike() {
                             TripMode::Bike
                         } else {
                             TripMode::Drive
@@ -281,6 +644,24 @@ impl TripSpawner {
                         map,
                     )
                 }
+                TripSpec::UsingDeliveryTruck {
+                    truck, deliveries, ..
+                } => {
+                    let mut legs = Vec::new();
+                    for b in deliveries {
+                        legs.push(TripLeg::Walk(SidewalkSpot::deferred_parking_spot()));
+                        legs.push(TripLeg::Drive(truck, DrivingGoal::ParkNear(b)));
+                        legs.push(TripLeg::Walk(SidewalkSpot::building(b, map)));
+                    }
+                    trips.new_trip(
+                        person.id,
+                        start_time,
+                        trip_start,
+                        TripMode::Drive,
+                        legs,
+                        map,
+                    )
+                }
                 TripSpec::JustWalking { goal, .. } => trips.new_trip(
                     person.id,
                     start_time,
@@ -304,6 +685,24 @@ impl TripSpawner {
                     };
                     trips.new_trip(person.id, start_time, trip_start, TripMode::Bike, legs, map)
                 }
+                TripSpec::UsingCargoBike {
+                    bike,
+                    start_bldg,
+                    deliveries,
+                } => {
+                    let mut legs = Vec::new();
+                    let mut from = start_bldg;
+                    for b in deliveries.into_iter().chain(std::iter::once(start_bldg)) {
+                        legs.push(TripLeg::Walk(
+                            SidewalkSpot::bike_from_bike_rack(map.get_b(from).sidewalk(), map)
+                                .unwrap(),
+                        ));
+                        legs.push(TripLeg::Drive(bike, DrivingGoal::ParkNear(b)));
+                        legs.push(TripLeg::Walk(SidewalkSpot::building(b, map)));
+                        from = b;
+                    }
+                    trips.new_trip(person.id, start_time, trip_start, TripMode::Bike, legs, map)
+                }
                 TripSpec::UsingTransit {
                     route,
                     stop1,
@@ -325,6 +724,36 @@ impl TripSpawner {
                         map,
                     )
                 }
+                TripSpec::MultimodalChain { legs, mode, .. } => {
+                    trips.new_trip(person.id, start_time, trip_start, mode, legs, map)
+                }
+                TripSpec::UsingRideHail { goal, .. } => trips.new_trip(
+                    person.id,
+                    start_time,
+                    trip_start,
+                    TripMode::Drive,
+                    vec![TripLeg::RideHail(goal)],
+                    map,
+                ),
+                TripSpec::UsingDemandResponsiveTransit { goal, .. } => trips.new_trip(
+                    person.id,
+                    start_time,
+                    trip_start,
+                    TripMode::Transit,
+                    vec![TripLeg::DemandResponsive(goal)],
+                    map,
+                ),
+                TripSpec::UsingSchoolBus { bus, school, .. } => trips.new_trip(
+                    person.id,
+                    start_time,
+                    trip_start,
+                    TripMode::Transit,
+                    vec![
+                        TripLeg::SchoolBusDrive(bus, school),
+                        TripLeg::Walk(SidewalkSpot::building(school, map)),
+                    ],
+                    map,
+                ),
                 TripSpec::Remote { to, mode, .. } => trips.new_trip(
                     person.id,
                     start_time,
@@ -374,7 +803,7 @@ impl TripSpec {
                 use_vehicle,
                 ..
             } => {
-                let constraints = if use_vehicle.1 == VehicleType::Bike {
+                let constraints = if use_vehicle.1.is_bike() {
                     PathConstraints::Bike
                 } else {
                     PathConstraints::Car
@@ -387,7 +816,9 @@ impl TripSpec {
             }
             TripSpec::NoRoomToSpawn { .. } => None,
             // We don't know where the parked car will be
-            TripSpec::UsingParkedCar { .. } => None,
+            TripSpec::UsingParkedCar { .. }
+            | TripSpec::UsingDeliveryTruck { .. }
+            | TripSpec::MultimodalChain { .. } => None,
             TripSpec::JustWalking { start, goal, .. } => Some(PathRequest {
                 start: start.sidewalk_pos,
                 end: goal.sidewalk_pos,
@@ -400,12 +831,79 @@ impl TripSpec {
                     .sidewalk_pos,
                 constraints: PathConstraints::Pedestrian,
             }),
+            TripSpec::UsingCargoBike { start_bldg, .. } => {
+                let sidewalk = map.get_b(*start_bldg).sidewalk();
+                Some(PathRequest {
+                    start: SidewalkSpot::building(*start_bldg, map).sidewalk_pos,
+                    end: SidewalkSpot::bike_from_bike_rack(sidewalk, map)
+                        .unwrap()
+                        .sidewalk_pos,
+                    constraints: PathConstraints::Pedestrian,
+                })
+            }
             TripSpec::UsingTransit { start, stop1, .. } => Some(PathRequest {
                 start: start.sidewalk_pos,
                 end: SidewalkSpot::bus_stop(*stop1, map).sidewalk_pos,
                 constraints: PathConstraints::Pedestrian,
             }),
-            TripSpec::Remote { .. } => None,
+            TripSpec::UsingRideHail { .. }
+            | TripSpec::UsingDemandResponsiveTransit { .. }
+            | TripSpec::UsingSchoolBus { .. }
+            | TripSpec::Remote { .. } => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geom::Speed;
+
+    #[test]
+    fn test_invalid_chains() {
+        let map = Map::blank();
+        let mut trips = TripManager::new(false, 1);
+        let person = trips.random_person(Speed::meters_per_second(1.0), Vec::new());
+        let leg = |mode, origin, destination| ModeSpec {
+            mode,
+            origin,
+            destination,
+        };
+        let (b1, b2, b3) = (
+            ModeEndpoint::Bldg(BuildingID(1)),
+            ModeEndpoint::Bldg(BuildingID(2)),
+            ModeEndpoint::Bldg(BuildingID(3)),
+        );
+        let stop = ModeEndpoint::BusStop(BusStopID {
+            sidewalk: map_model::LaneID(0),
+            idx: 0,
+        });
+
+        let mut spawner = TripSpawner::new();
+        let mut check = |legs: Vec<ModeSpec>| {
+            spawner
+                .start_trip_using_multimodal_chain(person, Time::START_OF_DAY, legs, &map)
+                .is_err()
+        };
+        assert!(check(Vec::new()));
+        // Switching vehicles requires walking in between
+        assert!(check(vec![
+            leg(TripMode::Drive, b1, b2),
+            leg(TripMode::Bike, b2, b3)
+        ]));
+        // Legs have to connect
+        assert!(check(vec![
+            leg(TripMode::Walk, b1, b2),
+            leg(TripMode::Walk, b3, b1)
+        ]));
+        // Trips start at buildings
+        assert!(check(vec![leg(TripMode::Walk, stop, b1)]));
+        // Bus stops are only for getting on and off transit
+        assert!(check(vec![
+            leg(TripMode::Walk, b1, stop),
+            leg(TripMode::Walk, stop, b2)
+        ]));
+        // Somebody without a car can't drive
+        assert!(check(vec![leg(TripMode::Drive, b1, b2)]));
+    }
+}
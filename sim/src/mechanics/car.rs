@@ -1,11 +1,11 @@
 use crate::{
-    CarStatus, DistanceInterval, DrawCarInput, ParkingSpot, PersonID, Router, TimeInterval,
-    TransitSimState, TripID, Vehicle, VehicleType,
+    CarID, CarStatus, DistanceInterval, DrawCarInput, ParkingSpot, PersonID, Router,
+    SpeedOverrideSource, TimeInterval, TransitSimState, TripID, Vehicle, VehicleType,
 };
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{Map, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{LaneID, Map, Traversable};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct Car {
@@ -17,15 +17,55 @@ pub struct Car {
     pub trip_and_person: Option<(TripID, PersonID)>,
     pub started_at: Time,
     pub total_blocked_time: Duration,
+    pub parking_search_started: Option<Time>,
+    pub adaptive_routing: bool,
+    // Where the front of the car entered the current traversable. Only nonzero for the first one.
+    pub entered_at: Distance,
+    // When the front of the car entered the current traversable
+    pub entered_time: Time,
+    // Set when this car closely follows another through a turn, or leads such a car
+    pub platoon_id: Option<u32>,
 
     // In reverse order -- most recently left is first. The sum length of these must be >=
     // vehicle.length.
     pub last_steps: VecDeque<Traversable>,
 }
 
+// Only idling this recent is remembered interval by interval
+const IDLE_LOG_WINDOW: Duration = Duration::const_seconds(3600.0);
+
 impl Car {
+    // Call when the car stops being Queued or WaitingToAdvance.
+    pub fn unblocked(
+        &mut self,
+        blocked_since: Time,
+        now: Time,
+        idle_log: &mut BTreeMap<CarID, IdleLog>,
+    ) {
+        self.total_blocked_time += now - blocked_since;
+        if now > blocked_since {
+            idle_log
+                .entry(self.vehicle.id)
+                .or_insert_with(IdleLog::new)
+                .record(blocked_since, now);
+        }
+    }
+
+    // Call whenever the router may have started looking for parking.
+    pub fn update_parking_search(&mut self, now: Time) {
+        if self.parking_search_started.is_none() && self.router.is_searching_for_parking() {
+            self.parking_search_started = Some(now);
+        }
+    }
+
     // Assumes the current head of the path is the thing to cross.
-    pub fn crossing_state(&self, start_dist: Distance, start_time: Time, map: &Map) -> CarState {
+    pub fn crossing_state(
+        &self,
+        start_dist: Distance,
+        start_time: Time,
+        map: &Map,
+        speed_overrides: &BTreeMap<LaneID, BTreeMap<SpeedOverrideSource, Speed>>,
+    ) -> CarState {
         let dist_int = DistanceInterval::new_driving(
             start_dist,
             if self.router.last_step() {
@@ -34,7 +74,7 @@ impl Car {
                 self.router.head().length(map)
             },
         );
-        self.crossing_state_with_end_dist(dist_int, start_time, map)
+        self.crossing_state_with_end_dist(dist_int, start_time, map, speed_overrides)
     }
 
     pub fn crossing_state_with_end_dist(
@@ -42,12 +82,20 @@ impl Car {
         dist_int: DistanceInterval,
         start_time: Time,
         map: &Map,
+        speed_overrides: &BTreeMap<LaneID, BTreeMap<SpeedOverrideSource, Speed>>,
     ) -> CarState {
         let on = self.router.head();
         let mut speed = on.speed_limit(map);
         if let Some(s) = self.vehicle.max_speed {
             speed = speed.min(s);
         }
+        if let Traversable::Lane(l) = on {
+            if let Some(sources) = speed_overrides.get(&l) {
+                for s in sources.values() {
+                    speed = speed.min(*s);
+                }
+            }
+        }
         let dt = (dist_int.end - dist_int.start) / speed;
         CarState::Crossing(TimeInterval::new(start_time, start_time + dt), dist_int)
     }
@@ -196,6 +244,60 @@ impl Car {
     }
 }
 
+// When a vehicle was stuck in a queue or waiting to advance. Vehicles are reused between trips,
+// so this outlives any one Car.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct IdleLog {
+    total: Duration,
+    // Pruned to IDLE_LOG_WINDOW
+    recent: VecDeque<(Time, Time)>,
+    // The end of the latest interval pruned from recent
+    forgotten_until: Time,
+}
+
+impl IdleLog {
+    fn new() -> IdleLog {
+        IdleLog {
+            total: Duration::ZERO,
+            recent: VecDeque::new(),
+            forgotten_until: Time::START_OF_DAY,
+        }
+    }
+
+    fn record(&mut self, start: Time, end: Time) {
+        self.total += end - start;
+        self.recent.push_back((start, end));
+        while self
+            .recent
+            .front()
+            .map(|(_, t)| end - *t > IDLE_LOG_WINDOW)
+            .unwrap_or(false)
+        {
+            let (_, t) = self.recent.pop_front().unwrap();
+            self.forgotten_until = t;
+        }
+    }
+
+    // Idle time since some point, not counting right now. Intervals older than IDLE_LOG_WINDOW
+    // are only remembered in total, so this is None if start falls before them, unless it's the
+    // beginning of the day.
+    pub fn since(&self, start: Time) -> Option<Duration> {
+        if start == Time::START_OF_DAY {
+            return Some(self.total);
+        }
+        if start < self.forgotten_until {
+            return None;
+        }
+        let mut total = Duration::ZERO;
+        for (t1, t2) in &self.recent {
+            if *t2 > start {
+                total += *t2 - (*t1).max(start);
+            }
+        }
+        Some(total)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum CarState {
     Crossing(TimeInterval, DistanceInterval),
@@ -219,3 +321,26 @@ impl CarState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_log_prunes_old_intervals() {
+        let mut log = IdleLog::new();
+        let t = |secs: f64| Time::START_OF_DAY + Duration::seconds(secs);
+        log.record(t(0.0), t(10.0));
+        log.record(t(100.0), t(130.0));
+        log.record(t(5000.0), t(5020.0));
+
+        // Only the last interval is recent, but the whole day is still known
+        assert_eq!(log.recent.len(), 1);
+        assert_eq!(log.since(Time::START_OF_DAY), Some(Duration::seconds(60.0)));
+        assert_eq!(log.since(t(5010.0)), Some(Duration::seconds(10.0)));
+        // The pruned intervals ended at 130s. Any later start is still exact, but earlier ones
+        // would be missing time.
+        assert_eq!(log.since(t(130.0)), Some(Duration::seconds(20.0)));
+        assert_eq!(log.since(t(120.0)), None);
+    }
+}
@@ -1,21 +1,27 @@
-use crate::mechanics::car::{Car, CarState};
+use crate::mechanics::car::{Car, CarState, IdleLog};
 use crate::mechanics::Queue;
 use crate::{
     ActionAtEnd, AgentID, AgentProperties, CarID, Command, CreateCar, DistanceInterval,
-    DrawCarInput, Event, IntersectionSimState, ParkedCar, ParkingSimState, PersonID, Scheduler,
-    TimeInterval, TransitSimState, TripManager, UnzoomedAgent, Vehicle, WalkingSimState,
-    FOLLOWING_DISTANCE,
+    DrawCarInput, Event, IncidentID, IntersectionSimState, ParkedCar, ParkingSimState, PersonID,
+    Scheduler, TimeInterval, TransitSimState, TripManager, UnzoomedAgent, Vehicle, VehicleType,
+    WalkingSimState, FOLLOWING_DISTANCE, MIN_CAR_LENGTH,
+};
+use abstutil::{deserialize_btreemap, retain_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{
+    BuildingID, LaneID, Map, Path, PathRequest, PathStep, Position, Traversable, TurnID,
 };
-use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{LaneID, Map, Path, PathStep, Traversable};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
 const TIME_TO_UNPARK: Duration = Duration::const_seconds(10.0);
 const TIME_TO_PARK: Duration = Duration::const_seconds(15.0);
 const TIME_TO_WAIT_AT_STOP: Duration = Duration::const_seconds(10.0);
 
+const DENSITY_PROFILE_SEGMENTS: usize = 20;
+// Cars with adaptive routing avoid lanes more full than this
+const ADAPTIVE_ROUTING_VC_THRESHOLD: f64 = 0.9;
+
 // TODO Do something else.
 pub(crate) const BLIND_RETRY_TO_CREEP_FORWARDS: Duration = Duration::const_seconds(0.1);
 pub(crate) const BLIND_RETRY_TO_REACH_END_DIST: Duration = Duration::const_seconds(5.0);
@@ -35,15 +41,71 @@ pub struct DrivingSimState {
     events: Vec<Event>,
 
     recalc_lanechanging: bool,
+    // If set, a car starting a turn this close behind the car ahead of it joins that car's
+    // platoon.
+    platoon_gap: Option<Distance>,
+    next_platoon_id: u32,
+    // Temporarily lowered speed limits, like school zones. Each lane uses the lowest one.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    speed_overrides: BTreeMap<LaneID, BTreeMap<SpeedOverrideSource, Speed>>,
+    // Odometer for every vehicle that's ever driven, across all of its trips
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    cumulative_distance: BTreeMap<CarID, Distance>,
+    // Lanes whose queue has backed up to the start. Only tracked if detect_spillback is set.
+    detect_spillback: bool,
+    spillback: BTreeSet<LaneID>,
+    // Replanning more than this many times within the window probably means the car is
+    // flip-flopping between routes.
+    routing_oscillation: (usize, Duration),
+    // When each car replanned within the last routing_oscillation window
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    recent_replans: BTreeMap<CarID, VecDeque<Time>>,
+    // When each car was stuck in a queue or waiting to advance, not counting right now
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    idle_log: BTreeMap<CarID, IdleLog>,
+}
+
+// What's lowering the speed limit on a lane
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpeedOverrideSource {
+    // Indexes into Sim's school zones
+    SchoolZone(usize),
+    Incident(IncidentID),
 }
 
 impl DrivingSimState {
-    pub fn new(map: &Map, recalc_lanechanging: bool) -> DrivingSimState {
+    pub fn new(
+        map: &Map,
+        recalc_lanechanging: bool,
+        detect_spillback: bool,
+        routing_oscillation: (usize, Duration),
+    ) -> DrivingSimState {
         let mut sim = DrivingSimState {
             cars: BTreeMap::new(),
             queues: BTreeMap::new(),
             events: Vec::new(),
             recalc_lanechanging,
+            platoon_gap: None,
+            next_platoon_id: 0,
+            speed_overrides: BTreeMap::new(),
+            cumulative_distance: BTreeMap::new(),
+            detect_spillback,
+            spillback: BTreeSet::new(),
+            routing_oscillation,
+            recent_replans: BTreeMap::new(),
+            idle_log: BTreeMap::new(),
         };
 
         for l in map.all_lanes() {
@@ -92,6 +154,11 @@ impl DrivingSimState {
                 last_steps: VecDeque::new(),
                 started_at: now,
                 total_blocked_time: Duration::ZERO,
+                parking_search_started: None,
+                adaptive_routing: params.adaptive_routing,
+                entered_at: params.start_dist,
+                entered_time: now,
+                platoon_id: None,
                 trip_and_person: params.trip_and_person,
             };
             if let Some(p) = params.maybe_parked_car {
@@ -111,7 +178,8 @@ impl DrivingSimState {
                         car.trip_and_person,
                         &mut self.events,
                     ) {
-                        None | Some(ActionAtEnd::GotoLaneEnd) => {}
+                        // Stopping at the curb right away is handled once the car is Queued.
+                        None | Some(ActionAtEnd::GotoLaneEnd) | Some(ActionAtEnd::StopAtCurb) => {}
                         x => {
                             panic!(
                                 "Car with one-step route {:?} had unexpected result from \
@@ -120,6 +188,7 @@ impl DrivingSimState {
                             );
                         }
                     }
+                    car.update_parking_search(now);
                     // We might've decided to go park somewhere farther, so get_end_dist no longer
                     // makes sense.
                     if car.router.last_step() && params.start_dist > car.router.get_end_dist() {
@@ -131,7 +200,7 @@ impl DrivingSimState {
                     }
                 }
 
-                car.state = car.crossing_state(params.start_dist, now, map);
+                car.state = car.crossing_state(params.start_dist, now, map, &self.speed_overrides);
             }
             scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             {
@@ -203,6 +272,9 @@ impl DrivingSimState {
                 scheduler,
             );
             self.cars.insert(id, car);
+            if self.detect_spillback {
+                self.check_spillback(id, now, map);
+            }
         }
 
         if need_distances {
@@ -223,6 +295,8 @@ impl DrivingSimState {
             ) {
                 self.cars.insert(id, car);
             } else {
+                let dist = dists[idx].1 - car.entered_at;
+                self.record_distance(&car, car.router.head(), dist, now, map);
                 self.delete_car(&mut car, dists, idx, now, map, scheduler, intersections);
             }
         }
@@ -253,7 +327,12 @@ impl DrivingSimState {
                     // Want to re-run, but no urgency about it happening immediately.
                     car.state = CarState::WaitingToAdvance { blocked_since: now };
                     if self.recalc_lanechanging {
-                        car.router.opportunistically_lanechange(&self.queues, map);
+                        if let Some((from, to)) =
+                            car.router.opportunistically_lanechange(&self.queues, map)
+                        {
+                            self.events
+                                .push(Event::LaneChanged(car.vehicle.id, from, to));
+                        }
                     }
                     scheduler.push(now, Command::UpdateCar(car.vehicle.id));
                 }
@@ -272,15 +351,16 @@ impl DrivingSimState {
                         car.trip_and_person,
                         &mut self.events,
                     );
+                    car.update_parking_search(now);
                 }
-                car.state = car.crossing_state(front, now, map);
+                car.state = car.crossing_state(front, now, map, &self.speed_overrides);
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             }
             CarState::Idling(dist, _) => {
-                car.router = transit.bus_departed_from_stop(car.vehicle.id);
+                car.router = transit.bus_departed_from_stop(car.vehicle.id, scheduler);
                 self.events
                     .push(Event::PathAmended(car.router.get_path().clone()));
-                car.state = car.crossing_state(dist, now, map);
+                car.state = car.crossing_state(dist, now, map, &self.speed_overrides);
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
 
                 // Update our follower, so they know we stopped idling.
@@ -297,12 +377,14 @@ impl DrivingSimState {
                             // If they're on their last step, they might be ending early and not
                             // right behind us.
                             if !follower.router.last_step() {
-                                follower.total_blocked_time += now - blocked_since;
+                                follower.unblocked(blocked_since, now, &mut self.idle_log);
                                 follower.state = follower.crossing_state(
                                     // Since the follower was Queued, this must be where they are.
-                                    dist - car.vehicle.length - FOLLOWING_DISTANCE,
+                                    dist - car.vehicle.length
+                                        - queue.following_distance(follower.vehicle.id),
                                     now,
                                     map,
+                                    &self.speed_overrides,
                                 );
                                 scheduler.update(
                                     follower.state.get_end_time(),
@@ -333,6 +415,11 @@ impl DrivingSimState {
                     if let Some(s) = car.vehicle.max_speed {
                         speed = speed.min(s);
                     }
+                    // The platoon decides how much room to reserve on the next lane.
+                    let old_platoon = car.platoon_id;
+                    let platoon_leader = self.platoon_leader(t, now, map);
+                    car.platoon_id = platoon_leader
+                        .map(|c| self.cars[&c].platoon_id.unwrap_or(self.next_platoon_id));
                     if !intersections.maybe_start_turn(
                         AgentID::Car(car.vehicle.id),
                         t,
@@ -342,9 +429,23 @@ impl DrivingSimState {
                         scheduler,
                         Some((&car, &self.cars, &mut self.queues)),
                     ) {
+                        car.platoon_id = old_platoon;
                         // Don't schedule a retry here.
                         return false;
                     }
+                    if let Some(leader) = platoon_leader {
+                        let leader = self.cars.get_mut(&leader).unwrap();
+                        if leader.platoon_id.is_none() {
+                            leader.platoon_id = car.platoon_id;
+                            self.next_platoon_id += 1;
+                        }
+                    }
+                    if !map
+                        .get_t(t)
+                        .legal_for(car.vehicle.vehicle_type.to_constraints(), map)
+                    {
+                        self.events.push(Event::TurnViolation(car.vehicle.id, t));
+                    }
                 }
 
                 {
@@ -356,6 +457,10 @@ impl DrivingSimState {
                 // We do NOT need to update the follower. If they were Queued, they'll remain that
                 // way, until laggy_head is None.
 
+                self.record_distance(car, from, from.length(map) - car.entered_at, now, map);
+                car.entered_at = Distance::ZERO;
+                car.entered_time = now;
+
                 let last_step = car.router.advance(
                     &car.vehicle,
                     parking,
@@ -363,8 +468,11 @@ impl DrivingSimState {
                     car.trip_and_person,
                     &mut self.events,
                 );
-                car.total_blocked_time += now - blocked_since;
-                car.state = car.crossing_state(Distance::ZERO, now, map);
+                car.unblocked(blocked_since, now, &mut self.idle_log);
+                if car.adaptive_routing {
+                    self.maybe_replan(car, now, map);
+                }
+                car.state = car.crossing_state(Distance::ZERO, now, map, &self.speed_overrides);
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                 self.events.push(Event::AgentEntersTraversable(
                     AgentID::Car(car.vehicle.id),
@@ -386,6 +494,7 @@ impl DrivingSimState {
                         ),
                         now,
                         map,
+                        &self.speed_overrides,
                     )
                     .get_end_time(),
                     Command::UpdateLaggyHead(car.vehicle.id),
@@ -424,16 +533,18 @@ impl DrivingSimState {
             | CarState::Idling(_, _)
             | CarState::WaitingToAdvance { .. } => unreachable!(),
             CarState::Queued { blocked_since } => {
-                match car.router.maybe_handle_end(
+                let action = car.router.maybe_handle_end(
                     our_dist,
                     &car.vehicle,
                     parking,
                     map,
                     car.trip_and_person,
                     &mut self.events,
-                ) {
+                );
+                car.update_parking_search(now);
+                match action {
                     Some(ActionAtEnd::VanishAtBorder(i)) => {
-                        car.total_blocked_time += now - blocked_since;
+                        car.unblocked(blocked_since, now, &mut self.idle_log);
                         trips.car_or_bike_reached_border(
                             now,
                             car.vehicle.id,
@@ -446,7 +557,7 @@ impl DrivingSimState {
                         false
                     }
                     Some(ActionAtEnd::GiveUpOnParking) => {
-                        car.total_blocked_time += now - blocked_since;
+                        car.unblocked(blocked_since, now, &mut self.idle_log);
                         trips.abort_trip(
                             now,
                             car.trip_and_person.unwrap().0,
@@ -460,7 +571,7 @@ impl DrivingSimState {
                         false
                     }
                     Some(ActionAtEnd::StartParking(spot)) => {
-                        car.total_blocked_time += now - blocked_since;
+                        car.unblocked(blocked_since, now, &mut self.idle_log);
                         car.state = CarState::Parking(
                             our_dist,
                             spot,
@@ -475,14 +586,14 @@ impl DrivingSimState {
                         true
                     }
                     Some(ActionAtEnd::GotoLaneEnd) => {
-                        car.total_blocked_time += now - blocked_since;
-                        car.state = car.crossing_state(our_dist, now, map);
+                        car.unblocked(blocked_since, now, &mut self.idle_log);
+                        car.state = car.crossing_state(our_dist, now, map, &self.speed_overrides);
                         scheduler
                             .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                         true
                     }
                     Some(ActionAtEnd::StopBiking(bike_rack)) => {
-                        car.total_blocked_time += now - blocked_since;
+                        car.unblocked(blocked_since, now, &mut self.idle_log);
                         trips.bike_reached_end(
                             now,
                             car.vehicle.id,
@@ -502,7 +613,7 @@ impl DrivingSimState {
                             scheduler,
                             map,
                         );
-                        car.total_blocked_time += now - blocked_since;
+                        car.unblocked(blocked_since, now, &mut self.idle_log);
                         car.state = CarState::Idling(
                             our_dist,
                             TimeInterval::new(now, now + TIME_TO_WAIT_AT_STOP),
@@ -511,6 +622,42 @@ impl DrivingSimState {
                             .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                         true
                     }
+                    Some(ActionAtEnd::StopAtCurb) => {
+                        car.unblocked(blocked_since, now, &mut self.idle_log);
+                        let pos = Position::new(car.router.head().as_lane(), our_dist);
+                        let wait = if car.vehicle.vehicle_type == VehicleType::SchoolBus {
+                            transit.school_bus_reached_curb(
+                                now,
+                                car.vehicle.id,
+                                pos,
+                                trips,
+                                scheduler,
+                                map,
+                            )
+                        } else {
+                            transit.ride_hail_reached_curb(
+                                now,
+                                car.vehicle.id,
+                                pos,
+                                trips,
+                                parking,
+                                scheduler,
+                                map,
+                            )
+                        };
+                        if wait {
+                            // Wait for riders to get in
+                            car.state = CarState::Idling(
+                                our_dist,
+                                TimeInterval::new(now, now + TIME_TO_WAIT_AT_STOP),
+                            );
+                            scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            true
+                        } else {
+                            false
+                        }
+                    }
                     None => {
                         scheduler.push(
                             now + BLIND_RETRY_TO_REACH_END_DIST,
@@ -521,7 +668,12 @@ impl DrivingSimState {
                         // to be slower otherwise. :(
                         /*
                         // If this car wasn't blocked at all, when would it reach its goal?
-                        let ideal_end_time = match car.crossing_state(our_dist, now, map) {
+                        let ideal_end_time = match car.crossing_state(
+                            our_dist,
+                            now,
+                            map,
+                            &self.speed_overrides,
+                        ) {
                             CarState::Crossing(time_int, _) => time_int.end,
                             _ => unreachable!(),
                         };
@@ -540,7 +692,7 @@ impl DrivingSimState {
                     }
                 }
             }
-            CarState::Parking(_, spot, _) => {
+            CarState::Parking(_, spot, ref time_int) => {
                 parking.add_parked_car(ParkedCar {
                     vehicle: car.vehicle.clone(),
                     spot,
@@ -550,6 +702,8 @@ impl DrivingSimState {
                     car.vehicle.id,
                     spot,
                     car.total_blocked_time,
+                    // The search ends once the car starts maneuvering into the spot.
+                    car.parking_search_started.map(|t| time_int.start - t),
                     map,
                     parking,
                     scheduler,
@@ -637,8 +791,9 @@ impl DrivingSimState {
             match follower.state {
                 CarState::Queued { blocked_since } => {
                     // Prevent them from jumping forwards.
-                    follower.total_blocked_time += now - blocked_since;
-                    follower.state = follower.crossing_state(follower_dist, now, map);
+                    follower.unblocked(blocked_since, now, &mut self.idle_log);
+                    follower.state =
+                        follower.crossing_state(follower_dist, now, map, &self.speed_overrides);
                     scheduler.update(
                         follower.state.get_end_time(),
                         Command::UpdateCar(follower_id),
@@ -648,7 +803,8 @@ impl DrivingSimState {
                     // If the follower was still Crossing, they might not've been blocked
                     // by leader yet. In that case, recalculating their Crossing state is a
                     // no-op.
-                    follower.state = follower.crossing_state(follower_dist, now, map);
+                    follower.state =
+                        follower.crossing_state(follower_dist, now, map, &self.speed_overrides);
                     scheduler.update(
                         follower.state.get_end_time(),
                         Command::UpdateCar(follower_id),
@@ -717,6 +873,7 @@ impl DrivingSimState {
                     ),
                     now,
                     map,
+                    &self.speed_overrides,
                 )
                 .get_end_time();
             // Sometimes due to rounding, retry_at will be exactly time, but we really need to
@@ -783,9 +940,16 @@ impl DrivingSimState {
                                 // immediately promote them to WaitingToAdvance.
                                 follower.state = CarState::WaitingToAdvance { blocked_since };
                                 if self.recalc_lanechanging {
-                                    follower
+                                    if let Some((from, to)) = follower
                                         .router
-                                        .opportunistically_lanechange(&self.queues, map);
+                                        .opportunistically_lanechange(&self.queues, map)
+                                    {
+                                        self.events.push(Event::LaneChanged(
+                                            follower.vehicle.id,
+                                            from,
+                                            to,
+                                        ));
+                                    }
                                 }
                                 scheduler.push(now, Command::UpdateCar(follower.vehicle.id));
                             }
@@ -947,6 +1111,358 @@ impl DrivingSimState {
         car.router.get_path().trace(map, front, dist_ahead)
     }
 
+    // Cars already crossing the lane keep their old speed; the override applies to cars entering
+    // the lane afterwards. None removes the override from this source, leaving any others.
+    pub fn lane_speed_override(
+        &mut self,
+        lane: LaneID,
+        source: SpeedOverrideSource,
+        speed: Option<Speed>,
+    ) {
+        if let Some(s) = speed {
+            self.speed_overrides
+                .entry(lane)
+                .or_insert_with(BTreeMap::new)
+                .insert(source, s);
+        } else if let Some(sources) = self.speed_overrides.get_mut(&lane) {
+            sources.remove(&source);
+            if sources.is_empty() {
+                self.speed_overrides.remove(&lane);
+            }
+        }
+    }
+
+    pub fn enable_platooning(&mut self, gap: Distance) {
+        assert!(gap >= FOLLOWING_DISTANCE);
+        self.platoon_gap = Some(gap);
+    }
+
+    // Returns the cars travelling together with this one on its current queue, from front to
+    // back. Without platooning enabled, or for a car on its own, that's just the car.
+    pub fn get_platoon(&self, id: CarID) -> Vec<CarID> {
+        let car = if let Some(car) = self.cars.get(&id) {
+            car
+        } else {
+            return Vec::new();
+        };
+        let platoon = if let Some(p) = car.platoon_id {
+            p
+        } else {
+            return vec![id];
+        };
+
+        let cars = &self.queues[&car.router.head()].cars;
+        let idx = cars.iter().position(|c| *c == id).unwrap();
+        let same_platoon = |c: &CarID| self.cars[c].platoon_id == Some(platoon);
+        let first = cars
+            .iter()
+            .take(idx)
+            .rposition(|c| !same_platoon(c))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let last = cars
+            .iter()
+            .skip(idx)
+            .position(|c| !same_platoon(c))
+            .map(|i| idx + i)
+            .unwrap_or(cars.len());
+        cars.range(first..last).cloned().collect()
+    }
+
+    // A car about to start this turn joins the platoon of the car ahead of it, if that car is
+    // within platoon_gap. The car ahead is either still in the turn or already on the next lane.
+    fn platoon_leader(&self, turn: TurnID, now: Time, map: &Map) -> Option<CarID> {
+        let gap = self.platoon_gap?;
+        let (leader, back) = if let Some((c, front)) = self.queues[&Traversable::Turn(turn)]
+            .get_car_positions(now, &self.cars, &self.queues)
+            .last()
+        {
+            (*c, *front - self.cars[c].vehicle.length)
+        } else {
+            let (c, front) = *self.queues[&Traversable::Lane(turn.dst)]
+                .get_car_positions(now, &self.cars, &self.queues)
+                .last()?;
+            (
+                c,
+                front + map.get_t(turn).geom.length() - self.cars[&c].vehicle.length,
+            )
+        };
+        if back <= gap {
+            Some(leader)
+        } else {
+            None
+        }
+    }
+
+    // Splits a lane into equal segments and returns (percent along the lane of the segment's
+    // middle, cars per meter in that segment). Cars are counted by where their front is.
+    pub fn get_density_wave_profile(&self, lane: LaneID, now: Time, map: &Map) -> Vec<(f64, f64)> {
+        let len = map.get_l(lane).length();
+        let mut counts = vec![0; DENSITY_PROFILE_SEGMENTS];
+        if let Some(queue) = self.queues.get(&Traversable::Lane(lane)) {
+            for (_, dist) in queue.get_car_positions(now, &self.cars, &self.queues) {
+                let idx = ((dist / len) * (DENSITY_PROFILE_SEGMENTS as f64)) as usize;
+                counts[idx.min(DENSITY_PROFILE_SEGMENTS - 1)] += 1;
+            }
+        }
+        let segment_len = len.inner_meters() / (DENSITY_PROFILE_SEGMENTS as f64);
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(idx, cnt)| {
+                (
+                    ((idx as f64) + 0.5) / (DENSITY_PROFILE_SEGMENTS as f64),
+                    (cnt as f64) / segment_len,
+                )
+            })
+            .collect()
+    }
+
+    // Called when a car enters a lane. If the next lane is congested, look for a path around it.
+    fn maybe_replan(&mut self, car: &mut Car, now: Time, map: &Map) {
+        let (current, next) = {
+            let steps = car.router.get_path().get_steps();
+            if steps.len() < 3 || car.router.get_path().last_step() == steps[2] {
+                return;
+            }
+            match (steps[0], steps[2]) {
+                (PathStep::Lane(l1), PathStep::Lane(l2)) => (l1, l2),
+                _ => {
+                    return;
+                }
+            }
+        };
+        let queue = &self.queues[&Traversable::Lane(next)];
+        if queue.reserved_length / queue.geom_len <= ADAPTIVE_ROUTING_VC_THRESHOLD {
+            return;
+        }
+
+        let end = Position::new(
+            car.router.get_path().last_step().as_lane(),
+            car.router.get_path().end_dist(),
+        );
+        let mut avoid = HashSet::new();
+        avoid.insert(next);
+        if let Some(path) = map.pathfind_avoiding_lanes(
+            PathRequest {
+                start: Position::new(current, Distance::ZERO),
+                end,
+                constraints: car.vehicle.vehicle_type.to_constraints(),
+            },
+            &avoid,
+        ) {
+            car.router.reroute(path);
+            self.events.push(Event::CarReplanned(car.vehicle.id, next));
+
+            let (threshold, window) = self.routing_oscillation;
+            // Forget about cars that haven't replanned recently
+            for times in self.recent_replans.values_mut() {
+                while times.front().map(|t| now - *t > window).unwrap_or(false) {
+                    times.pop_front();
+                }
+            }
+            retain_btreemap(&mut self.recent_replans, |_, times| !times.is_empty());
+            let times = self
+                .recent_replans
+                .entry(car.vehicle.id)
+                .or_insert_with(VecDeque::new);
+            times.push_back(now);
+            // Only once each time the car crosses the threshold
+            if times.len() == threshold + 1 {
+                self.events
+                    .push(Event::RoutingOscillation(AgentID::Car(car.vehicle.id)));
+            }
+        }
+    }
+
+    // Where's the back of the last stopped car in the line at the end of this lane? If nobody's
+    // stopped, that's the full length of the lane.
+    pub fn queue_tail_position(&self, lane: LaneID, now: Time) -> Distance {
+        let queue = &self.queues[&Traversable::Lane(lane)];
+        let mut tail = queue.geom_len;
+        for (id, dist) in queue.get_car_positions(now, &self.cars, &self.queues) {
+            let car = &self.cars[&id];
+            match car.state {
+                CarState::Queued { .. } | CarState::WaitingToAdvance { .. } => {
+                    tail = dist - car.vehicle.length;
+                }
+                _ => break,
+            }
+        }
+        tail
+    }
+
+    // Call when a car might've just stopped. Only detects the start of each spillback, and
+    // notices it's cleared up the next time somebody on the lane stops.
+    fn check_spillback(&mut self, id: CarID, now: Time, map: &Map) {
+        let car = &self.cars[&id];
+        let lane = match (&car.state, car.router.head()) {
+            (CarState::Queued { .. }, Traversable::Lane(l)) => l,
+            _ => {
+                return;
+            }
+        };
+        // No room for anybody else to enter the lane
+        if self.queue_tail_position(lane, now) < MIN_CAR_LENGTH + FOLLOWING_DISTANCE {
+            if self.spillback.insert(lane) {
+                self.events
+                    .push(Event::QueueSpillback(lane, map.get_l(lane).src_i));
+            }
+        } else {
+            self.spillback.remove(&lane);
+        }
+    }
+
+    // Called when the front of the car leaves a traversable, or the car vanishes partway along it
+    fn record_distance(
+        &mut self,
+        car: &Car,
+        on: Traversable,
+        dist: Distance,
+        now: Time,
+        map: &Map,
+    ) {
+        *self
+            .cumulative_distance
+            .entry(car.vehicle.id)
+            .or_insert(Distance::ZERO) += dist;
+        if let Traversable::Lane(l) = on {
+            self.events.push(Event::VehicleLeftRoad(
+                car.vehicle.id,
+                car.trip_and_person.map(|(t, _)| t),
+                map.get_l(l).parent,
+                dist,
+                now - car.entered_time,
+            ));
+        }
+    }
+
+    // The front of every car on the lane
+    pub fn get_cars_on_lane(&self, lane: LaneID, now: Time) -> Vec<(CarID, Distance)> {
+        if let Some(queue) = self.queues.get(&Traversable::Lane(lane)) {
+            queue.get_car_positions(now, &self.cars, &self.queues)
+        } else {
+            Vec::new()
+        }
+    }
+
+    // How fast is each car on this lane moving right now? Cars that aren't crossing the lane are
+    // stopped.
+    pub fn get_speeds_on_lane(&self, lane: LaneID) -> Vec<Speed> {
+        let queue = if let Some(q) = self.queues.get(&Traversable::Lane(lane)) {
+            q
+        } else {
+            return Vec::new();
+        };
+        queue
+            .cars
+            .iter()
+            .map(|id| match self.cars[id].state {
+                CarState::Crossing(ref time_int, ref dist_int) => {
+                    let dt = time_int.end - time_int.start;
+                    if dt == Duration::ZERO {
+                        Speed::ZERO
+                    } else {
+                        Speed::meters_per_second(
+                            (dist_int.end - dist_int.start).inner_meters() / dt.inner_seconds(),
+                        )
+                    }
+                }
+                _ => Speed::ZERO,
+            })
+            .collect()
+    }
+
+    // How far has this vehicle driven, over all of its trips? Progress along the traversable it's
+    // currently on isn't counted yet.
+    pub fn get_cumulative_distance(&self, car: CarID) -> Option<Distance> {
+        if let Some(d) = self.cumulative_distance.get(&car) {
+            Some(*d)
+        } else if self.cars.contains_key(&car) {
+            Some(Distance::ZERO)
+        } else {
+            None
+        }
+    }
+
+    // Includes vehicles that aren't on the map anymore
+    pub fn cumulative_distance_by_type(&self) -> BTreeMap<VehicleType, Distance> {
+        let mut results = BTreeMap::new();
+        for (car, dist) in &self.cumulative_distance {
+            *results.entry(car.1).or_insert(Distance::ZERO) += *dist;
+        }
+        results
+    }
+
+    // How long has this car been stopped in traffic (but not parked) over the last window of time?
+    // None if the window reaches back past what the car's idle log remembers, unless it covers the
+    // whole day.
+    pub fn get_idle_time(&self, car: CarID, now: Time, window: Duration) -> Option<Duration> {
+        let start = if now - Time::START_OF_DAY > window {
+            now - window
+        } else {
+            Time::START_OF_DAY
+        };
+        let mut total = match self.idle_log.get(&car) {
+            Some(log) => log.since(start)?,
+            None => Duration::ZERO,
+        };
+        if let Some(c) = self.cars.get(&car) {
+            match c.state {
+                CarState::Queued { blocked_since }
+                | CarState::WaitingToAdvance { blocked_since } => {
+                    total += now - blocked_since.max(start);
+                }
+                _ => {}
+            }
+        }
+        Some(total)
+    }
+
+    // The average time each vehicle that's ever driven has spent stopped in traffic
+    pub fn mean_idle_time_by_type(&self, now: Time) -> BTreeMap<VehicleType, Duration> {
+        let window = now - Time::START_OF_DAY;
+        let mut totals: BTreeMap<VehicleType, (Duration, usize)> = BTreeMap::new();
+        for car in self.cumulative_distance.keys() {
+            let entry = totals.entry(car.1).or_insert((Duration::ZERO, 0));
+            // The whole day is always known
+            entry.0 += self.get_idle_time(*car, now, window).unwrap();
+            entry.1 += 1;
+        }
+        totals
+            .into_iter()
+            .map(|(vt, (total, cnt))| (vt, total / (cnt as f64)))
+            .collect()
+    }
+
+    pub fn total_cumulative_distance(&self) -> Distance {
+        self.cumulative_distance
+            .values()
+            .fold(Distance::ZERO, |sum, d| sum + *d)
+    }
+
+    // The building each car searching for parking is trying to park near
+    pub fn parking_search_targets(&self) -> Vec<BuildingID> {
+        self.cars
+            .values()
+            .filter(|car| car.router.is_searching_for_parking())
+            .filter_map(|car| car.router.parking_target())
+            .collect()
+    }
+
+    // How many cars on the turn's source lane are headed for this turn next?
+    pub fn turn_queue_length(&self, turn: TurnID) -> usize {
+        if let Some(queue) = self.queues.get(&Traversable::Lane(turn.src)) {
+            queue
+                .cars
+                .iter()
+                .filter(|c| self.cars[*c].router.maybe_next() == Some(Traversable::Turn(turn)))
+                .count()
+        } else {
+            0
+        }
+    }
+
     pub fn percent_along_route(&self, id: CarID) -> f64 {
         self.cars[&id].router.get_path().percent_dist_crossed()
     }
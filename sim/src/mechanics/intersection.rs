@@ -13,6 +13,36 @@ use std::collections::{BTreeMap, BTreeSet, HashSet};
 const WAIT_AT_STOP_SIGN: Duration = Duration::const_seconds(0.5);
 const WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL: Duration = Duration::const_seconds(0.2);
 
+// Level of service at a signalized intersection, graded by the average control delay per vehicle,
+// following the Highway Capacity Manual.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IntersectionLOS {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+}
+
+impl IntersectionLOS {
+    pub fn from_delay(delay: Duration) -> IntersectionLOS {
+        if delay <= Duration::seconds(10.0) {
+            IntersectionLOS::A
+        } else if delay <= Duration::seconds(20.0) {
+            IntersectionLOS::B
+        } else if delay <= Duration::seconds(35.0) {
+            IntersectionLOS::C
+        } else if delay <= Duration::seconds(55.0) {
+            IntersectionLOS::D
+        } else if delay <= Duration::seconds(80.0) {
+            IntersectionLOS::E
+        } else {
+            IntersectionLOS::F
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct IntersectionSimState {
     state: BTreeMap<IntersectionID, State>,
@@ -22,9 +52,25 @@ pub struct IntersectionSimState {
     // (x, y) means x is blocked by y. It's a many-to-many relationship. TODO Better data
     // structure.
     blocked_by: BTreeSet<(CarID, CarID)>,
+    // Only for traffic signals. A running total, so this doesn't grow over the day.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    turn_delays: BTreeMap<TurnID, TurnDelays>,
     events: Vec<Event>,
 }
 
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct TurnDelays {
+    // How many agents have started this turn
+    count: usize,
+    // How many of them started it as soon as they asked
+    nonstop: usize,
+    // Between asking and starting the turn, summed over all agents
+    total: Duration,
+}
+
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 struct State {
     id: IntersectionID,
@@ -61,6 +107,7 @@ impl IntersectionSimState {
             dont_block_the_box,
             break_turn_conflict_cycles,
             blocked_by: BTreeSet::new(),
+            turn_delays: BTreeMap::new(),
             events: Vec::new(),
         };
         for i in map.all_intersections() {
@@ -101,6 +148,12 @@ impl IntersectionSimState {
         if map.get_t(turn).turn_type != TurnType::SharedSidewalkCorner {
             self.wakeup_waiting(now, turn.parent, scheduler, map);
         }
+        self.events.push(Event::AgentFinishedTurn(agent, turn));
+        if let AgentID::Pedestrian(ped) = agent {
+            if map.get_t(turn).turn_type == TurnType::Crosswalk {
+                self.events.push(Event::PedestrianCrossed(ped, turn.src));
+            }
+        }
         if self.break_turn_conflict_cycles {
             if let AgentID::Car(car) = agent {
                 retain_btreeset(&mut self.blocked_by, |(_, c)| *c != car);
@@ -239,16 +292,32 @@ impl IntersectionSimState {
             if state.current_phase == signal.phases.len() {
                 state.current_phase = 0;
             }
+            state.phase_ends_at = now
+                + signal.phases[state.current_phase]
+                    .phase_type
+                    .simple_duration();
+        } else {
+            let (phase, remaining) = initial_phase(signal);
+            state.current_phase = phase;
+            state.phase_ends_at = now + remaining;
         }
-
-        state.phase_ends_at = now
-            + signal.phases[state.current_phase]
-                .phase_type
-                .simple_duration();
         scheduler.push(state.phase_ends_at, Command::UpdateIntersection(id));
         self.wakeup_waiting(now, id, scheduler, map);
     }
 
+    // After a signal's timing is edited, start its cycle over. Since live map edits aren't
+    // supported, this only works before the simulation starts.
+    pub fn restart_signal(&mut self, id: IntersectionID, map: &Map, scheduler: &mut Scheduler) {
+        if self.use_freeform_policy_everywhere {
+            return;
+        }
+        let state = self.state.get_mut(&id).unwrap();
+        let (phase, remaining) = initial_phase(map.get_traffic_signal(id));
+        state.current_phase = phase;
+        state.phase_ends_at = Time::START_OF_DAY + remaining;
+        scheduler.update(state.phase_ends_at, Command::UpdateIntersection(id));
+    }
+
     // For cars: The head car calls this when they're at the end of the lane WaitingToAdvance. If
     // this returns true, then the head car MUST actually start this turn.
     // For peds: Likewise -- only called when the ped is at the start of the turn. They must
@@ -330,14 +399,41 @@ impl IntersectionSimState {
         // TODO For now, we're only interested in signals, and there's too much raw data to store
         // for stop signs too.
         let state = self.state.get_mut(&turn.parent).unwrap();
-        let delay = now - state.waiting.remove(&req).unwrap();
+        let requested_at = state.waiting.remove(&req).unwrap();
+        let delay = now - requested_at;
         if map.maybe_get_traffic_signal(state.id).is_some() {
+            let delays = self.turn_delays.entry(turn).or_insert_with(|| TurnDelays {
+                count: 0,
+                nonstop: 0,
+                total: Duration::ZERO,
+            });
+            delays.count += 1;
+            delays.total += delay;
+            if delay == Duration::ZERO {
+                delays.nonstop += 1;
+            }
             self.events.push(Event::IntersectionDelayMeasured(
                 turn.parent,
                 delay,
                 TripMode::from_agent(agent),
             ));
         }
+        let this_turn = map.get_t(turn);
+        for other in state.waiting.keys() {
+            let other_turn = map.get_t(other.turn);
+            if (this_turn.turn_type == TurnType::Crosswalk)
+                != (other_turn.turn_type == TurnType::Crosswalk)
+                && this_turn.conflicts_with(other_turn)
+            {
+                let crosswalk = if this_turn.turn_type == TurnType::Crosswalk {
+                    turn
+                } else {
+                    other.turn
+                };
+                self.events
+                    .push(Event::CrosswalkConflict(crosswalk, agent, other.agent));
+            }
+        }
         state.accepted.insert(req);
         if self.break_turn_conflict_cycles {
             if let AgentID::Car(car) = agent {
@@ -400,6 +496,61 @@ impl IntersectionSimState {
         candidates
     }
 
+    // How long has the longest-waiting pedestrian been waiting to cross from this sidewalk?
+    pub fn pedestrian_wait_at_crossing(
+        &self,
+        now: Time,
+        sidewalk: LaneID,
+        map: &Map,
+    ) -> Option<Duration> {
+        let lane = map.get_l(sidewalk);
+        let mut earliest: Option<Time> = None;
+        for i in vec![lane.src_i, lane.dst_i] {
+            for (req, t) in &self.state[&i].waiting {
+                if let AgentID::Pedestrian(_) = req.agent {
+                    if req.turn.src == sidewalk
+                        && map.get_t(req.turn).turn_type == TurnType::Crosswalk
+                    {
+                        earliest = Some(earliest.map(|e| e.min(*t)).unwrap_or(*t));
+                    }
+                }
+            }
+        }
+        earliest.map(|t| now - t)
+    }
+
+    // The mean delay of every agent that's done this turn so far. Only for traffic signals.
+    pub fn turn_delay(&self, turn: TurnID) -> Option<Duration> {
+        let delays = self.turn_delays.get(&turn)?;
+        Some(delays.total / (delays.count as f64))
+    }
+
+    // Of every agent that's done one of these turns so far, how many started it as soon as they
+    // asked? (didn't wait, total) Only for traffic signals.
+    pub fn turns_without_stopping(&self, turns: &[TurnID]) -> (usize, usize) {
+        let mut nonstop = 0;
+        let mut total = 0;
+        for t in turns {
+            if let Some(delays) = self.turn_delays.get(t) {
+                nonstop += delays.nonstop;
+                total += delays.count;
+            }
+        }
+        (nonstop, total)
+    }
+
+    // The turns with the highest mean delay, worst first
+    pub fn worst_turn_delays(&self, n: usize) -> Vec<(TurnID, Duration)> {
+        let mut delays: Vec<(TurnID, Duration)> = self
+            .turn_delays
+            .keys()
+            .map(|t| (*t, self.turn_delay(*t).unwrap()))
+            .collect();
+        delays.sort_by(|(_, d1), (_, d2)| d2.partial_cmp(d1).unwrap());
+        delays.truncate(n);
+        delays
+    }
+
     // Weird way to measure this, but it works.
     pub fn worst_delay(
         &self,
@@ -724,3 +875,136 @@ fn allow_conflicting_turns(osm_node_id: i64) -> bool {
     ]
     .contains(&osm_node_id)
 }
+
+// Where a signal is in its cycle at the start of the day: the current phase and how much of it is
+// left. The offset is how far into the cycle the signal already is.
+fn initial_phase(signal: &ControlTrafficSignal) -> (usize, Duration) {
+    let mut offset = signal.offset;
+    let mut phase = 0;
+    loop {
+        let dt = signal.phases[phase].phase_type.simple_duration();
+        if offset < dt {
+            return (phase, dt - offset);
+        }
+        offset -= dt;
+        phase = (phase + 1) % signal.phases.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PedestrianID;
+    use abstutil::Timer;
+    use map_model::raw::{OriginalIntersection, RawMap};
+    use map_model::{IntersectionType, LaneType, Phase, RoadSpec};
+
+    #[test]
+    fn test_initial_phase_uses_offset() {
+        let phase = |secs| Phase {
+            protected_groups: BTreeSet::new(),
+            yield_groups: BTreeSet::new(),
+            phase_type: PhaseType::Fixed(Duration::seconds(secs)),
+        };
+        let mut signal = ControlTrafficSignal {
+            id: IntersectionID(0),
+            phases: vec![phase(30.0), phase(20.0)],
+            offset: Duration::ZERO,
+            turn_groups: BTreeMap::new(),
+        };
+        assert_eq!(initial_phase(&signal), (0, Duration::seconds(30.0)));
+
+        signal.offset = Duration::seconds(40.0);
+        assert_eq!(initial_phase(&signal), (1, Duration::seconds(10.0)));
+
+        // Wraps around the 50s cycle
+        signal.offset = Duration::seconds(65.0);
+        assert_eq!(initial_phase(&signal), (0, Duration::seconds(15.0)));
+    }
+
+    #[test]
+    fn test_pedestrian_wait_at_crossing() {
+        let mut raw = RawMap::synthetic_grid(
+            3,
+            3,
+            &RoadSpec {
+                fwd: vec![LaneType::Driving, LaneType::Sidewalk],
+                back: vec![LaneType::Driving, LaneType::Sidewalk],
+            },
+        );
+        // The middle of the grid
+        raw.intersections
+            .get_mut(&OriginalIntersection { osm_node_id: 5 })
+            .unwrap()
+            .intersection_type = IntersectionType::TrafficSignal;
+        let map = Map::create_from_raw(raw, true, &mut Timer::throwaway());
+        let i = map.find_i_by_osm_id(5).unwrap();
+        let signal = map.get_traffic_signal(i);
+
+        let mut scheduler = Scheduler::new();
+        let mut intersections =
+            IntersectionSimState::new(&map, &mut scheduler, false, false, false);
+        // A crosswalk that has to wait for the next phase
+        let turn = map
+            .get_i(i)
+            .turns
+            .iter()
+            .find(|t| {
+                map.get_t(**t).turn_type == TurnType::Crosswalk
+                    && signal.phases[0].get_priority_of_turn(**t, signal) == TurnPriority::Banned
+            })
+            .cloned()
+            .unwrap();
+        let ped = AgentID::Pedestrian(PedestrianID(0));
+        let speed = Speed::meters_per_second(1.0);
+        let start = Time::START_OF_DAY;
+
+        assert_eq!(
+            intersections.pedestrian_wait_at_crossing(start, turn.src, &map),
+            None
+        );
+        assert!(!intersections.maybe_start_turn(
+            ped,
+            turn,
+            speed,
+            start,
+            &map,
+            &mut scheduler,
+            None
+        ));
+        let mut last_wait = Duration::ZERO;
+        let mut now;
+        loop {
+            // Move to the next phase
+            now = intersections.state[&i].phase_ends_at;
+            let wait = intersections
+                .pedestrian_wait_at_crossing(now, turn.src, &map)
+                .unwrap();
+            assert_eq!(wait, now - start);
+            assert!(wait > last_wait);
+            last_wait = wait;
+
+            // Pretend the rest of the sim handled everything due by now
+            while scheduler
+                .peek_next_time()
+                .map(|t| t <= now)
+                .unwrap_or(false)
+            {
+                scheduler.get_next();
+            }
+            intersections.update_intersection(now, i, &map, &mut scheduler);
+            if intersections.maybe_start_turn(ped, turn, speed, now, &map, &mut scheduler, None) {
+                break;
+            }
+        }
+
+        intersections.turn_finished(now, ped, turn, &mut scheduler, &map);
+        assert_eq!(
+            intersections.pedestrian_wait_at_crossing(now, turn.src, &map),
+            None
+        );
+        assert!(intersections
+            .collect_events()
+            .contains(&Event::PedestrianCrossed(PedestrianID(0), turn.src)));
+    }
+}
@@ -5,8 +5,8 @@ mod parking;
 mod queue;
 mod walking;
 
-pub use self::driving::DrivingSimState;
-pub use self::intersection::IntersectionSimState;
-pub use self::parking::ParkingSimState;
+pub use self::driving::{DrivingSimState, SpeedOverrideSource};
+pub use self::intersection::{IntersectionLOS, IntersectionSimState};
+pub use self::parking::{ParkingGarage, ParkingSimState};
 pub use self::queue::Queue;
-pub use self::walking::WalkingSimState;
+pub use self::walking::{PedestrianLOS, WalkingSimState};
@@ -6,11 +6,13 @@ use abstutil::{
 use geom::{Distance, PolyLine, Pt2D};
 use map_model::{
     BuildingID, Lane, LaneID, LaneType, Map, ParkingLotID, PathConstraints, PathStep, Position,
-    Traversable, TurnID,
+    RoadID, Traversable, TurnID,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap};
 
+const DEFAULT_GARAGE_WALK_THRESHOLD: Distance = Distance::const_meters(400.0);
+
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct ParkingSimState {
     #[serde(
@@ -50,9 +52,23 @@ pub struct ParkingSimState {
     )]
     driving_to_lots: MultiMap<LaneID, ParkingLotID>,
 
+    // Parking structures built over on-street parking lanes
+    garages: Vec<ParkingGarage>,
+    // When seeding parked cars, prefer a garage within this distance of the building
+    garage_walk_threshold: Distance,
+    // Drivers searching for parking beyond the lane they're on give up instead of driving further
+    // than this
+    max_search_dist: Option<Distance>,
+
     events: Vec<Event>,
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub struct ParkingGarage {
+    pub lanes: Vec<LaneID>,
+    pub stories: usize,
+}
+
 impl ParkingSimState {
     // Counterintuitive: any spots located in blackholes are just not represented here. If somebody
     // tries to drive from a blackholed spot, they couldn't reach most places.
@@ -69,6 +85,10 @@ impl ParkingSimState {
             num_spots_per_lot: BTreeMap::new(),
             driving_to_lots: MultiMap::new(),
 
+            garages: Vec::new(),
+            garage_walk_threshold: DEFAULT_GARAGE_WALK_THRESHOLD,
+            max_search_dist: None,
+
             events: Vec::new(),
         };
         for l in map.all_lanes() {
@@ -98,6 +118,40 @@ impl ParkingSimState {
         sim
     }
 
+    // Each story of the garage has as many spots as the original parking lane.
+    pub fn add_garage(&mut self, lanes: Vec<LaneID>, stories: usize) {
+        assert!(stories > 0);
+        for l in &lanes {
+            assert!(self.get_parking_garage_capacity(*l).is_none());
+            self.onstreet_lanes
+                .get_mut(l)
+                .unwrap_or_else(|| panic!("Can't build a garage over {}", l))
+                .stories = stories;
+        }
+        self.garages.push(ParkingGarage { lanes, stories });
+    }
+
+    pub fn get_parking_garage_capacity(&self, l: LaneID) -> Option<usize> {
+        let garage = self.garages.iter().find(|g| g.lanes.contains(&l))?;
+        Some(garage.stories * self.onstreet_lanes[&l].spot_dist_along.len())
+    }
+
+    pub fn get_all_garages(&self) -> &Vec<ParkingGarage> {
+        &self.garages
+    }
+
+    pub fn set_garage_walk_threshold(&mut self, dist: Distance) {
+        self.garage_walk_threshold = dist;
+    }
+
+    pub fn get_garage_walk_threshold(&self) -> Distance {
+        self.garage_walk_threshold
+    }
+
+    pub fn set_max_parking_search(&mut self, dist: Option<Distance>) {
+        self.max_search_dist = dist;
+    }
+
     pub fn get_free_onstreet_spots(&self, l: LaneID) -> Vec<ParkingSpot> {
         let mut spots: Vec<ParkingSpot> = Vec::new();
         if let Some(lane) = self.onstreet_lanes.get(&l) {
@@ -110,6 +164,18 @@ impl ParkingSimState {
         spots
     }
 
+    pub fn num_parked_onstreet(&self, l: LaneID) -> usize {
+        self.onstreet_lanes
+            .get(&l)
+            .map(|lane| {
+                lane.spots()
+                    .into_iter()
+                    .filter(|spot| self.occupants.contains_key(spot))
+                    .count()
+            })
+            .unwrap_or(0)
+    }
+
     pub fn get_free_offstreet_spots(&self, b: BuildingID) -> Vec<ParkingSpot> {
         let mut spots: Vec<ParkingSpot> = Vec::new();
         for idx in 0..self.num_spots_per_offstreet.get(&b).cloned().unwrap_or(0) {
@@ -139,7 +205,8 @@ impl ParkingSimState {
         // Sanity check the spot exists
         match spot {
             ParkingSpot::Onstreet(l, idx) => {
-                assert!(idx < self.onstreet_lanes[&l].spot_dist_along.len());
+                let lane = &self.onstreet_lanes[&l];
+                assert!(idx < lane.spot_dist_along.len() * lane.stories);
             }
             ParkingSpot::Offstreet(b, idx) => {
                 assert!(idx < self.num_spots_per_offstreet[&b]);
@@ -159,11 +226,17 @@ impl ParkingSimState {
             .expect("remove_parked_car missing from occupants");
         self.events
             .push(Event::CarLeftParkingSpot(p.vehicle.id, p.spot));
+        if self.in_garage(p.spot) {
+            self.events.push(Event::ExitedGarage(p.vehicle.id));
+        }
     }
 
     pub fn add_parked_car(&mut self, p: ParkedCar) {
         self.events
             .push(Event::CarReachedParkingSpot(p.vehicle.id, p.spot));
+        if self.in_garage(p.spot) {
+            self.events.push(Event::EnteredGarage(p.vehicle.id));
+        }
 
         assert!(self.reserved_spots.remove(&p.spot));
 
@@ -347,7 +420,8 @@ impl ParkingSimState {
                 // Always centered in the entire parking spot
                 Position::new(
                     l,
-                    lane.spot_dist_along[idx] - (map_model::PARKING_SPOT_LENGTH / 2.0),
+                    lane.spot_dist_along[idx % lane.spot_dist_along.len()]
+                        - (map_model::PARKING_SPOT_LENGTH / 2.0),
                 )
                 .equiv_pos(lane.sidewalk, Distance::ZERO, map)
             }
@@ -359,9 +433,27 @@ impl ParkingSimState {
     pub fn get_owner_of_car(&self, id: CarID) -> Option<PersonID> {
         self.parked_cars.get(&id).and_then(|p| p.vehicle.owner)
     }
+
+    // Hand a parked car to somebody else, returning the updated vehicle. Cars that're moving
+    // can't be reassigned here.
+    pub fn transfer_car(
+        &mut self,
+        id: CarID,
+        new_owner: Option<PersonID>,
+    ) -> Result<Vehicle, String> {
+        if let Some(p) = self.parked_cars.get_mut(&id) {
+            p.vehicle.owner = new_owner;
+            Ok(p.vehicle.clone())
+        } else {
+            Err(format!("{} isn't parked, can't transfer it", id))
+        }
+    }
     pub fn lookup_parked_car(&self, id: CarID) -> Option<&ParkedCar> {
         self.parked_cars.get(&id)
     }
+    pub fn all_parked_cars(&self) -> Vec<CarID> {
+        self.parked_cars.keys().cloned().collect()
+    }
 
     // (Filled, available)
     pub fn get_all_parking_spots(&self) -> (Vec<ParkingSpot>, Vec<ParkingSpot>) {
@@ -392,6 +484,55 @@ impl ParkingSimState {
         (filled, available)
     }
 
+    // Per road, (filled spots, total spots). Off-street spots and lots count towards the road
+    // they're accessed from.
+    pub fn utilization_by_road(&self, map: &Map) -> BTreeMap<RoadID, (usize, usize)> {
+        let (filled, available) = self.get_all_parking_spots();
+        let mut results: BTreeMap<RoadID, (usize, usize)> = BTreeMap::new();
+        for (spots, is_filled) in vec![(filled, true), (available, false)] {
+            for spot in spots {
+                let lane = match spot {
+                    ParkingSpot::Onstreet(l, _) => l,
+                    ParkingSpot::Offstreet(b, _) => map.get_b(b).sidewalk(),
+                    ParkingSpot::Lot(pl, _) => map.get_pl(pl).driving_pos.lane(),
+                };
+                let entry = results.entry(map.get_l(lane).parent).or_insert((0, 0));
+                if is_filled {
+                    entry.0 += 1;
+                }
+                entry.1 += 1;
+            }
+        }
+        results
+    }
+
+    // Per on-street parking lane (including garages), (filled spots, total spots)
+    pub fn utilization_by_lane(&self) -> BTreeMap<LaneID, (usize, usize)> {
+        self.onstreet_lanes
+            .iter()
+            .map(|(l, lane)| {
+                let spots = lane.spots();
+                let filled = spots
+                    .iter()
+                    .filter(|spot| self.occupants.contains_key(spot))
+                    .count();
+                (*l, (filled, spots.len()))
+            })
+            .collect()
+    }
+
+    // Like path_to_free_parking_spot, but for drivers searching during their trip, who might give
+    // up sooner.
+    pub fn search_for_free_spot(
+        &self,
+        start: LaneID,
+        vehicle: &Vehicle,
+        target: BuildingID,
+        map: &Map,
+    ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
+        self.find_free_spot(start, vehicle, target, self.max_search_dist, map)
+    }
+
     // Unrealistically assumes the driver has knowledge of currently free parking spots, even if
     // they're far away. Since they don't reserve the spot in advance, somebody else can still beat
     // them there, producing some nice, realistic churn if there's too much contention.
@@ -402,6 +543,17 @@ impl ParkingSimState {
         vehicle: &Vehicle,
         target: BuildingID,
         map: &Map,
+    ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
+        self.find_free_spot(start, vehicle, target, None, map)
+    }
+
+    fn find_free_spot(
+        &self,
+        start: LaneID,
+        vehicle: &Vehicle,
+        target: BuildingID,
+        max_dist: Option<Distance>,
+        map: &Map,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
         let mut backrefs: HashMap<LaneID, TurnID> = HashMap::new();
         // Don't travel far.
@@ -412,6 +564,12 @@ impl ParkingSimState {
 
         while !queue.is_empty() {
             let (dist_so_far, current) = queue.pop().unwrap();
+            if let Some(max) = max_dist {
+                // Everything left in the queue is even further away
+                if -dist_so_far > max {
+                    break;
+                }
+            }
             // If the current lane has a spot open, we wouldn't be asking. This can happen if a spot
             // opens up on the 'start' lane, but behind the car.
             if current != start {
@@ -456,6 +614,13 @@ impl ParkingSimState {
         None
     }
 
+    fn in_garage(&self, spot: ParkingSpot) -> bool {
+        match spot {
+            ParkingSpot::Onstreet(l, _) => self.get_parking_garage_capacity(l).is_some(),
+            ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => false,
+        }
+    }
+
     pub fn collect_events(&mut self) -> Vec<Event> {
         std::mem::replace(&mut self.events, Vec::new())
     }
@@ -468,6 +633,9 @@ struct ParkingLane {
     sidewalk: LaneID,
     // The front of the parking spot (farthest along the lane)
     spot_dist_along: Vec<Distance>,
+    // More than 1 for garages. Every story has the same spots; upper stories just have higher
+    // indices.
+    stories: usize,
 }
 
 impl ParkingLane {
@@ -499,17 +667,19 @@ impl ParkingLane {
             spot_dist_along: (0..lane.number_parking_spots())
                 .map(|idx| map_model::PARKING_SPOT_LENGTH * (2.0 + idx as f64))
                 .collect(),
+            stories: 1,
         })
     }
 
     fn dist_along_for_car(&self, spot_idx: usize, vehicle: &Vehicle) -> Distance {
         // Find the offset to center this particular car in the parking spot
-        self.spot_dist_along[spot_idx] - (map_model::PARKING_SPOT_LENGTH - vehicle.length) / 2.0
+        self.spot_dist_along[spot_idx % self.spot_dist_along.len()]
+            - (map_model::PARKING_SPOT_LENGTH - vehicle.length) / 2.0
     }
 
     fn spots(&self) -> Vec<ParkingSpot> {
         let mut spots = Vec::new();
-        for idx in 0..self.spot_dist_along.len() {
+        for idx in 0..self.spot_dist_along.len() * self.stories {
             spots.push(ParkingSpot::Onstreet(self.parking_lane, idx));
         }
         spots
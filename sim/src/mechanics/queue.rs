@@ -1,5 +1,5 @@
 use crate::mechanics::car::{Car, CarState};
-use crate::{CarID, FOLLOWING_DISTANCE};
+use crate::{CarID, FOLLOWING_DISTANCE, PLATOON_FOLLOWING_DISTANCE};
 use geom::{Distance, Time};
 use map_model::{Map, Traversable};
 use serde::{Deserialize, Serialize};
@@ -19,6 +19,9 @@ pub struct Queue {
     // length first. This is unused for turns themselves. This value can exceed geom_len (for the
     // edge case of ONE long car on a short queue).
     pub reserved_length: Distance,
+    // Cars that entered this queue as part of a platoon, following the car ahead with
+    // PLATOON_FOLLOWING_DISTANCE instead. Fixed from when the space is reserved until it's freed.
+    pub platoon_followers: BTreeSet<CarID>,
 }
 
 impl Queue {
@@ -29,6 +32,16 @@ impl Queue {
             laggy_head: None,
             geom_len: id.length(map),
             reserved_length: Distance::ZERO,
+            platoon_followers: BTreeSet::new(),
+        }
+    }
+
+    // How far this car stays behind the car in front of it on this queue
+    pub fn following_distance(&self, car: CarID) -> Distance {
+        if self.platoon_followers.contains(&car) {
+            PLATOON_FOLLOWING_DISTANCE
+        } else {
+            FOLLOWING_DISTANCE
         }
     }
 
@@ -56,9 +69,10 @@ impl Queue {
         let mut result: Vec<(CarID, Distance)> = Vec::new();
 
         for id in &self.cars {
+            let following_dist = self.following_distance(*id);
             let bound = match result.last() {
                 Some((leader, last_dist)) => {
-                    *last_dist - cars[leader].vehicle.length - FOLLOWING_DISTANCE
+                    *last_dist - cars[leader].vehicle.length - following_dist
                 }
                 None => match self.laggy_head {
                     Some(id) => {
@@ -109,7 +123,7 @@ impl Queue {
                             {
                                 self.geom_len
                                     - (cars[&id].vehicle.length - dist_away_from_this_queue)
-                                    - FOLLOWING_DISTANCE
+                                    - following_dist
                             } else {
                                 self.geom_len
                             }
@@ -155,7 +169,7 @@ impl Queue {
 
             result.push((*id, front));
         }
-        validate_positions(result, cars, now, self.id)
+        self.validate_positions(result, cars, now)
     }
 
     pub fn get_idx_to_insert_car(
@@ -190,7 +204,9 @@ impl Queue {
             return None;
         }
         // Or the follower?
-        if idx != dists.len() && start_dist - vehicle_len - FOLLOWING_DISTANCE < dists[idx].1 {
+        if idx != dists.len()
+            && start_dist - vehicle_len - self.following_distance(dists[idx].0) < dists[idx].1
+        {
             return None;
         }
 
@@ -203,13 +219,22 @@ impl Queue {
         // Sometimes a car + FOLLOWING_DISTANCE might be longer than the geom_len entirely. In that
         // case, it just means the car won't totally fit on the queue at once, which is fine.
         // Reserve the normal amount of space; the next car trying to enter will get rejected.
-        // Also allow this don't-block-the-box prevention to be disabled.
-        let dist = car.vehicle.length + FOLLOWING_DISTANCE;
+        // Also allow this don't-block-the-box prevention to be disabled. A car in a platoon
+        // joins as a follower.
+        let dist = car.vehicle.length
+            + if car.platoon_id.is_some() {
+                PLATOON_FOLLOWING_DISTANCE
+            } else {
+                FOLLOWING_DISTANCE
+            };
         if self.reserved_length + dist < self.geom_len
             || self.reserved_length == Distance::ZERO
             || force_entry
         {
             self.reserved_length += dist;
+            if car.platoon_id.is_some() {
+                self.platoon_followers.insert(car.vehicle.id);
+            }
             return true;
         }
         false
@@ -222,27 +247,30 @@ impl Queue {
     }
 
     pub fn free_reserved_space(&mut self, car: &Car) {
-        self.reserved_length -= car.vehicle.length + FOLLOWING_DISTANCE;
+        self.reserved_length -= car.vehicle.length + self.following_distance(car.vehicle.id);
+        self.platoon_followers.remove(&car.vehicle.id);
         assert!(self.reserved_length >= Distance::ZERO);
     }
-}
 
-fn validate_positions(
-    dists: Vec<(CarID, Distance)>,
-    cars: &BTreeMap<CarID, Car>,
-    now: Time,
-    id: Traversable,
-) -> Vec<(CarID, Distance)> {
-    for pair in dists.windows(2) {
-        if pair[0].1 - cars[&pair[0].0].vehicle.length - FOLLOWING_DISTANCE < pair[1].1 {
-            dump_cars(&dists, cars, id, now);
-            panic!(
-                "get_car_positions wound up with bad positioning: {} then {}\n{:?}",
-                pair[0].1, pair[1].1, dists
-            );
+    fn validate_positions(
+        &self,
+        dists: Vec<(CarID, Distance)>,
+        cars: &BTreeMap<CarID, Car>,
+        now: Time,
+    ) -> Vec<(CarID, Distance)> {
+        for pair in dists.windows(2) {
+            if pair[0].1 - cars[&pair[0].0].vehicle.length - self.following_distance(pair[1].0)
+                < pair[1].1
+            {
+                dump_cars(&dists, cars, self.id, now);
+                panic!(
+                    "get_car_positions wound up with bad positioning: {} then {}\n{:?}",
+                    pair[0].1, pair[1].1, dists
+                );
+            }
         }
+        dists
     }
-    dists
 }
 
 fn dump_cars(
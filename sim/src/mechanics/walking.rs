@@ -7,7 +7,8 @@ use crate::{
 use abstutil::{deserialize_multimap, serialize_multimap, MultiMap};
 use geom::{Distance, Duration, Line, PolyLine, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, Map, ParkingLotID, Path, PathStep, Traversable, SIDEWALK_THICKNESS,
+    BuildingID, BusRouteID, LaneID, Map, ParkingLotID, Path, PathStep, Position, Traversable,
+    SIDEWALK_THICKNESS,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -15,6 +16,37 @@ use std::collections::BTreeMap;
 const TIME_TO_START_BIKING: Duration = Duration::const_seconds(30.0);
 const TIME_TO_FINISH_BIKING: Duration = Duration::const_seconds(45.0);
 
+// Pedestrian level of service on a walkway, graded by flow per unit width, following the Highway
+// Capacity Manual.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PedestrianLOS {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+}
+
+impl PedestrianLOS {
+    // Flow is in pedestrians per minute per meter of width.
+    pub fn from_flow(flow: f64) -> PedestrianLOS {
+        if flow <= 16.0 {
+            PedestrianLOS::A
+        } else if flow <= 23.0 {
+            PedestrianLOS::B
+        } else if flow <= 33.0 {
+            PedestrianLOS::C
+        } else if flow <= 49.0 {
+            PedestrianLOS::D
+        } else if flow <= 75.0 {
+            PedestrianLOS::E
+        } else {
+            PedestrianLOS::F
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct WalkingSimState {
     // BTreeMap not for deterministic simulation, but to make serialized things easier to compare.
@@ -124,7 +156,16 @@ impl WalkingSimState {
     ) {
         let mut ped = self.peds.get_mut(&id).unwrap();
         match ped.state {
-            PedState::Crossing(ref dist_int, _) => {
+            PedState::Crossing(ref dist_int, ref time_int) => {
+                if let PathStep::Lane(l) | PathStep::ContraflowLane(l) = ped.path.current_step() {
+                    self.events.push(Event::PedestrianLeavesSidewalk(
+                        ped.id,
+                        ped.trip,
+                        l,
+                        (dist_int.end - dist_int.start).abs(),
+                        now - time_int.start,
+                    ));
+                }
                 if ped.path.is_last_step() {
                     match ped.goal.connection {
                         SidewalkPOI::ParkingSpot(spot) => {
@@ -385,6 +426,63 @@ impl WalkingSimState {
         peds
     }
 
+    // Pedestrians per minute per meter of width currently moving along a sidewalk. Flow is
+    // density times speed; people waiting at either end don't count.
+    pub fn pedestrian_flow(&self, sidewalk: LaneID, map: &Map) -> f64 {
+        let lane = map.get_l(sidewalk);
+        let area = lane.length().inner_meters() * lane.width.inner_meters();
+        if area == 0.0 {
+            return 0.0;
+        }
+        let total_speed: f64 = self
+            .peds_per_traversable
+            .get(Traversable::Lane(sidewalk))
+            .iter()
+            .filter_map(|id| match self.peds[id].state {
+                PedState::Crossing(_, _) => Some(self.peds[id].speed.inner_meters_per_second()),
+                _ => None,
+            })
+            .sum();
+        60.0 * total_speed / area
+    }
+
+    // Where is everybody walking along or waiting at the end of a sidewalk?
+    pub fn get_peds_on_lane(
+        &self,
+        lane: LaneID,
+        now: Time,
+        map: &Map,
+    ) -> Vec<(PedestrianID, Distance)> {
+        self.peds_per_traversable
+            .get(Traversable::Lane(lane))
+            .iter()
+            .filter_map(|id| match self.peds[id].state {
+                PedState::Crossing(_, _) | PedState::WaitingToTurn(_, _) => {
+                    Some((*id, self.peds[id].get_dist_along(now, map)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Everybody on the sidewalk near a position, including anybody waiting there
+    pub fn get_peds_near(
+        &self,
+        pos: Position,
+        tolerance: Distance,
+        now: Time,
+        map: &Map,
+    ) -> Vec<PedestrianID> {
+        self.peds_per_traversable
+            .get(Traversable::Lane(pos.lane()))
+            .iter()
+            .filter(|id| {
+                (self.peds[*id].get_dist_along(now, map) - pos.dist_along()).abs() <= tolerance
+            })
+            .cloned()
+            .collect()
+    }
+
     pub fn does_ped_exist(&self, id: PedestrianID) -> bool {
         self.peds.contains_key(&id)
     }
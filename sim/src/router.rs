@@ -4,8 +4,8 @@ use crate::{
 };
 use geom::Distance;
 use map_model::{
-    BuildingID, IntersectionID, Map, Path, PathConstraints, PathRequest, PathStep, Position,
-    Traversable, TurnID,
+    BuildingID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
+    Position, Traversable, TurnID,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -24,6 +24,7 @@ pub enum ActionAtEnd {
     GotoLaneEnd,
     StopBiking(SidewalkSpot),
     BusAtStop,
+    StopAtCurb,
     GiveUpOnParking,
 }
 
@@ -47,6 +48,10 @@ enum Goal {
     FollowBusRoute {
         end_dist: Distance,
     },
+    // Pick up or drop off somebody at the curb
+    StopAtCurb {
+        end_dist: Distance,
+    },
 }
 
 impl Router {
@@ -92,6 +97,13 @@ impl Router {
         }
     }
 
+    pub fn stop_at_curb(path: Path, end_dist: Distance) -> Router {
+        Router {
+            path,
+            goal: Goal::StopAtCurb { end_dist },
+        }
+    }
+
     pub fn head(&self) -> Traversable {
         self.path.current_step().as_traversable()
     }
@@ -124,6 +136,27 @@ impl Router {
             } => stuck_end_dist.unwrap_or_else(|| spot.unwrap().1),
             Goal::BikeThenStop { end_dist } => end_dist,
             Goal::FollowBusRoute { end_dist } => end_dist,
+            Goal::StopAtCurb { end_dist } => end_dist,
+        }
+    }
+
+    // Once a car reaches the last lane of its path, it picks a spot or starts roaming around. Until
+    // it finally parks, it's searching.
+    pub fn is_searching_for_parking(&self) -> bool {
+        match self.goal {
+            Goal::ParkNearBuilding {
+                ref spot,
+                ref stuck_end_dist,
+                ..
+            } => spot.is_some() || stuck_end_dist.is_some(),
+            _ => false,
+        }
+    }
+
+    pub fn parking_target(&self) -> Option<BuildingID> {
+        match self.goal {
+            Goal::ParkNearBuilding { target, .. } => Some(target),
+            _ => None,
         }
     }
 
@@ -201,7 +234,14 @@ impl Router {
                 }
 
                 let need_new_spot = match spot {
-                    Some((s, _)) => !parking.is_free(*s),
+                    Some((s, _)) => {
+                        if parking.is_free(*s) {
+                            false
+                        } else {
+                            events.push(Event::CurbsideConflict(vehicle.id, *s));
+                            true
+                        }
+                    }
                     None => true,
                 };
                 if need_new_spot {
@@ -247,7 +287,7 @@ impl Router {
                         *spot = Some((new_spot, new_pos.dist_along()));
                     } else {
                         if let Some((new_path_steps, new_spot, new_pos)) =
-                            parking.path_to_free_parking_spot(current_lane, vehicle, target, map)
+                            parking.search_for_free_spot(current_lane, vehicle, target, map)
                         {
                             *spot = Some((new_spot, new_pos.dist_along()));
                             for step in new_path_steps {
@@ -307,23 +347,31 @@ impl Router {
                     None
                 }
             }
+            Goal::StopAtCurb { end_dist } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::StopAtCurb)
+                } else {
+                    None
+                }
+            }
         }
     }
 
+    // Returns the original and new lane on the next road, if the car switches to a less crowded one
     pub fn opportunistically_lanechange(
         &mut self,
         queues: &BTreeMap<Traversable, Queue>,
         map: &Map,
-    ) {
+    ) -> Option<(LaneID, LaneID)> {
         let (current_turn, next_lane) = {
             let steps = self.path.get_steps();
             if steps.len() < 5 {
-                return;
+                return None;
             }
             match (steps[1], steps[4]) {
                 (PathStep::Turn(t), PathStep::Lane(l)) => (t, l),
                 _ => {
-                    return;
+                    return None;
                 }
             }
         };
@@ -367,12 +415,20 @@ impl Router {
         // unnecessarily.
         // TODO Better weight function... any slower vehicles in one?
         if best_lane == orig_target_lane {
-            return;
+            return None;
         }
 
         self.path.modify_step(1, PathStep::Turn(turn1), map);
         self.path.modify_step(2, PathStep::Lane(best_lane), map);
         self.path.modify_step(3, PathStep::Turn(turn2), map);
+        Some((orig_target_lane, best_lane))
+    }
+
+    // Switch to a different path starting from the current step and ending at the same place.
+    pub fn reroute(&mut self, path: Path) {
+        assert_eq!(self.head(), path.current_step().as_traversable());
+        assert_eq!(self.path.last_step(), path.last_step());
+        self.path = path;
     }
 
     pub fn replace_path_for_serialization(&mut self, path: Path) -> Path {
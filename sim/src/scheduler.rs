@@ -1,5 +1,6 @@
 use crate::{
-    pandemic, AgentID, CarID, CreateCar, CreatePedestrian, PedestrianID, TripID, TripSpec,
+    pandemic, AgentID, CarID, CreateCar, CreatePedestrian, IncidentID, PedestrianID, TripID,
+    TripSpec,
 };
 use derivative::Derivative;
 use geom::{Duration, Histogram, Time};
@@ -23,6 +24,13 @@ pub enum Command {
     Callback(Duration),
     Pandemic(pandemic::Cmd),
     FinishRemoteTrip(TripID),
+    RequestRideHail(TripID),
+    // Match waiting ride-hailing requests with idle drivers
+    DispatchRideHail,
+    CheckBusStalled(CarID),
+    // The index of a school zone in Sim
+    UpdateSchoolZone(usize),
+    ClearIncident(IncidentID),
 }
 
 impl Command {
@@ -46,6 +54,11 @@ impl Command {
             Command::Callback(_) => CommandType::Callback,
             Command::Pandemic(ref p) => CommandType::Pandemic(p.clone()),
             Command::FinishRemoteTrip(t) => CommandType::FinishRemoteTrip(*t),
+            Command::RequestRideHail(t) => CommandType::RequestRideHail(*t),
+            Command::DispatchRideHail => CommandType::DispatchRideHail,
+            Command::CheckBusStalled(car) => CommandType::CheckBusStalled(*car),
+            Command::UpdateSchoolZone(idx) => CommandType::SchoolZone(*idx),
+            Command::ClearIncident(id) => CommandType::Incident(*id),
         }
     }
 }
@@ -62,6 +75,11 @@ pub enum CommandType {
     Callback,
     Pandemic(pandemic::Cmd),
     FinishRemoteTrip(TripID),
+    RequestRideHail(TripID),
+    DispatchRideHail,
+    CheckBusStalled(CarID),
+    SchoolZone(usize),
+    Incident(IncidentID),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -164,6 +182,26 @@ impl Scheduler {
         });
     }
 
+    // Reschedule every trip that hasn't started yet, so the time until it departs shrinks by
+    // factor. 2.0 means trips start twice as quickly.
+    pub fn scale_trip_departures(&mut self, now: Time, factor: f64) {
+        assert!(factor > 0.0);
+        let mut updates = Vec::new();
+        for (cmd_type, (_, time)) in &self.queued_commands {
+            if let CommandType::StartTrip(_) = cmd_type {
+                if *time > now {
+                    updates.push((cmd_type.clone(), now + (*time - now) / factor));
+                }
+            }
+        }
+        for (cmd_type, time) in updates {
+            self.last_time = self.last_time.max(time);
+            self.queued_commands.get_mut(&cmd_type).unwrap().1 = time;
+            // The old item is skipped by get_next, since the times won't match.
+            self.items.push(Item { time, cmd_type });
+        }
+    }
+
     pub fn cancel(&mut self, cmd: Command) {
         // It's fine if a previous command hasn't actually been scheduled.
         self.queued_commands.remove(&cmd.to_type());
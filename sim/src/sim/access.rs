@@ -0,0 +1,498 @@
+use super::Sim;
+use crate::{DrivingGoal, SidewalkSpot, TripEndpoint, TripID, TripMode};
+use abstutil::Timer;
+use geom::{Distance, Duration, Pt2D, Speed};
+use map_model::{
+    connectivity, Building, BuildingID, Lane, LaneID, Map, Path, PathConstraints, PathRequest,
+    Position, RoadID, Traversable,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+// Assumed speeds for estimating access to jobs
+const ACCESS_WALKING_SPEED: Speed = Speed::const_meters_per_second(1.2);
+const ACCESS_BIKING_SPEED: Speed = Speed::const_meters_per_second(4.0);
+const ACCESS_BUS_SPEED: Speed = Speed::const_meters_per_second(8.0);
+
+// Reachability and access to jobs
+impl Sim {
+    // The convex hull of everywhere someone could walk from a building within max_time. If fewer
+    // than 3 distinct points are reachable, returns just those.
+    pub fn get_walking_isochrone_polygon(
+        &self,
+        origin: BuildingID,
+        max_time: Duration,
+        map: &Map,
+    ) -> Vec<Pt2D> {
+        let b = map.get_b(origin);
+        let mut pts = vec![b.polygon.center(), b.front_path.sidewalk.pt(map)];
+        for (l, time) in travel_times_from_bldg(origin, TripMode::Walk, max_time, map) {
+            let lane = map.get_l(l);
+            let speed = ACCESS_WALKING_SPEED.min(map.get_r(lane.parent).speed_limit);
+            let dist = (speed * (max_time - time).max(Duration::ZERO)).min(lane.length());
+            pts.push(lane.lane_center_pts.first_pt());
+            pts.push(lane.lane_center_pts.dist_along(dist).0);
+        }
+        convex_hull(pts)
+    }
+
+    // How many workplaces can be reached from a building within some time? Workplaces are any
+    // buildings with amenities or shops.
+    // TODO office=* isn't imported from OSM yet.
+    pub fn get_access_to_jobs(
+        &self,
+        bldg: BuildingID,
+        mode: TripMode,
+        max_time: Duration,
+        map: &Map,
+    ) -> usize {
+        let reached = travel_times_from_bldg(bldg, mode, max_time, map);
+
+        let reached_roads: BTreeSet<RoadID> =
+            reached.keys().map(|l| map.get_l(*l).parent).collect();
+        map.all_buildings()
+            .iter()
+            .filter(|b| {
+                b.id != bldg
+                    && !b.amenities.is_empty()
+                    && reached_roads.contains(&map.get_l(b.sidewalk()).parent)
+            })
+            .count()
+    }
+
+    // Travel time from every building to every other using some mode. matrix[i][j] is from
+    // buildings[i] to buildings[j], and None means there's no way there. Buildings are reached at
+    // the start of the nearest lane, and the times have the same limitations as
+    // get_access_to_jobs.
+    pub fn get_origin_destination_travel_time_matrix(
+        &self,
+        buildings: &[BuildingID],
+        mode: TripMode,
+        map: &Map,
+        timer: &mut Timer,
+    ) -> Vec<Vec<Option<Duration>>> {
+        timer.parallelize(
+            "calculate travel time matrix",
+            buildings.to_vec(),
+            |origin| {
+                let reached = travel_times_from_bldg(origin, mode, Duration::hours(24), map);
+                buildings
+                    .iter()
+                    .map(|b| {
+                        if *b == origin {
+                            Some(Duration::ZERO)
+                        } else {
+                            reached.get(&bldg_lane(*b, mode, map)).cloned()
+                        }
+                    })
+                    .collect()
+            },
+        )
+    }
+
+    // One row per origin, one column per destination, with times in seconds. No path is blank.
+    pub fn od_matrix_to_csv(
+        matrix: &[Vec<Option<Duration>>],
+        buildings: &[BuildingID],
+        path: &str,
+    ) -> Result<(), std::io::Error> {
+        let mut f = std::fs::File::create(path)?;
+        let header: Vec<String> = buildings.iter().map(|b| b.0.to_string()).collect();
+        writeln!(f, "origin,{}", header.join(","))?;
+        for (b, row) in buildings.iter().zip(matrix) {
+            let cells: Vec<String> = row
+                .iter()
+                .map(|dt| {
+                    dt.map(|dt| dt.inner_seconds().to_string())
+                        .unwrap_or_else(String::new)
+                })
+                .collect();
+            writeln!(f, "{},{}", b.0, cells.join(","))?;
+        }
+        Ok(())
+    }
+
+    // How long would a trip between two buildings take if it started now? Driving uses how fast
+    // cars are moving on each road right now, or the speed limit on empty roads. Walking and
+    // biking use the same speeds as get_access_to_jobs. None if there's no way there.
+    // TODO The path is the shortest one, not the fastest one given current traffic. Transit uses
+    // travel_times_from_bldg, ignoring congestion.
+    pub fn forecast_travel_time(
+        &self,
+        origin: BuildingID,
+        dest: BuildingID,
+        mode: TripMode,
+        map: &Map,
+    ) -> Option<Duration> {
+        if mode == TripMode::Transit {
+            return travel_times_from_bldg(origin, mode, Duration::hours(24), map)
+                .get(&bldg_lane(dest, mode, map))
+                .cloned();
+        }
+        let path = path_between_bldgs(origin, dest, mode, map)?;
+        let effective_speeds = self.get_effective_speed_by_road(map);
+        let mut time = Duration::ZERO;
+        for step in path.get_steps() {
+            let on = step.as_traversable();
+            let speed_limit = on.speed_limit(map);
+            let speed = match mode {
+                TripMode::Walk => ACCESS_WALKING_SPEED.min(speed_limit),
+                TripMode::Bike => ACCESS_BIKING_SPEED.min(speed_limit),
+                TripMode::Drive => {
+                    let road = match on {
+                        Traversable::Lane(l) => map.get_l(l).parent,
+                        Traversable::Turn(t) => map.get_l(t.dst).parent,
+                    };
+                    effective_speeds.get(&road).cloned().unwrap_or(speed_limit)
+                }
+                TripMode::Transit => unreachable!(),
+            };
+            // Stopped traffic doesn't move at all; don't divide by zero.
+            time +=
+                on.length(map) / Speed::meters_per_second(speed.inner_meters_per_second().max(0.1));
+        }
+        Some(time)
+    }
+
+    // Forecasts how long a trip between buildings will take, and remembers it for
+    // forecast_accuracy. Call this when the trip starts.
+    pub fn forecast_trip_time(&mut self, trip: TripID, map: &Map) -> Option<Duration> {
+        let (_, start, end, mode) = self.trips.trip_info(trip);
+        let forecast = match (start, end) {
+            (TripEndpoint::Bldg(b1), TripEndpoint::Bldg(b2)) => {
+                self.forecast_travel_time(b1, b2, mode, map)?
+            }
+            _ => {
+                return None;
+            }
+        };
+        self.travel_time_forecasts.insert(trip, forecast);
+        Some(forecast)
+    }
+
+    // The relative error of a finished trip's forecast from forecast_trip_time; 0 is a perfect
+    // forecast. None if the trip hasn't finished or wasn't forecast.
+    pub fn forecast_accuracy(&self, trip: TripID) -> Option<f64> {
+        let forecast = self.travel_time_forecasts.get(&trip)?;
+        let (actual, _) = self.finished_trip_time(trip)?;
+        if actual == Duration::ZERO {
+            return None;
+        }
+        Some((forecast.inner_seconds() - actual.inner_seconds()).abs() / actual.inner_seconds())
+    }
+
+    // A rough estimate of the percent change in transit trips per percent change in the fare.
+    // -0.3 means a 10% fare increase loses 3% of riders. This is a heuristic, not a simulation:
+    // mode choice is fixed when a scenario is instantiated, so nothing is rerun. Instead, the fare
+    // is treated as part of the cost of each finished transit trip, proportional to how long it
+    // took, and the trips that'd be quicker on foot after the change are counted as switching.
+    // TODO Only handles trips between buildings; other transit trips never switch.
+    // TODO Walking is the only alternative considered.
+    pub fn estimate_transit_fare_elasticity(&self, fare_change_pct: f64, map: &Map) -> f64 {
+        let multiplier = 1.0 + fare_change_pct / 100.0;
+        let mut baseline = 0;
+        let mut switched: isize = 0;
+        for (_, trip, mode, total_time) in &self.analytics.finished_trips {
+            if *mode != Some(TripMode::Transit) {
+                continue;
+            }
+            baseline += 1;
+            let (b1, b2) = match self.trips.trip_info(*trip) {
+                (_, TripEndpoint::Bldg(b1), TripEndpoint::Bldg(b2), _) => (b1, b2),
+                _ => {
+                    continue;
+                }
+            };
+            let walking = match map.pathfind(PathRequest {
+                start: map.get_b(b1).front_path.sidewalk,
+                end: map.get_b(b2).front_path.sidewalk,
+                constraints: PathConstraints::Pedestrian,
+            }) {
+                Some(path) => path.total_length() / ACCESS_WALKING_SPEED,
+                None => {
+                    continue;
+                }
+            };
+            let before = *total_time <= walking;
+            let after = *total_time * multiplier <= walking;
+            if before && !after {
+                switched += 1;
+            } else if !before && after {
+                // Fares went down
+                switched -= 1;
+            }
+        }
+        if baseline == 0 || fare_change_pct == 0.0 {
+            return 0.0;
+        }
+        (-100.0 * (switched as f64) / (baseline as f64)) / fare_change_pct
+    }
+
+    // Which finished trips using one mode could have used another? Just checks that some path
+    // exists for the new mode, not that it's reasonable.
+    // TODO Only handles trips between buildings.
+    pub fn modal_shift_potential(&self, from: TripMode, to: TripMode, map: &Map) -> Vec<TripID> {
+        let mut results = Vec::new();
+        for (_, trip, mode, _) in &self.analytics.finished_trips {
+            if *mode != Some(from) {
+                continue;
+            }
+            let (b1, b2) = match self.trips.trip_info(*trip) {
+                (_, TripEndpoint::Bldg(b1), TripEndpoint::Bldg(b2), _) => (b1, b2),
+                _ => {
+                    continue;
+                }
+            };
+            if path_exists(b1, b2, to, map) {
+                results.push(*trip);
+            }
+        }
+        results
+    }
+
+    // How many buildings can't reach the nearest building of the opposite type by this mode?
+    // Buildings with amenities count as commercial, everything else as residential. Only depends
+    // on the map, so it's cached per mode.
+    pub fn count_unreachable_buildings(&mut self, mode: TripMode, map: &Map) -> usize {
+        if let Some(cnt) = self.unreachable_buildings.get(&mode) {
+            return *cnt;
+        }
+        let (commercial, residential): (Vec<&Building>, Vec<&Building>) = map
+            .all_buildings()
+            .iter()
+            .partition(|b| !b.amenities.is_empty());
+        let mut cnt = 0;
+        for b in map.all_buildings() {
+            let pt = b.polygon.center();
+            let others = if b.amenities.is_empty() {
+                &commercial
+            } else {
+                &residential
+            };
+            if let Some(other) = others
+                .iter()
+                .min_by_key(|other| other.polygon.center().dist_to(pt))
+            {
+                if !path_exists(b.id, other.id, mode, map) {
+                    cnt += 1;
+                }
+            }
+        }
+        self.unreachable_buildings.insert(mode, cnt);
+        cnt
+    }
+}
+
+// Andrew's monotone chain. Returns the hull counter-clockwise, without repeating the first point.
+fn convex_hull(mut pts: Vec<Pt2D>) -> Vec<Pt2D> {
+    pts.sort_by(|a, b| {
+        a.x()
+            .partial_cmp(&b.x())
+            .unwrap()
+            .then(a.y().partial_cmp(&b.y()).unwrap())
+    });
+    pts.dedup();
+    if pts.len() < 3 {
+        return pts;
+    }
+
+    let cross = |o: Pt2D, a: Pt2D, b: Pt2D| {
+        (a.x() - o.x()) * (b.y() - o.y()) - (a.y() - o.y()) * (b.x() - o.x())
+    };
+    let mut hull: Vec<Pt2D> = Vec::new();
+    // Lower half, then upper half
+    for pass in 0..2 {
+        let start = hull.len();
+        let iter: Box<dyn Iterator<Item = &Pt2D>> = if pass == 0 {
+            Box::new(pts.iter())
+        } else {
+            Box::new(pts.iter().rev())
+        };
+        for pt in iter {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], *pt) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(*pt);
+        }
+        // The last point of each half is the first point of the other
+        hull.pop();
+    }
+    hull
+}
+
+// How long it takes to reach the start of every lane from a building using some mode, up to
+// max_time
+// TODO Driving times use speed limits, not current congestion. Transit ignores waiting for the bus.
+fn travel_times_from_bldg(
+    bldg: BuildingID,
+    mode: TripMode,
+    max_time: Duration,
+    map: &Map,
+) -> BTreeMap<LaneID, Duration> {
+    let sidewalk = map.get_b(bldg).sidewalk();
+    let walking = |lane: &Lane| ACCESS_WALKING_SPEED.min(map.get_r(lane.parent).speed_limit);
+    match mode {
+        TripMode::Walk => travel_times(
+            map,
+            vec![(sidewalk, Duration::ZERO)],
+            PathConstraints::Pedestrian,
+            walking,
+            max_time,
+        ),
+        TripMode::Bike => travel_times(
+            map,
+            vec![(map.find_biking_lane_near_building(bldg), Duration::ZERO)],
+            PathConstraints::Bike,
+            |lane| ACCESS_BIKING_SPEED.min(map.get_r(lane.parent).speed_limit),
+            max_time,
+        ),
+        TripMode::Drive => travel_times(
+            map,
+            vec![(map.find_driving_lane_near_building(bldg), Duration::ZERO)],
+            PathConstraints::Car,
+            |lane| map.get_r(lane.parent).speed_limit,
+            max_time,
+        ),
+        TripMode::Transit => {
+            let on_foot = travel_times(
+                map,
+                vec![(sidewalk, Duration::ZERO)],
+                PathConstraints::Pedestrian,
+                walking,
+                max_time,
+            );
+            // Ride from any stop reached on foot to every later stop on its routes, then
+            // walk from there.
+            let mut starts = vec![(sidewalk, Duration::ZERO)];
+            for stop in map.all_bus_stops().values() {
+                if let Some(t) = on_foot.get(&stop.sidewalk_pos.lane()) {
+                    for route in map.get_routes_serving_stop(stop.id) {
+                        let idx = route.stops.iter().position(|s| *s == stop.id).unwrap();
+                        let mut time = *t;
+                        let mut prev = stop.sidewalk_pos.pt(map);
+                        for i in 1..route.stops.len() {
+                            let next = map.get_bs(route.stops[(idx + i) % route.stops.len()]);
+                            let pt = next.sidewalk_pos.pt(map);
+                            time += prev.dist_to(pt) / ACCESS_BUS_SPEED;
+                            if time > max_time {
+                                break;
+                            }
+                            starts.push((next.sidewalk_pos.lane(), time));
+                            prev = pt;
+                        }
+                    }
+                }
+            }
+            travel_times(map, starts, PathConstraints::Pedestrian, walking, max_time)
+        }
+    }
+}
+
+// Where travel_times_from_bldg reaches a building using some mode
+fn bldg_lane(bldg: BuildingID, mode: TripMode, map: &Map) -> LaneID {
+    match mode {
+        TripMode::Drive => map.find_driving_lane_near_building(bldg),
+        TripMode::Bike => map.find_biking_lane_near_building(bldg),
+        TripMode::Walk | TripMode::Transit => map.get_b(bldg).sidewalk(),
+    }
+}
+
+// Dijkstra from some lanes, with initial times, to every lane reachable within max_time. Returns
+// when each lane is first entered.
+fn travel_times<F: Fn(&Lane) -> Speed>(
+    map: &Map,
+    starts: Vec<(LaneID, Duration)>,
+    constraints: PathConstraints,
+    speed: F,
+    max_time: Duration,
+) -> BTreeMap<LaneID, Duration> {
+    let (reached, _) = connectivity::dijkstra(
+        starts.into_iter().filter(|(_, t)| *t <= max_time).collect(),
+        |l, t| {
+            let lane = map.get_l(l);
+            let dt = lane.length() / speed(lane);
+            if t + dt > max_time {
+                return Vec::new();
+            }
+            map.get_turns_from_lane(l)
+                .into_iter()
+                .filter(|turn| constraints.can_use(map.get_l(turn.id.dst), map))
+                .map(|turn| (turn.id.dst, dt))
+                .collect()
+        },
+        |_, _| false,
+    );
+    reached.into_iter().collect()
+}
+
+// Just checks that some path exists for the mode, not that it's reasonable.
+fn path_exists(b1: BuildingID, b2: BuildingID, mode: TripMode, map: &Map) -> bool {
+    match mode {
+        TripMode::Transit => map
+            .should_use_transit(
+                SidewalkSpot::building(b1, map).sidewalk_pos,
+                SidewalkSpot::building(b2, map).sidewalk_pos,
+            )
+            .is_some(),
+        TripMode::Walk | TripMode::Bike | TripMode::Drive => {
+            path_between_bldgs(b1, b2, mode, map).is_some()
+        }
+    }
+}
+
+// Transit isn't a single path, so it's always None.
+fn path_between_bldgs(b1: BuildingID, b2: BuildingID, mode: TripMode, map: &Map) -> Option<Path> {
+    match mode {
+        TripMode::Walk => map.pathfind(PathRequest {
+            start: SidewalkSpot::building(b1, map).sidewalk_pos,
+            end: SidewalkSpot::building(b2, map).sidewalk_pos,
+            constraints: PathConstraints::Pedestrian,
+        }),
+        TripMode::Bike => map.pathfind(PathRequest {
+            start: Position::new(map.find_biking_lane_near_building(b1), Distance::ZERO),
+            end: DrivingGoal::ParkNear(b2).goal_pos(PathConstraints::Bike, map),
+            constraints: PathConstraints::Bike,
+        }),
+        TripMode::Drive => map.pathfind(PathRequest {
+            start: Position::new(map.find_driving_lane_near_building(b1), Distance::ZERO),
+            end: DrivingGoal::ParkNear(b2).goal_pos(PathConstraints::Car, map),
+            constraints: PathConstraints::Car,
+        }),
+        TripMode::Transit => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convex_hull() {
+        let hull = convex_hull(vec![
+            Pt2D::new(0.0, 0.0),
+            Pt2D::new(2.0, 0.0),
+            Pt2D::new(1.0, 1.0),
+            Pt2D::new(2.0, 2.0),
+            Pt2D::new(0.0, 2.0),
+            Pt2D::new(2.0, 2.0),
+        ]);
+        // The interior point and the duplicate corner are gone.
+        assert_eq!(
+            hull,
+            vec![
+                Pt2D::new(0.0, 0.0),
+                Pt2D::new(2.0, 0.0),
+                Pt2D::new(2.0, 2.0),
+                Pt2D::new(0.0, 2.0),
+            ]
+        );
+
+        // Too few points to make a polygon
+        assert_eq!(
+            convex_hull(vec![Pt2D::new(1.0, 1.0), Pt2D::new(1.0, 1.0)]),
+            vec![Pt2D::new(1.0, 1.0)]
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use super::Sim;
+use crate::analytics::emissions_factor_at_speed;
+use crate::{AgentID, PedestrianID, PersonID};
+use geom::{Distance, Duration, Speed};
+use map_model::{BuildingID, LaneID, Map, RoadID};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// Emissions and exposure
+impl Sim {
+    // Total kg of CO2-equivalent emitted by vehicles so far on each road. Roads nobody has driven
+    // on are included with 0.
+    pub fn get_emissions_by_road(&self, map: &Map) -> BTreeMap<RoadID, f64> {
+        let mut results: BTreeMap<RoadID, f64> =
+            map.all_roads().iter().map(|r| (r.id, 0.0)).collect();
+        for (r, kg) in &self.analytics.road_emissions {
+            results.insert(*r, *kg);
+        }
+        results
+    }
+
+    // Grams of CO2-equivalent a car emits per km on this road, based on how fast cars are moving
+    // there right now. Roads without any cars use the speed limit.
+    pub fn get_road_level_emissions_factor(&self, road: RoadID, map: &Map) -> f64 {
+        let speed = self
+            .get_effective_speed(road, map)
+            .unwrap_or_else(|| map.get_r(road).speed_limit);
+        emissions_factor_at_speed(speed.inner_meters_per_second() * 3.6)
+    }
+
+    // kg of CO2-equivalent emitted on behalf of the person controlling this agent, over all of
+    // their trips so far. Agents not belonging to a person (like buses) count as 0.
+    pub fn get_agent_carbon_footprint(&self, id: AgentID) -> f64 {
+        if let Some(person) = self.agent_to_person(id) {
+            self.person_carbon_footprint(person)
+        } else {
+            0.0
+        }
+    }
+
+    // kg of CO2-equivalent emitted by everybody who lives in this building
+    pub fn household_carbon_footprint(&self, bldg: BuildingID) -> f64 {
+        self.bldg_to_people(bldg)
+            .into_iter()
+            .map(|p| self.person_carbon_footprint(p))
+            .sum()
+    }
+
+    // Vehicle emissions are counted as each lane is driven, at the speed driven there. Bus legs
+    // split the bus's emissions between everybody riding it at the time.
+    fn person_carbon_footprint(&self, person: PersonID) -> f64 {
+        self.get_person(person)
+            .trips
+            .iter()
+            .filter_map(|t| self.analytics.trip_emissions.get(t))
+            .sum()
+    }
+
+    // One line per road: ID, longitude and latitude of the middle, kg CO2-equivalent emitted
+    pub fn get_emissions_map_csv(&self, path: &str, map: &Map) -> Result<(), std::io::Error> {
+        let mut f = csv::Writer::from_path(path)?;
+        for (r, kg) in self.get_emissions_by_road(map) {
+            let pt = map
+                .get_r(r)
+                .center_pts
+                .middle()
+                .forcibly_to_gps(map.get_gps_bounds());
+            f.serialize(RoadEmissions {
+                road: r.0,
+                longitude: pt.x(),
+                latitude: pt.y(),
+                kg_co2e: kg,
+            })?;
+        }
+        f.flush()
+    }
+
+    // How much pollution has this pedestrian breathed in, walking along every road so far? Time
+    // spent next to each road is multiplied by the road's emissions (like from
+    // get_emissions_by_road), in kg * seconds.
+    // TODO Time spent waiting to cross at intersections isn't counted.
+    pub fn get_pedestrian_exposure_to_pollution(
+        &self,
+        ped: PedestrianID,
+        emissions_by_road: &BTreeMap<RoadID, f64>,
+    ) -> f64 {
+        match self.analytics.pedestrian_time_per_road.get(&ped) {
+            Some(per_road) => per_road
+                .iter()
+                .map(|(r, (dt, _))| {
+                    dt.inner_seconds() * emissions_by_road.get(r).cloned().unwrap_or(0.0)
+                })
+                .sum(),
+            None => 0.0,
+        }
+    }
+
+    // How much more exposure would the pedestrian have had walking along a different route, instead
+    // of every route they actually took? They're assumed to walk at their usual pace so far.
+    // Negative means the alternative is cleaner.
+    pub fn compare_route_exposure(
+        &self,
+        ped: PedestrianID,
+        alt_path: &[LaneID],
+        emissions_by_road: &BTreeMap<RoadID, f64>,
+        map: &Map,
+    ) -> f64 {
+        let (time, dist) = match self.analytics.pedestrian_time_per_road.get(&ped) {
+            Some(per_road) => per_road
+                .values()
+                .fold((Duration::ZERO, Distance::ZERO), |(t1, d1), (t2, d2)| {
+                    (t1 + *t2, d1 + *d2)
+                }),
+            None => {
+                return 0.0;
+            }
+        };
+        if time == Duration::ZERO {
+            return 0.0;
+        }
+        let speed = Speed::meters_per_second(dist.inner_meters() / time.inner_seconds());
+        route_exposure(alt_path, speed, emissions_by_road, map)
+            - self.get_pedestrian_exposure_to_pollution(ped, emissions_by_road)
+    }
+}
+
+#[derive(Serialize)]
+struct RoadEmissions {
+    road: usize,
+    longitude: f64,
+    latitude: f64,
+    kg_co2e: f64,
+}
+
+// Seconds spent walking next to each road, times the road's emissions
+fn route_exposure(
+    lanes: &[LaneID],
+    speed: Speed,
+    emissions_by_road: &BTreeMap<RoadID, f64>,
+    map: &Map,
+) -> f64 {
+    lanes
+        .iter()
+        .map(|l| {
+            let lane = map.get_l(*l);
+            (lane.length() / speed).inner_seconds()
+                * emissions_by_road.get(&lane.parent).cloned().unwrap_or(0.0)
+        })
+        .sum()
+}
@@ -0,0 +1,739 @@
+use super::{road_capacity_per_hour, Sim, SimOptions, LANE_CAPACITY_PER_HOUR};
+use crate::{AgentID, CarID, NetworkFlowStats, Scenario, TripEndpoint, TripID};
+use abstutil::Timer;
+use geom::{Distance, Duration, Pt2D};
+use map_model::raw::{OriginalRoad, RawMap, RawRoad};
+use map_model::{
+    connectivity, osm, BuildingID, BusStopID, EditCmd, IntersectionID, LaneID, LaneType, Map,
+    PathConstraints, PathRequest, Position, RoadID, RoadSpec, Traversable,
+};
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+const NUM_RESILIENCE_SAMPLES: usize = 1000;
+
+// What-if experiments and sensitivity analysis
+impl Sim {
+    // Describes what changed going from this sim to the other one. Both must be on the same map.
+    pub fn snapshot_diff(&self, other: &Sim, map: &Map) -> SnapshotDiff {
+        assert_eq!(self.map_name, other.map_name);
+
+        let old_agents: BTreeSet<AgentID> = self.active_agents().into_iter().collect();
+        let new_agents: BTreeSet<AgentID> = other.active_agents().into_iter().collect();
+        let mut moved_agents = Vec::new();
+        for id in old_agents.intersection(&new_agents) {
+            if let (Some(pt1), Some(pt2)) = (
+                self.canonical_pt_for_agent(*id, map),
+                other.canonical_pt_for_agent(*id, map),
+            ) {
+                if pt1 != pt2 {
+                    moved_agents.push((*id, pt1, pt2));
+                }
+            }
+        }
+
+        let old_parked: BTreeSet<CarID> = self.parking.all_parked_cars().into_iter().collect();
+        let new_parked: BTreeSet<CarID> = other.parking.all_parked_cars().into_iter().collect();
+
+        SnapshotDiff {
+            time_delta: other.time - self.time,
+            new_agents: new_agents.difference(&old_agents).cloned().collect(),
+            removed_agents: old_agents.difference(&new_agents).cloned().collect(),
+            moved_agents,
+            new_parked_cars: new_parked.difference(&old_parked).cloned().collect(),
+            unparked_cars: old_parked.difference(&new_parked).cloned().collect(),
+        }
+    }
+
+    // For each stop, how many cars are parked within walking distance of it, owned by somebody
+    // whose home is far away by car? Somebody's home is assumed to be where their first trip
+    // starts.
+    pub fn compute_park_and_ride_demand(
+        &self,
+        stops: &[BusStopID],
+        walk_threshold: Distance,
+        drive_threshold: Duration,
+        map: &Map,
+    ) -> BTreeMap<BusStopID, usize> {
+        // Everywhere within walking distance of each stop, found once
+        let reachable: Vec<(BusStopID, Position, HashMap<IntersectionID, Distance>)> = stops
+            .iter()
+            .map(|bs| {
+                let pos = map.get_bs(*bs).sidewalk_pos;
+                (
+                    *bs,
+                    pos,
+                    connectivity::walking_distances(map, pos, walk_threshold),
+                )
+            })
+            .collect();
+
+        let mut demand: BTreeMap<BusStopID, usize> = stops.iter().map(|bs| (*bs, 0)).collect();
+        for id in self.parking.all_parked_cars() {
+            let parked_car = self.parking.lookup_parked_car(id).unwrap();
+            let sidewalk_pos = self.parking.spot_to_sidewalk_pos(parked_car.spot, map);
+            let near_stops: Vec<BusStopID> = reachable
+                .iter()
+                .filter(|(_, pos, dists)| {
+                    connectivity::walking_distance_to(map, dists, *pos, sidewalk_pos)
+                        .map(|d| d <= walk_threshold)
+                        .unwrap_or(false)
+                })
+                .map(|(bs, _, _)| *bs)
+                .collect();
+            if near_stops.is_empty() {
+                continue;
+            }
+
+            let home = match parked_car
+                .vehicle
+                .owner
+                .and_then(|p| self.get_person(p).trips.get(0).cloned())
+                .map(|t| self.trips.trip_info(t).1)
+            {
+                Some(TripEndpoint::Bldg(b)) => b,
+                _ => {
+                    continue;
+                }
+            };
+            let driving_time = match map.pathfind(PathRequest {
+                start: Position::new(map.find_driving_lane_near_building(home), Distance::ZERO),
+                end: self
+                    .parking
+                    .spot_to_driving_pos(parked_car.spot, &parked_car.vehicle, map),
+                constraints: PathConstraints::Car,
+            }) {
+                Some(path) => path
+                    .get_steps()
+                    .iter()
+                    .map(|step| {
+                        let t = step.as_traversable();
+                        t.length(map) / t.speed_limit(map)
+                    })
+                    .sum::<Duration>(),
+                None => {
+                    continue;
+                }
+            };
+            if driving_time <= drive_threshold {
+                continue;
+            }
+            for bs in near_stops {
+                *demand.get_mut(&bs).unwrap() += 1;
+            }
+        }
+        demand
+    }
+
+    // What happens if some lanes become impassable? Doesn't modify the map or the simulation.
+    // Buildings are isolated if they were reachable by car before, but aren't anymore. Trips are
+    // disconnected if their current path crosses a flooded lane; an alternative route is
+    // available if the rest of the trip is still reachable without those lanes.
+    pub fn flood_risk_analysis(&self, flood_lanes: Vec<LaneID>, map: &Map) -> FloodImpact {
+        let closed: HashSet<LaneID> = flood_lanes.into_iter().collect();
+
+        let (before, _) = connectivity::find_scc(map, PathConstraints::Car);
+        let (after, _) = connectivity::find_scc_without(map, PathConstraints::Car, &closed);
+        let mut isolated_buildings = Vec::new();
+        for b in map.all_buildings() {
+            let l = map.find_driving_lane_near_building(b.id);
+            if before.contains(&l) && !after.contains(&l) {
+                isolated_buildings.push(b.id);
+            }
+        }
+
+        let mut disconnected_trips = Vec::new();
+        let mut alternative_routes_available = 0;
+        // Lazily calculated per PathConstraints
+        let mut reachable: Vec<(PathConstraints, HashSet<LaneID>)> = Vec::new();
+        for a in self.active_agents() {
+            let constraints = match a {
+                AgentID::Car(c) => c.1.to_constraints(),
+                AgentID::Pedestrian(_) => PathConstraints::Pedestrian,
+                // Covered by the bus itself
+                AgentID::BusPassenger(_, _) => {
+                    continue;
+                }
+            };
+            let path = match self.get_path(a) {
+                Some(p) => p,
+                None => {
+                    continue;
+                }
+            };
+            let lanes: Vec<LaneID> = path
+                .get_steps()
+                .iter()
+                .filter_map(|step| match step.as_traversable() {
+                    Traversable::Lane(l) => Some(l),
+                    Traversable::Turn(_) => None,
+                })
+                .collect();
+            if !lanes.iter().any(|l| closed.contains(l)) {
+                continue;
+            }
+            if let Some(trip) = self.agent_to_trip(a) {
+                disconnected_trips.push(trip);
+            }
+
+            if !reachable.iter().any(|(c, _)| *c == constraints) {
+                reachable.push((
+                    constraints,
+                    connectivity::find_scc_without(map, constraints, &closed).0,
+                ));
+            }
+            let still_reachable = &reachable.iter().find(|(c, _)| *c == constraints).unwrap().1;
+            if still_reachable.contains(&lanes[0])
+                && still_reachable.contains(lanes.last().unwrap())
+            {
+                alternative_routes_available += 1;
+            }
+        }
+
+        FloodImpact {
+            isolated_buildings,
+            disconnected_trips,
+            alternative_routes_available,
+        }
+    }
+
+    // How much longer does one agent's trip take because of another agent? Runs two copies of the
+    // entire simulation to completion, one without from_agent, so this is very expensive. None if
+    // agent isn't on a trip, from_agent can't be removed, or the trip doesn't finish in both.
+    // Positive means from_agent slowed down agent.
+    pub fn get_induced_delay(
+        &self,
+        agent: AgentID,
+        from_agent: AgentID,
+        map: &Map,
+    ) -> Option<Duration> {
+        let trip = self.agent_to_trip(agent)?;
+        let mut without = self.clone();
+        without.despawn_agent(from_agent, map).ok()?;
+        let mut with = self.clone();
+
+        with.run_until_done(map, |_, _| {}, None);
+        without.run_until_done(map, |_, _| {}, None);
+        let (time_with, _) = with.finished_trip_time(trip)?;
+        let (time_without, _) = without.finished_trip_time(trip)?;
+        Some(time_with - time_without)
+    }
+
+    // How much more traffic gets through after turning a lane into a driving lane? Runs the
+    // scenario from scratch to completion with and without the change, using the same RNG seed, so
+    // this is very expensive. The relative increase in finished trips is divided by the relative
+    // increase in driving capacity on the lane's road. Negative means the new lane made things
+    // worse, like Braess's paradox. The edited copy of the map is loaded from disk; the map passed
+    // in isn't modified.
+    pub fn get_induced_demand_elasticity(
+        scenario: &Scenario,
+        opts: &SimOptions,
+        new_lane: LaneID,
+        seed: u64,
+        map: &Map,
+        timer: &mut Timer,
+    ) -> Result<f64, String> {
+        let lane = map.get_l(new_lane);
+        if lane.is_driving() {
+            return Err(format!("{} is already a driving lane", new_lane));
+        }
+        let capacity_before = road_capacity_per_hour(lane.parent, map);
+        if capacity_before == 0.0 {
+            return Err(format!(
+                "{} has no driving capacity to compare to",
+                lane.parent
+            ));
+        }
+        let capacity_increase = LANE_CAPACITY_PER_HOUR / capacity_before;
+
+        let run = |map: &Map, timer: &mut Timer| {
+            let mut sim = Sim::new(map, opts.clone(), timer);
+            let mut rng = XorShiftRng::seed_from_u64(seed);
+            scenario.instantiate(&mut sim, map, &mut rng, timer);
+            sim.run_until_done(map, |_, _| {}, None);
+            sim.analytics
+                .finished_trips
+                .iter()
+                .filter(|(_, _, mode, _)| mode.is_some())
+                .count() as f64
+        };
+
+        let baseline_trips = run(map, timer);
+        if baseline_trips == 0.0 {
+            return Err("no trips finish in the baseline".to_string());
+        }
+
+        let mut edited = Map::new(abstutil::path_map(map.get_name()), timer);
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(EditCmd::ChangeLaneType {
+            id: new_lane,
+            lt: LaneType::Driving,
+            orig_lt: lane.lane_type,
+        });
+        edited.apply_edits(edits, timer);
+        edited.recalculate_pathfinding_after_edits(timer);
+        let new_trips = run(&edited, timer);
+
+        Ok((new_trips - baseline_trips) / baseline_trips / capacity_increase)
+    }
+
+    // For each value, runs a copy of the simulation to completion with the parameter changed, and
+    // measures the whole network. Very expensive.
+    pub fn run_sensitivity_analysis(
+        &self,
+        param: SensitivityParam,
+        range: &[f64],
+        map: &Map,
+        timer: &mut Timer,
+    ) -> Vec<(f64, NetworkFlowStats)> {
+        let requests: Vec<(f64, Sim)> = range.iter().map(|x| (*x, self.clone())).collect();
+        timer.parallelize("run sensitivity analysis", requests, |(value, mut sim)| {
+            match param {
+                SensitivityParam::DepartureRate => {
+                    sim.scheduler.scale_trip_departures(sim.time, value);
+                }
+                SensitivityParam::PlatoonGap => {
+                    sim.enable_platooning(Distance::meters(value));
+                }
+                SensitivityParam::ParkingSearchOvershoot => {
+                    sim.set_max_parking_search(Some(Distance::meters(value)));
+                }
+            }
+            sim.run_until_done(map, |_, _| {}, None);
+            (value, sim.get_network_flow_stats(map))
+        })
+    }
+
+    // Would a new road between two intersections help? Runs the scenario from scratch to
+    // completion with and without the road, using the same RNG seed, so this is very expensive. The
+    // new road is a straight line with the same lanes in both directions. Since map edits can't add
+    // roads, the copy of the map with the road is built from the raw map on disk; the map passed in
+    // isn't modified.
+    pub fn run_what_if_new_road(
+        scenario: &Scenario,
+        opts: &SimOptions,
+        from: IntersectionID,
+        to: IntersectionID,
+        lanes: Vec<LaneType>,
+        seed: u64,
+        map: &Map,
+        timer: &mut Timer,
+    ) -> Result<WhatIfResult, String> {
+        let raw: RawMap =
+            abstutil::maybe_read_binary(abstutil::path_raw_map(map.get_name()), timer)
+                .map_err(|err| format!("couldn't load the raw map: {}", err))?;
+        what_if_new_road(scenario, opts, raw, from, to, lanes, seed, map, timer)
+    }
+
+    // How well does the driving network hold up without some roads? Samples random pairs of
+    // buildings.
+    pub fn get_network_resilience(
+        &self,
+        removed_roads: &[RoadID],
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> ResilienceScore {
+        let baseline = sample_building_pairs(map, rng);
+        resilience_score(&baseline, &removed_roads.iter().cloned().collect(), map)
+    }
+
+    // Rank every road by how much removing it hurts the network, worst first. The impact counts
+    // the fraction of trips that become impossible, plus the extra detour for the rest. This
+    // reruns every sampled pathfinding request once per road, so it's split across threads.
+    pub fn most_critical_roads(
+        &self,
+        map: &Map,
+        top_n: usize,
+        rng: &mut XorShiftRng,
+        timer: &mut Timer,
+    ) -> Vec<(RoadID, f64)> {
+        let baseline = sample_building_pairs(map, rng);
+        let baseline = &baseline;
+        let requests = map.all_roads().iter().map(|r| r.id).collect();
+        let mut results = timer.parallelize("find critical roads", requests, |r| {
+            let mut closed = HashSet::new();
+            closed.insert(r);
+            let score = resilience_score(baseline, &closed, map);
+            (
+                r,
+                (1.0 - score.reachable_building_pairs_pct) + (score.mean_detour_ratio - 1.0),
+            )
+        });
+        results.sort_by(|(_, x1), (_, x2)| x2.partial_cmp(x1).unwrap());
+        results.truncate(top_n);
+        results
+    }
+
+    // Root-mean-square error between the simulated throughput so far and observed counts, over
+    // every observed road that exists in this map. Used to tune demand generation.
+    pub fn calibrate_against_counts(&self, observed: &BTreeMap<RoadID, usize>, map: &Map) -> f64 {
+        let errors = self.calibrate_report(observed, map);
+        if errors.is_empty() {
+            return 0.0;
+        }
+        let sum_sq: f64 = errors.values().map(|e| e * e).sum();
+        (sum_sq / (errors.len() as f64)).sqrt()
+    }
+
+    // Per road, simulated minus observed count
+    pub fn calibrate_report(
+        &self,
+        observed: &BTreeMap<RoadID, usize>,
+        map: &Map,
+    ) -> BTreeMap<RoadID, f64> {
+        observed
+            .iter()
+            .filter(|(r, _)| map.maybe_get_r(**r).is_some())
+            .map(|(r, cnt)| {
+                let simulated = self.analytics.road_thruput.total_for(*r);
+                (*r, (simulated as f64) - (*cnt as f64))
+            })
+            .collect()
+    }
+}
+
+// TODO Car ownership is only decided when generating people from census data, and bike-share docks
+// aren't modeled, so neither can be varied on a running simulation yet.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SensitivityParam {
+    // Multiplies how quickly the remaining trips start
+    DepartureRate,
+    // The gap between platooning cars, in meters
+    PlatoonGap,
+    // How far past their destination lane drivers search for parking before giving up, in meters
+    ParkingSearchOvershoot,
+}
+
+pub struct SnapshotDiff {
+    // Can be negative if the other sim is earlier
+    pub time_delta: Duration,
+    pub new_agents: Vec<AgentID>,
+    pub removed_agents: Vec<AgentID>,
+    // (agent, old position, new position). Agents on turns don't have a lane Position, so use
+    // their canonical point.
+    pub moved_agents: Vec<(AgentID, Pt2D, Pt2D)>,
+    pub new_parked_cars: Vec<CarID>,
+    pub unparked_cars: Vec<CarID>,
+}
+
+pub struct WhatIfResult {
+    // In seconds, the change in the mean duration of finished trips. Negative means faster.
+    pub mean_trip_time_delta: f64,
+    // The change in the number of finished trips
+    pub throughput_delta: f64,
+    // Trips whose duration changed, or that only finished in one of the runs
+    pub affected_trips: usize,
+}
+
+pub struct FloodImpact {
+    pub isolated_buildings: Vec<BuildingID>,
+    pub disconnected_trips: Vec<TripID>,
+    // How many of the disconnected trips could still reach their destination some other way
+    pub alternative_routes_available: usize,
+}
+
+pub struct ResilienceScore {
+    // Of the sampled pairs that were connected before
+    pub reachable_building_pairs_pct: f64,
+    // For pairs still connected, the new distance divided by the original. 1.0 means no detours.
+    pub mean_detour_ratio: f64,
+}
+
+// Adds a road to the raw map, builds it, and compares running the scenario on both maps. The new
+// road's OSM ID sorts after every other road, so existing road and lane IDs don't change and the
+// scenario still refers to the same places.
+fn what_if_new_road(
+    scenario: &Scenario,
+    opts: &SimOptions,
+    mut raw: RawMap,
+    from: IntersectionID,
+    to: IntersectionID,
+    lanes: Vec<LaneType>,
+    seed: u64,
+    map: &Map,
+    timer: &mut Timer,
+) -> Result<WhatIfResult, String> {
+    if from == to {
+        return Err(format!("a new road can't start and end at {}", from));
+    }
+    if !lanes.contains(&LaneType::Driving) {
+        return Err("the new road needs a driving lane".to_string());
+    }
+    let (i1, i2) = (map.get_i(from).orig_id, map.get_i(to).orig_id);
+    if !raw.intersections.contains_key(&i1) || !raw.intersections.contains_key(&i2) {
+        return Err(format!("the raw map doesn't match {}", map.get_name()));
+    }
+    if raw
+        .roads
+        .keys()
+        .any(|r| (r.i1 == i1 && r.i2 == i2) || (r.i1 == i2 && r.i2 == i1))
+    {
+        return Err(format!("{} and {} are already connected", from, to));
+    }
+
+    let id = OriginalRoad {
+        osm_way_id: raw.roads.keys().map(|r| r.osm_way_id).max().unwrap_or(0) + 1,
+        i1,
+        i2,
+    };
+    let mut osm_tags = BTreeMap::new();
+    osm_tags.insert(osm::SYNTHETIC.to_string(), "true".to_string());
+    osm_tags.insert(
+        osm::SYNTHETIC_LANES.to_string(),
+        RoadSpec {
+            fwd: lanes.clone(),
+            back: lanes,
+        }
+        .to_string(),
+    );
+    osm_tags.insert(osm::ENDPT_FWD.to_string(), "true".to_string());
+    osm_tags.insert(osm::ENDPT_BACK.to_string(), "true".to_string());
+    osm_tags.insert(osm::OSM_WAY_ID.to_string(), id.osm_way_id.to_string());
+    osm_tags.insert(osm::NAME.to_string(), "New road".to_string());
+    osm_tags.insert(osm::MAXSPEED.to_string(), "25 mph".to_string());
+    raw.roads.insert(
+        id,
+        RawRoad {
+            center_points: vec![raw.intersections[&i1].point, raw.intersections[&i2].point],
+            osm_tags,
+            turn_restrictions: Vec::new(),
+            complicated_turn_restrictions: Vec::new(),
+        },
+    );
+    let mut edited = Map::create_from_raw(raw, true, timer);
+    if !map.get_edits().commands.is_empty() {
+        edited.apply_edits(map.get_edits().clone(), timer);
+        edited.recalculate_pathfinding_after_edits(timer);
+    }
+
+    let before = finished_trip_times(scenario, opts, seed, map, timer);
+    if before.is_empty() {
+        return Err("no trips finish in the baseline".to_string());
+    }
+    let after = finished_trip_times(scenario, opts, seed, &edited, timer);
+
+    let mean = |times: &BTreeMap<TripID, Duration>| {
+        if times.is_empty() {
+            0.0
+        } else {
+            times.values().map(|dt| dt.inner_seconds()).sum::<f64>() / (times.len() as f64)
+        }
+    };
+    let all_trips: BTreeSet<&TripID> = before.keys().chain(after.keys()).collect();
+    Ok(WhatIfResult {
+        mean_trip_time_delta: mean(&after) - mean(&before),
+        throughput_delta: (after.len() as f64) - (before.len() as f64),
+        affected_trips: all_trips
+            .into_iter()
+            .filter(|t| before.get(t) != after.get(t))
+            .count(),
+    })
+}
+
+// Runs the scenario from scratch to completion, returning how long each finished trip took.
+fn finished_trip_times(
+    scenario: &Scenario,
+    opts: &SimOptions,
+    seed: u64,
+    map: &Map,
+    timer: &mut Timer,
+) -> BTreeMap<TripID, Duration> {
+    let mut sim = Sim::new(map, opts.clone(), timer);
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+    scenario.instantiate(&mut sim, map, &mut rng, timer);
+    sim.run_until_done(map, |_, _| {}, None);
+    sim.analytics
+        .finished_trips
+        .iter()
+        .filter(|(_, _, mode, _)| mode.is_some())
+        .map(|(_, trip, _, dt)| (*trip, *dt))
+        .collect()
+}
+
+// Returns (start lane, end lane, baseline distance) for pairs of buildings connected by driving.
+fn sample_building_pairs(map: &Map, rng: &mut XorShiftRng) -> Vec<(LaneID, LaneID, Distance)> {
+    let mut pairs = Vec::new();
+    if map.all_buildings().is_empty() {
+        return pairs;
+    }
+    for _ in 0..NUM_RESILIENCE_SAMPLES {
+        let b1 = map.all_buildings().choose(rng).unwrap().id;
+        let b2 = map.all_buildings().choose(rng).unwrap().id;
+        let l1 = map.find_driving_lane_near_building(b1);
+        let l2 = map.find_driving_lane_near_building(b2);
+        if let Some(dist) = connectivity::shortest_distance_without(
+            map,
+            PathConstraints::Car,
+            l1,
+            l2,
+            &HashSet::new(),
+        ) {
+            pairs.push((l1, l2, dist));
+        }
+    }
+    pairs
+}
+
+fn resilience_score(
+    baseline: &[(LaneID, LaneID, Distance)],
+    closed: &HashSet<RoadID>,
+    map: &Map,
+) -> ResilienceScore {
+    let mut reachable = 0;
+    let mut sum_detour = 0.0;
+    for (l1, l2, orig_dist) in baseline {
+        if let Some(dist) =
+            connectivity::shortest_distance_without(map, PathConstraints::Car, *l1, *l2, closed)
+        {
+            reachable += 1;
+            sum_detour += dist / *orig_dist;
+        }
+    }
+    ResilienceScore {
+        reachable_building_pairs_pct: if baseline.is_empty() {
+            1.0
+        } else {
+            (reachable as f64) / (baseline.len() as f64)
+        },
+        mean_detour_ratio: if reachable == 0 {
+            1.0
+        } else {
+            sum_detour / (reachable as f64)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IndividTrip, PersonID, PersonSpec, SpawnTrip, TripMode};
+    use geom::Time;
+    use map_model::raw::OriginalIntersection;
+    use map_model::IntersectionType;
+
+    #[test]
+    fn test_most_critical_road_is_the_bottleneck() {
+        let spec = RoadSpec {
+            fwd: vec![LaneType::Driving, LaneType::Sidewalk],
+            back: vec![LaneType::Driving, LaneType::Sidewalk],
+        };
+        // A 4x2 grid, minus the bottom road between the middle columns. The top road between the
+        // middle columns is the only link between the two halves.
+        let mut raw = RawMap::synthetic_grid(4, 2, &spec);
+        raw.roads.remove(&OriginalRoad {
+            osm_way_id: 6,
+            i1: OriginalIntersection { osm_node_id: 6 },
+            i2: OriginalIntersection { osm_node_id: 7 },
+        });
+        let map = Map::create_from_raw(raw, true, &mut Timer::throwaway());
+        let bottleneck = map
+            .all_roads()
+            .iter()
+            .find(|r| r.orig_id.osm_way_id == 4)
+            .unwrap()
+            .id;
+
+        let sim = Sim::new(&map, SimOptions::new("test"), &mut Timer::throwaway());
+        let mut rng = XorShiftRng::from_seed([0; 16]);
+        assert_eq!(
+            sim.get_network_resilience(&[], &map, &mut rng)
+                .reachable_building_pairs_pct,
+            1.0
+        );
+        assert!(
+            sim.get_network_resilience(&[bottleneck], &map, &mut rng)
+                .reachable_building_pairs_pct
+                < 1.0
+        );
+
+        let ranked = sim.most_critical_roads(&map, 1, &mut rng, &mut Timer::throwaway());
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].0, bottleneck);
+        assert!(ranked[0].1 > 0.0);
+    }
+
+    // A straight street between two borders, except the middle block is missing, so traffic has
+    // to detour around it.
+    fn detour_raw_map() -> RawMap {
+        let spec = RoadSpec {
+            fwd: vec![LaneType::Driving, LaneType::Sidewalk],
+            back: vec![LaneType::Driving, LaneType::Sidewalk],
+        };
+        let mut raw = RawMap::synthetic_grid(6, 2, &spec);
+        for (id, i1, i2) in vec![(2, 1, 7), (3, 7, 8), (7, 3, 4), (15, 11, 12), (16, 6, 12)] {
+            raw.roads.remove(&OriginalRoad {
+                osm_way_id: id,
+                i1: OriginalIntersection { osm_node_id: i1 },
+                i2: OriginalIntersection { osm_node_id: i2 },
+            });
+        }
+        for id in vec![7, 12] {
+            raw.intersections
+                .remove(&OriginalIntersection { osm_node_id: id });
+        }
+        for id in vec![1, 6] {
+            raw.intersections
+                .get_mut(&OriginalIntersection { osm_node_id: id })
+                .unwrap()
+                .intersection_type = IntersectionType::Border;
+        }
+        raw
+    }
+
+    #[test]
+    fn test_what_if_new_road_shortcut() {
+        let map = Map::create_from_raw(detour_raw_map(), true, &mut Timer::throwaway());
+        let i = |id| map.find_i_by_osm_id(id).unwrap();
+        let mut scenario = Scenario::empty(&map, "shortcut");
+        for idx in 0..10 {
+            scenario.people.push(PersonSpec {
+                id: PersonID(idx),
+                orig_id: None,
+                trips: vec![IndividTrip {
+                    depart: Time::START_OF_DAY + Duration::seconds(10.0 * (idx as f64)),
+                    trip: SpawnTrip::new(
+                        TripEndpoint::Border(i(1), None),
+                        TripEndpoint::Border(i(6), None),
+                        TripMode::Drive,
+                        &map,
+                    ),
+                    cancelled: false,
+                }],
+            });
+        }
+
+        // Fill in the missing block
+        let result = what_if_new_road(
+            &scenario,
+            &SimOptions::new("test"),
+            detour_raw_map(),
+            i(3),
+            i(4),
+            vec![LaneType::Driving, LaneType::Sidewalk],
+            42,
+            &map,
+            &mut Timer::throwaway(),
+        )
+        .unwrap();
+        assert!(result.mean_trip_time_delta < 0.0);
+        assert_eq!(result.throughput_delta, 0.0);
+        assert_eq!(result.affected_trips, 10);
+
+        // The streets are already connected
+        assert!(what_if_new_road(
+            &scenario,
+            &SimOptions::new("test"),
+            detour_raw_map(),
+            i(2),
+            i(3),
+            vec![LaneType::Driving],
+            42,
+            &map,
+            &mut Timer::throwaway(),
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use super::Sim;
+use crate::agent_positions::AgentPositions;
+use crate::{AgentPosition, TripEndpoint, TripMode};
+use geom::{Pt2D, Time};
+use map_model::{BuildingID, BuildingType, Map};
+use prost::Message;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+// Exporting data for other tools
+impl Sim {
+    // Straight lines between the buildings where finished walking trips started and ended,
+    // regardless of the route taken. Returns (from, to, number of trips), only for pairs with at
+    // least min_trips.
+    pub fn get_pedestrian_desire_lines(
+        &self,
+        map: &Map,
+        min_trips: usize,
+    ) -> Vec<(Pt2D, Pt2D, usize)> {
+        self.desire_lines_by_bldg(min_trips)
+            .into_iter()
+            .map(|((b1, b2), cnt)| {
+                (
+                    map.get_b(b1).polygon.center(),
+                    map.get_b(b2).polygon.center(),
+                    cnt,
+                )
+            })
+            .collect()
+    }
+
+    fn desire_lines_by_bldg(&self, min_trips: usize) -> BTreeMap<(BuildingID, BuildingID), usize> {
+        let mut counts: BTreeMap<(BuildingID, BuildingID), usize> = BTreeMap::new();
+        for (_, trip, mode, _) in &self.analytics.finished_trips {
+            if *mode != Some(TripMode::Walk) {
+                continue;
+            }
+            // Trips to or from a border don't say much about what's missing inside the map
+            if let (_, TripEndpoint::Bldg(b1), TripEndpoint::Bldg(b2), _) =
+                self.trips.trip_info(*trip)
+            {
+                *counts.entry((b1, b2)).or_insert(0) += 1;
+            }
+        }
+        counts.retain(|_, cnt| *cnt >= min_trips);
+        counts
+    }
+
+    // Writes get_pedestrian_desire_lines as GeoJSON, with one LineString feature per pair.
+    pub fn get_pedestrian_desire_lines_geojson(
+        &self,
+        path: &str,
+        map: &Map,
+        min_trips: usize,
+    ) -> Result<(), std::io::Error> {
+        let gps_bounds = map.get_gps_bounds();
+        let features: Vec<String> = self
+            .desire_lines_by_bldg(min_trips)
+            .into_iter()
+            .map(|((b1, b2), cnt)| {
+                let pt1 = map.get_b(b1).polygon.center().forcibly_to_gps(gps_bounds);
+                let pt2 = map.get_b(b2).polygon.center().forcibly_to_gps(gps_bounds);
+                format!(
+                    "{{\"type\":\"Feature\",\"geometry\":{{\"type\":\"LineString\",\"coordinates\":\
+                     [[{},{}],[{},{}]]}},\"properties\":{{\"from\":{},\"to\":{},\"trips\":{}}}}}",
+                    pt1.x(),
+                    pt1.y(),
+                    pt2.x(),
+                    pt2.y(),
+                    b1.0,
+                    b2.0,
+                    cnt
+                )
+            })
+            .collect();
+        let mut f = std::fs::File::create(path)?;
+        writeln!(
+            f,
+            "{{\"type\":\"FeatureCollection\",\"features\":[{}]}}",
+            features.join(",")
+        )?;
+        Ok(())
+    }
+
+    // Writes every person's trips as a MATSim population file. Each trip becomes one leg between
+    // activities at its endpoints; intermediate walking to and from vehicles isn't included.
+    // Coordinates are longitude and latitude.
+    pub fn export_matsim_plans(&self, path: &str, map: &Map) -> Result<(), std::io::Error> {
+        let gps_bounds = map.get_gps_bounds();
+        let act = |endpt: &TripEndpoint, end_time: Option<Time>| {
+            let (act_type, pt) = match endpt {
+                TripEndpoint::Bldg(b) => {
+                    let bldg = map.get_b(*b);
+                    let act_type = match bldg.bldg_type() {
+                        BuildingType::Residential => "home",
+                        BuildingType::Commercial => "work",
+                    };
+                    (act_type, bldg.polygon.center())
+                }
+                TripEndpoint::Border(i, _) => ("border", map.get_i(*i).polygon.center()),
+            };
+            let gps = pt.forcibly_to_gps(gps_bounds);
+            let end_time = end_time
+                .map(|t| {
+                    let (hours, minutes, seconds, _) = t.get_parts();
+                    format!(" end_time=\"{:02}:{:02}:{:02}\"", hours, minutes, seconds)
+                })
+                .unwrap_or_else(String::new);
+            format!(
+                "      <act type=\"{}\" x=\"{}\" y=\"{}\"{} />",
+                act_type,
+                gps.x(),
+                gps.y(),
+                end_time
+            )
+        };
+
+        let mut f = std::fs::File::create(path)?;
+        writeln!(f, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+        writeln!(
+            f,
+            "<!DOCTYPE population SYSTEM \"http://www.matsim.org/files/dtd/population_v6.dtd\">"
+        )?;
+        writeln!(f, "<population>")?;
+        for person in self.trips.get_all_people() {
+            if person.trips.is_empty() {
+                continue;
+            }
+            writeln!(f, "  <person id=\"{}\">", person.id.0)?;
+            writeln!(f, "    <plan selected=\"yes\">")?;
+            let trips: Vec<(Time, TripEndpoint, TripEndpoint, TripMode)> = person
+                .trips
+                .iter()
+                .map(|t| self.trips.trip_info(*t))
+                .collect();
+            for (idx, (departure, start, _, mode)) in trips.iter().enumerate() {
+                if idx == 0 {
+                    writeln!(f, "{}", act(start, Some(*departure)))?;
+                }
+                let mode = match mode {
+                    TripMode::Walk => "walk",
+                    TripMode::Bike => "bike",
+                    TripMode::Transit => "pt",
+                    TripMode::Drive => "car",
+                };
+                writeln!(f, "      <leg mode=\"{}\" />", mode)?;
+                writeln!(
+                    f,
+                    "{}",
+                    act(&trips[idx].2, trips.get(idx + 1).map(|(t, _, _, _)| *t))
+                )?;
+            }
+            writeln!(f, "    </plan>")?;
+            writeln!(f, "  </person>")?;
+        }
+        writeln!(f, "</population>")?;
+        Ok(())
+    }
+
+    // Writes the position of every active agent in the Protocol Buffers format described by
+    // agent_positions.proto. Much more compact than JSON.
+    pub fn export_agent_positions_protobuf(
+        &self,
+        path: &str,
+        map: &Map,
+    ) -> Result<(), std::io::Error> {
+        let mut positions = AgentPositions::new(self.time);
+        for id in self.active_agents() {
+            if let Some(pt) = self.canonical_pt_for_agent(id, map) {
+                positions.agents.push(AgentPosition::new(id, pt));
+            }
+        }
+        let mut buf = Vec::new();
+        positions
+            .encode(&mut buf)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+        std::fs::write(path, buf)
+    }
+
+    pub fn load_agent_positions_protobuf(path: &str) -> Result<Vec<AgentPosition>, std::io::Error> {
+        let buf = std::fs::read(path)?;
+        let positions = AgentPositions::decode(&buf[..])
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        Ok(positions.agents)
+    }
+}
@@ -0,0 +1,2667 @@
+mod access;
+mod environment;
+mod experiments;
+mod export;
+mod parking_stats;
+mod safety;
+mod signals;
+mod traffic;
+mod transit_stats;
+mod trip_stats;
+
+pub use self::experiments::{
+    FloodImpact, ResilienceScore, SensitivityParam, SnapshotDiff, WhatIfResult,
+};
+pub use self::parking_stats::CurbsideStats;
+pub use self::signals::CorridorStats;
+pub use self::traffic::{NetworkFlowStats, RouteChoiceStats};
+pub use self::transit_stats::{BusStopDemand, StopSpacingStats};
+pub use self::trip_stats::TripTimeComponents;
+use crate::{
+    curb_pos, AgentID, AlertLocation, Analytics, CarID, Command, CreateCar, DrawCarInput,
+    DrawPedCrowdInput, DrawPedestrianInput, DrivingGoal, DrivingSimState, Event, GetDrawAgents,
+    IncidentID, IntersectionSimState, OrigPersonID, PandemicModel, ParkedCar, ParkingGarage,
+    ParkingSimState, ParkingSpot, PedestrianID, Person, PersonID, PersonState, Router, Scenario,
+    Scheduler, SidewalkPOI, SidewalkSpot, SpeedOverrideSource, TransitSimState, TripEndpoint,
+    TripID, TripManager, TripMode, TripPhaseType, TripResult, TripSpawner, TripSpec, UnzoomedAgent,
+    Vehicle, VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH, MIN_CAR_LENGTH,
+};
+use abstutil::{deserialize_btreemap, serialize_btreemap, Timer};
+use derivative::Derivative;
+use geom::{Distance, Duration, Histogram, PolyLine, Pt2D, Speed, Statistic, Time};
+use instant::Instant;
+use map_model::{
+    BuildingID, BusRoute, BusRouteID, IntersectionID, LaneID, LaneType, Map, ParkingLotID, Path,
+    PathConstraints, PathRequest, PathStep, Position, RoadID, Traversable,
+};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::panic;
+
+// TODO Do something else.
+const BLIND_RETRY_TO_SPAWN: Duration = Duration::const_seconds(5.0);
+
+#[derive(Serialize, Deserialize, Clone, Derivative)]
+#[derivative(PartialEq)]
+pub struct Sim {
+    driving: DrivingSimState,
+    parking: ParkingSimState,
+    walking: WalkingSimState,
+    intersections: IntersectionSimState,
+    transit: TransitSimState,
+    trips: TripManager,
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip_serializing, skip_deserializing)]
+    pandemic: Option<PandemicModel>,
+    scheduler: Scheduler,
+    time: Time,
+    school_zones: Vec<SchoolZone>,
+    // The lane, how bad it is, and when it clears
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    active_incidents: BTreeMap<IncidentID, (LaneID, IncidentSeverity, Time)>,
+    next_incident_id: usize,
+    // How much worse each change between vehicles feels, beyond the time it takes
+    transfer_penalty: Duration,
+    // From forecast_trip_time
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    travel_time_forecasts: BTreeMap<TripID, Duration>,
+
+    // TODO Reconsider these
+    pub(crate) map_name: String,
+    pub(crate) edits_name: String,
+    // Some tests deliberately set different scenario names for comparisons.
+    // TODO Maybe get rid of this, now that savestates aren't used
+    #[derivative(PartialEq = "ignore")]
+    run_name: String,
+    #[derivative(PartialEq = "ignore")]
+    step_count: usize,
+
+    // Don't serialize, to reduce prebaked savestate size. Analytics are saved once covering the
+    // full day and can be trimmed to any time.
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip_serializing, skip_deserializing)]
+    analytics: Analytics,
+
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip_serializing, skip_deserializing)]
+    alerts: AlertHandler,
+
+    // Every event ever dispatched, in order, so external tools can catch up without watching each
+    // step. Callers are responsible for pruning this.
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip_serializing, skip_deserializing)]
+    event_archive: Vec<(Time, Event)>,
+
+    // Per mode, from count_unreachable_buildings
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip_serializing, skip_deserializing)]
+    unreachable_buildings: BTreeMap<TripMode, usize>,
+}
+
+#[derive(Clone)]
+pub struct SimOptions {
+    pub run_name: String,
+    pub use_freeform_policy_everywhere: bool,
+    pub dont_block_the_box: bool,
+    pub recalc_lanechanging: bool,
+    pub break_turn_conflict_cycles: bool,
+    pub enable_pandemic_model: Option<XorShiftRng>,
+    pub alerts: AlertHandler,
+    pub pathfinding_upfront: bool,
+    // Pedestrians pick one of this many shortest paths, instead of always the shortest
+    pub pedestrian_route_choices: usize,
+    // Emit QueueSpillback events. Checking every time a car stops in a queue is slow.
+    pub detect_queue_spillback: bool,
+    // Emit RoutingOscillation when a car replans more than this many times within the duration
+    pub routing_oscillation: (usize, Duration),
+}
+
+#[derive(Clone)]
+pub enum AlertHandler {
+    // Just print the alert to STDOUT
+    Print,
+    // Print the alert to STDOUT and don't proceed until the UI calls clear_alerts()
+    Block,
+    // Don't do anything
+    Silence,
+}
+
+impl std::default::Default for AlertHandler {
+    fn default() -> AlertHandler {
+        AlertHandler::Print
+    }
+}
+
+impl SimOptions {
+    pub fn new(run_name: &str) -> SimOptions {
+        SimOptions {
+            run_name: run_name.to_string(),
+            use_freeform_policy_everywhere: false,
+            dont_block_the_box: true,
+            recalc_lanechanging: true,
+            break_turn_conflict_cycles: true,
+            enable_pandemic_model: None,
+            alerts: AlertHandler::Print,
+            pathfinding_upfront: false,
+            pedestrian_route_choices: 1,
+            detect_queue_spillback: false,
+            routing_oscillation: (3, Duration::minutes(10)),
+        }
+    }
+}
+
+// Setup
+impl Sim {
+    pub fn new(map: &Map, opts: SimOptions, timer: &mut Timer) -> Sim {
+        let mut scheduler = Scheduler::new();
+        Sim {
+            driving: DrivingSimState::new(
+                map,
+                opts.recalc_lanechanging,
+                opts.detect_queue_spillback,
+                opts.routing_oscillation,
+            ),
+            parking: ParkingSimState::new(map, timer),
+            walking: WalkingSimState::new(),
+            intersections: IntersectionSimState::new(
+                map,
+                &mut scheduler,
+                opts.use_freeform_policy_everywhere,
+                opts.dont_block_the_box,
+                opts.break_turn_conflict_cycles,
+            ),
+            transit: TransitSimState::new(),
+            trips: TripManager::new(opts.pathfinding_upfront, opts.pedestrian_route_choices),
+            pandemic: if let Some(rng) = opts.enable_pandemic_model {
+                Some(PandemicModel::new(rng))
+            } else {
+                None
+            },
+            scheduler,
+            time: Time::START_OF_DAY,
+            school_zones: Vec::new(),
+            active_incidents: BTreeMap::new(),
+            next_incident_id: 0,
+            transfer_penalty: DEFAULT_TRANSFER_PENALTY,
+            travel_time_forecasts: BTreeMap::new(),
+
+            map_name: map.get_name().to_string(),
+            // TODO
+            edits_name: "untitled edits".to_string(),
+            run_name: opts.run_name,
+            step_count: 0,
+            alerts: opts.alerts,
+
+            analytics: Analytics::new(),
+            event_archive: Vec::new(),
+            unreachable_buildings: BTreeMap::new(),
+        }
+    }
+
+    pub fn make_spawner(&self) -> TripSpawner {
+        TripSpawner::new()
+    }
+    pub fn flush_spawner(&mut self, spawner: TripSpawner, map: &Map, timer: &mut Timer) {
+        spawner.finalize(map, &mut self.trips, &mut self.scheduler, timer);
+
+        if let Some(ref mut m) = self.pandemic {
+            m.initialize(self.trips.get_all_people(), &mut self.scheduler);
+        }
+
+        self.dispatch_events(Vec::new(), map, &mut |_| {});
+    }
+
+    pub fn get_free_onstreet_spots(&self, l: LaneID) -> Vec<ParkingSpot> {
+        self.parking.get_free_onstreet_spots(l)
+    }
+
+    // One round of demand-based pricing, like SFpark: raise the hourly price of every parking lane
+    // that's fuller than the target occupancy (from 0 to 1), and lower it on lanes that're
+    // emptier. Lanes without a current price start at DEFAULT_PARKING_PRICE. Returns the new
+    // prices, for the caller to pass in next time.
+    // TODO Drivers don't consider prices yet.
+    pub fn optimize_parking_pricing(
+        &self,
+        current_prices: &BTreeMap<LaneID, f64>,
+        target_utilization: f64,
+    ) -> BTreeMap<LaneID, f64> {
+        let mut prices = BTreeMap::new();
+        for (l, (filled, total)) in self.parking.utilization_by_lane() {
+            if total == 0 {
+                continue;
+            }
+            let utilization = (filled as f64) / (total as f64);
+            let mut price = current_prices
+                .get(&l)
+                .cloned()
+                .unwrap_or(DEFAULT_PARKING_PRICE);
+            if utilization > target_utilization + PARKING_UTILIZATION_TOLERANCE {
+                price += PARKING_PRICE_STEP;
+            } else if utilization < target_utilization - PARKING_UTILIZATION_TOLERANCE {
+                price = (price - PARKING_PRICE_STEP).max(0.0);
+            }
+            prices.insert(l, price);
+        }
+        prices
+    }
+
+    // From 0 to 1, how full is a parking lane right now? 0 for lanes without any parking.
+    pub fn get_park_and_ride_lot_utilization(&self, lot_lane: LaneID) -> f64 {
+        match self.parking.utilization_by_lane().get(&lot_lane) {
+            Some((filled, total)) if *total > 0 => (*filled as f64) / (*total as f64),
+            _ => 0.0,
+        }
+    }
+
+    // Nearly full parking lanes within walking distance of a busy bus stop, which might be worth
+    // adding spots or a garage to.
+    // TODO Distance to the stop is a straight line, not a walking path.
+    pub fn recommend_park_and_ride_expansion(&self, map: &Map) -> Vec<LaneID> {
+        let busy_stops: Vec<Pt2D> = map
+            .all_bus_stops()
+            .values()
+            .filter(|bs| {
+                self.get_bus_stop_demand(bs.id, Duration::hours(1))
+                    .boardings
+                    >= PARK_AND_RIDE_MIN_BOARDINGS_PER_HOUR
+            })
+            .map(|bs| bs.sidewalk_pos.pt(map))
+            .collect();
+        let mut results = Vec::new();
+        for (l, (filled, total)) in self.parking.utilization_by_lane() {
+            if total == 0 || (filled as f64) / (total as f64) <= PARK_AND_RIDE_FULL_UTILIZATION {
+                continue;
+            }
+            let pt = map.get_l(l).lane_center_pts.middle();
+            if busy_stops
+                .iter()
+                .any(|stop| stop.dist_to(pt) <= PARK_AND_RIDE_WALK_DISTANCE)
+            {
+                results.push(l);
+            }
+        }
+        results
+    }
+
+    pub fn get_free_offstreet_spots(&self, b: BuildingID) -> Vec<ParkingSpot> {
+        self.parking.get_free_offstreet_spots(b)
+    }
+
+    pub fn get_free_lot_spots(&self, pl: ParkingLotID) -> Vec<ParkingSpot> {
+        self.parking.get_free_lot_spots(pl)
+    }
+
+    // (Filled, available)
+    pub fn get_all_parking_spots(&self) -> (Vec<ParkingSpot>, Vec<ParkingSpot>) {
+        self.parking.get_all_parking_spots()
+    }
+
+    // Only for lanes that're part of a garage. Counts all stories.
+    pub fn get_parking_garage_capacity(&self, l: LaneID) -> Option<usize> {
+        self.parking.get_parking_garage_capacity(l)
+    }
+
+    pub fn get_all_garages(&self) -> &Vec<ParkingGarage> {
+        self.parking.get_all_garages()
+    }
+
+    pub fn get_garage_walk_threshold(&self) -> Distance {
+        self.parking.get_garage_walk_threshold()
+    }
+
+    // Also returns the start distance of the building. TODO Do that in the Path properly.
+    pub fn walking_path_to_nearest_parking_spot(
+        &self,
+        map: &Map,
+        b: BuildingID,
+    ) -> Option<(Path, Distance)> {
+        let vehicle = Vehicle {
+            id: CarID(0, VehicleType::Car),
+            owner: None,
+            vehicle_type: VehicleType::Car,
+            length: MIN_CAR_LENGTH,
+            max_speed: None,
+        };
+        let driving_lane = map.find_driving_lane_near_building(b);
+
+        // Anything on the current lane? TODO Should find the closest one to the sidewalk, but
+        // need a new method in ParkingSimState to make that easy.
+        // TODO Refactor the logic in router
+        let spot = if let Some((spot, _)) = self
+            .parking
+            .get_all_free_spots(
+                Position::new(driving_lane, Distance::ZERO),
+                &vehicle,
+                b,
+                map,
+            )
+            .get(0)
+        {
+            spot.clone()
+        } else {
+            let (_, spot, _) =
+                self.parking
+                    .path_to_free_parking_spot(driving_lane, &vehicle, b, map)?;
+            spot
+        };
+
+        let start = SidewalkSpot::building(b, map).sidewalk_pos;
+        let end = SidewalkSpot::parking_spot(spot, map, &self.parking).sidewalk_pos;
+        let path = map.pathfind(PathRequest {
+            start,
+            end,
+            constraints: PathConstraints::Pedestrian,
+        })?;
+        Some((path, start.dist_along()))
+    }
+
+    // TODO Should these two be in TripSpawner?
+    pub(crate) fn new_person(
+        &mut self,
+        p: PersonID,
+        orig_id: Option<OrigPersonID>,
+        ped_speed: Speed,
+        vehicle_specs: Vec<VehicleSpec>,
+    ) {
+        self.trips.new_person(p, orig_id, ped_speed, vehicle_specs);
+    }
+    pub fn random_person(&mut self, ped_speed: Speed, vehicle_specs: Vec<VehicleSpec>) -> &Person {
+        self.trips.random_person(ped_speed, vehicle_specs)
+    }
+
+    // Create somebody with an e-bike and immediately schedule a trip for them.
+    pub fn start_trip_using_e_bike(
+        &mut self,
+        at: Time,
+        start_bldg: BuildingID,
+        goal: DrivingGoal,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Result<PersonID, String> {
+        let mut spawner = self.make_spawner();
+        let person = self.trips.random_person(
+            Scenario::rand_ped_speed(rng),
+            vec![Scenario::rand_e_bike(rng)],
+        );
+        let id = person.id;
+        spawner.schedule_trip(
+            person,
+            at,
+            TripSpec::UsingBike {
+                bike: person.vehicles[0].id,
+                start: SidewalkSpot::building(start_bldg, map),
+                goal,
+            },
+            TripEndpoint::Bldg(start_bldg),
+            false,
+            map,
+        )?;
+        spawner.finalize(
+            map,
+            &mut self.trips,
+            &mut self.scheduler,
+            &mut Timer::throwaway(),
+        );
+        Ok(id)
+    }
+
+    // Create a driver with a delivery truck parked near origin_warehouse, who drives to each
+    // delivery in order, walking from the curb to the front door at every stop.
+    pub fn start_trip_with_goods_delivery(
+        &mut self,
+        at: Time,
+        origin_warehouse: BuildingID,
+        deliveries: Vec<BuildingID>,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Result<PersonID, String> {
+        let mut spots = self.parking.get_free_offstreet_spots(origin_warehouse);
+        if let Ok(l) = map.find_closest_lane(
+            map.get_b(origin_warehouse).sidewalk(),
+            vec![LaneType::Parking],
+        ) {
+            spots.extend(self.parking.get_free_onstreet_spots(l));
+        }
+        let spot = *spots
+            .first()
+            .ok_or_else(|| format!("Nowhere to park a delivery truck near {}", origin_warehouse))?;
+
+        let mut spawner = self.make_spawner();
+        let person = self.trips.random_person(
+            Scenario::rand_ped_speed(rng),
+            vec![Scenario::delivery_truck()],
+        );
+        let id = person.id;
+        let truck = person.vehicles[0].clone();
+        spawner.start_trip_with_goods_delivery(person, at, origin_warehouse, deliveries, map)?;
+        self.seed_parked_car(truck, spot);
+        spawner.finalize(
+            map,
+            &mut self.trips,
+            &mut self.scheduler,
+            &mut Timer::throwaway(),
+        );
+        Ok(id)
+    }
+
+    // Create a courier with a cargo bike, who rides from start_bldg to each delivery in order,
+    // walking to the door at every stop, then rides back.
+    pub fn start_trip_using_cargo_bike(
+        &mut self,
+        at: Time,
+        start_bldg: BuildingID,
+        deliveries: Vec<BuildingID>,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Result<PersonID, String> {
+        let mut spawner = self.make_spawner();
+        let person = self.trips.random_person(
+            Scenario::rand_ped_speed(rng),
+            vec![Scenario::rand_cargo_bike(rng)],
+        );
+        let id = person.id;
+        spawner.schedule_trip(
+            person,
+            at,
+            TripSpec::UsingCargoBike {
+                bike: person.vehicles[0].id,
+                start_bldg,
+                deliveries,
+            },
+            TripEndpoint::Bldg(start_bldg),
+            false,
+            map,
+        )?;
+        spawner.finalize(
+            map,
+            &mut self.trips,
+            &mut self.scheduler,
+            &mut Timer::throwaway(),
+        );
+        Ok(id)
+    }
+
+    // Create somebody whose car appears at start_pos and drives to the goal, replanning whenever
+    // the next lane is congested. The trip is recorded as starting from origin, which should be
+    // wherever start_pos is near.
+    pub fn start_trip_using_adaptive_routing(
+        &mut self,
+        at: Time,
+        origin: TripEndpoint,
+        start_pos: Position,
+        goal: DrivingGoal,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Result<PersonID, String> {
+        let mut spawner = self.make_spawner();
+        let person = self
+            .trips
+            .random_person(Scenario::rand_ped_speed(rng), vec![Scenario::rand_car(rng)]);
+        let id = person.id;
+        spawner.schedule_trip(
+            person,
+            at,
+            TripSpec::VehicleAppearing {
+                start_pos,
+                goal,
+                use_vehicle: person.vehicles[0].id,
+                retry_if_no_room: true,
+                origin: None,
+            },
+            origin,
+            false,
+            map,
+        )?;
+        spawner.finalize(
+            map,
+            &mut self.trips,
+            &mut self.scheduler,
+            &mut Timer::throwaway(),
+        );
+        let trip = self.trips.get_person(id).unwrap().trips[0];
+        self.trips.enable_adaptive_routing(trip);
+        Ok(id)
+    }
+
+    // Create total_trips people, each driving a car from near origin to the goal. Departure times
+    // are sampled from a piecewise-linear cumulative distribution: each point of the curve is
+    // (time, fraction of trips departed by then), going from 0 to 1.
+    pub fn start_trip_with_timed_departure_curve(
+        &mut self,
+        curve: &[(Time, f64)],
+        total_trips: usize,
+        origin: BuildingID,
+        goal: DrivingGoal,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Result<Vec<PersonID>, String> {
+        if curve.is_empty() || curve[0].1 != 0.0 || curve.last().unwrap().1 != 1.0 {
+            return Err(format!("Departure curve must go from 0 to 1: {:?}", curve));
+        }
+        for pair in curve.windows(2) {
+            if pair[1].0 < pair[0].0 || pair[1].1 < pair[0].1 {
+                return Err(format!("Departure curve isn't increasing: {:?}", curve));
+            }
+        }
+        if curve[0].0 < self.time {
+            return Err(format!(
+                "Departure curve starts at {}, but it's already {}",
+                curve[0].0, self.time
+            ));
+        }
+
+        let sidewalk_pos = map.get_b(origin).front_path.sidewalk;
+        let lane = map.find_driving_lane_near_building(origin);
+        let pos = if map.get_l(lane).parent == map.get_l(sidewalk_pos.lane()).parent {
+            sidewalk_pos.equiv_pos(lane, Distance::ZERO, map)
+        } else {
+            Position::new(lane, map.get_l(lane).length() / 2.0)
+        };
+        let start_pos = TripSpec::spawn_vehicle_at(pos, false, map)
+            .ok_or_else(|| format!("Can't spawn a car on {}", lane))?;
+
+        let mut spawner = self.make_spawner();
+        let mut people = Vec::new();
+        for _ in 0..total_trips {
+            let x: f64 = rng.gen_range(0.0, 1.0);
+            // The first piece of the curve reaching x. It always exists, since the curve ends at 1,
+            // and it can't be flat, so there's no division by zero.
+            let pair = curve.windows(2).find(|pair| pair[1].1 > x).unwrap();
+            let ((t1, f1), (t2, f2)) = (pair[0], pair[1]);
+            let depart = t1 + (t2 - t1) * ((x - f1) / (f2 - f1));
+
+            let person = self
+                .trips
+                .random_person(Scenario::rand_ped_speed(rng), vec![Scenario::rand_car(rng)]);
+            people.push(person.id);
+            spawner.schedule_trip(
+                person,
+                depart,
+                TripSpec::VehicleAppearing {
+                    start_pos,
+                    goal: goal.clone(),
+                    use_vehicle: person.vehicles[0].id,
+                    retry_if_no_room: true,
+                    origin: None,
+                },
+                TripEndpoint::Bldg(origin),
+                false,
+                map,
+            )?;
+        }
+        spawner.finalize(
+            map,
+            &mut self.trips,
+            &mut self.scheduler,
+            &mut Timer::throwaway(),
+        );
+        Ok(people)
+    }
+
+    // Create somebody who waits at start_bldg for a ride-hailing driver to take them to goal.
+    pub fn start_trip_using_ride_hail(
+        &mut self,
+        at: Time,
+        start_bldg: BuildingID,
+        goal: BuildingID,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Result<PersonID, String> {
+        if !self.transit.ride_hail_enabled() {
+            return Err("Ride-hailing isn't enabled".to_string());
+        }
+        let mut spawner = self.make_spawner();
+        let person = self
+            .trips
+            .random_person(Scenario::rand_ped_speed(rng), Vec::new());
+        let id = person.id;
+        spawner.schedule_trip(
+            person,
+            at,
+            TripSpec::UsingRideHail { start_bldg, goal },
+            TripEndpoint::Bldg(start_bldg),
+            false,
+            map,
+        )?;
+        spawner.finalize(
+            map,
+            &mut self.trips,
+            &mut self.scheduler,
+            &mut Timer::throwaway(),
+        );
+        Ok(id)
+    }
+
+    pub fn start_trip_using_drt(
+        &mut self,
+        at: Time,
+        start_bldg: BuildingID,
+        goal: BuildingID,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Result<PersonID, String> {
+        if !self.transit.drt_enabled() {
+            return Err("Demand-responsive transit isn't enabled".to_string());
+        }
+        let mut spawner = self.make_spawner();
+        let person = self
+            .trips
+            .random_person(Scenario::rand_ped_speed(rng), Vec::new());
+        let id = person.id;
+        spawner.schedule_trip(
+            person,
+            at,
+            TripSpec::UsingDemandResponsiveTransit { start_bldg, goal },
+            TripEndpoint::Bldg(start_bldg),
+            false,
+            map,
+        )?;
+        spawner.finalize(
+            map,
+            &mut self.trips,
+            &mut self.scheduler,
+            &mut Timer::throwaway(),
+        );
+        Ok(id)
+    }
+
+    pub(crate) fn seed_parked_car(&mut self, vehicle: Vehicle, spot: ParkingSpot) {
+        self.parking.reserve_spot(spot);
+        self.parking.add_parked_car(ParkedCar { vehicle, spot });
+    }
+
+    pub fn seed_bus_route(&mut self, route: &BusRoute, map: &Map, timer: &mut Timer) -> Vec<CarID> {
+        let mut results: Vec<CarID> = Vec::new();
+
+        // Try to spawn just ONE bus anywhere.
+        // TODO Be more realistic. One bus per stop is too much, one is too little.
+        for (next_stop_idx, req, mut path, end_dist) in
+            self.transit.create_empty_route(route, map).into_iter()
+        {
+            // For now, no desire for randomness. Caller can pass in list of specs if that ever
+            // changes.
+            let vehicle = VehicleSpec {
+                vehicle_type: VehicleType::Bus,
+                length: BUS_LENGTH,
+                max_speed: None,
+            }
+            .make(CarID(self.trips.new_car_id(), VehicleType::Bus), None);
+            let id = vehicle.id;
+
+            loop {
+                if path.is_last_step() {
+                    timer.warn(format!(
+                        "Giving up on seeding a bus headed towards stop {} of {} ({})",
+                        next_stop_idx, route.name, route.id
+                    ));
+                    break;
+                }
+                let start_lane = if let PathStep::Lane(l) = path.current_step() {
+                    l
+                } else {
+                    path.shift(map);
+                    // TODO Technically should update request, but it shouldn't matter
+                    continue;
+                };
+                if map.get_l(start_lane).length() < vehicle.length {
+                    path.shift(map);
+                    // TODO Technically should update request, but it shouldn't matter
+                    continue;
+                }
+
+                // Bypass some layers of abstraction that don't make sense for buses.
+                if self.driving.start_car_on_lane(
+                    self.time,
+                    CreateCar {
+                        start_dist: vehicle.length,
+                        vehicle: vehicle.clone(),
+                        req: req.clone(),
+                        router: Router::follow_bus_route(path.clone(), end_dist),
+                        maybe_parked_car: None,
+                        trip_and_person: None,
+                        adaptive_routing: false,
+                    },
+                    map,
+                    &self.intersections,
+                    &self.parking,
+                    &mut self.scheduler,
+                ) {
+                    self.transit
+                        .bus_created(id, vehicle.length, route.id, next_stop_idx);
+                    self.analytics.record_demand(&path, map);
+                    results.push(id);
+                    return results;
+                } else {
+                    path.shift(map);
+                }
+            }
+        }
+        if results.is_empty() {
+            // TODO Bigger failure
+            timer.warn(format!("Failed to make ANY buses for {}!", route.name));
+        }
+        results
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.run_name = name;
+    }
+
+    // Turn some parking lanes into a multi-story garage.
+    pub fn add_parking_garage(&mut self, lanes: Vec<LaneID>, stories: usize) {
+        self.parking.add_garage(lanes, stories);
+    }
+
+    // When seeding parked cars, a garage within this distance of the building is preferred over
+    // on-street parking.
+    pub fn set_garage_walk_threshold(&mut self, dist: Distance) {
+        self.parking.set_garage_walk_threshold(dist);
+    }
+
+    // Drivers who can't park on the lane near their destination give up on parking instead of
+    // driving further than this to find a spot. None means they search indefinitely.
+    pub fn set_max_parking_search(&mut self, dist: Option<Distance>) {
+        self.parking.set_max_parking_search(dist);
+    }
+
+    // Reassign a parked car to a different person (or nobody), like when a ride-hailing fleet
+    // hands a car to another driver.
+    pub fn transfer_car_owner(
+        &mut self,
+        car: CarID,
+        new_owner: Option<PersonID>,
+    ) -> Result<(), String> {
+        let old_owner = self.parking.get_owner_of_car(car);
+        let vehicle = self.parking.transfer_car(car, new_owner)?;
+        self.trips.transfer_vehicle(old_owner, vehicle);
+        Ok(())
+    }
+
+    // Lower the speed limit on the roads next to a building during some periods of the day.
+    pub fn simulate_school_zone(
+        &mut self,
+        bldg: BuildingID,
+        periods: Vec<(Time, Time)>,
+        speed_limit: Speed,
+        map: &Map,
+    ) {
+        let affected_lanes = map
+            .get_parent(map.get_b(bldg).sidewalk())
+            .all_lanes()
+            .into_iter()
+            .filter(|l| map.get_l(*l).lane_type.is_for_moving_vehicles())
+            .collect();
+        let idx = self.school_zones.len();
+        self.school_zones.push(SchoolZone {
+            bldg,
+            affected_lanes,
+            periods,
+            speed_limit,
+            active: false,
+        });
+        // This'll figure out if the zone is active right now and when it next changes.
+        self.scheduler
+            .push(self.time, Command::UpdateSchoolZone(idx));
+    }
+
+    // Slow down traffic on one lane for a while, like after a crash. If something else is also
+    // slowing down the lane, the lowest speed applies.
+    pub fn apply_incident(
+        &mut self,
+        lane: LaneID,
+        severity: IncidentSeverity,
+        duration: Duration,
+        map: &Map,
+    ) -> IncidentID {
+        let id = IncidentID(self.next_incident_id);
+        self.next_incident_id += 1;
+        let speed = map.get_parent(lane).speed_limit * severity.speed_factor();
+        self.driving
+            .lane_speed_override(lane, SpeedOverrideSource::Incident(id), Some(speed));
+        let ends = self.time + duration;
+        self.active_incidents.insert(id, (lane, severity, ends));
+        self.scheduler.push(ends, Command::ClearIncident(id));
+        id
+    }
+
+    // Returns (incident, lane, time left until it clears)
+    pub fn get_active_incidents(&self) -> Vec<(IncidentID, LaneID, Duration)> {
+        self.active_incidents
+            .iter()
+            .map(|(id, (l, _, ends))| (*id, *l, *ends - self.time))
+            .collect()
+    }
+
+    // Clear an incident now, instead of waiting for it to expire.
+    pub fn clear_incident(&mut self, id: IncidentID) {
+        if self.active_incidents.contains_key(&id) {
+            self.scheduler.update(self.time, Command::ClearIncident(id));
+        }
+    }
+
+    // Create one student per building, who waits there for a school bus to take them to school.
+    // Each bus leaves the school at the given time, picks up as many students as fit, stopping at
+    // the buildings in the order given, and then drops them off near the school.
+    pub fn start_trip_school_bus(
+        &mut self,
+        at: Time,
+        school: BuildingID,
+        student_buildings: Vec<BuildingID>,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Result<Vec<PersonID>, String> {
+        if student_buildings.contains(&school) {
+            return Err(format!("School bus trip starts and ends at {}", school));
+        }
+
+        // Plan every bus's whole route before creating anybody, so a failure doesn't leave some
+        // buses and students behind
+        let mut plans = Vec::new();
+        for students in student_buildings.chunks(SCHOOL_BUS_CAPACITY) {
+            // Students living near the same curb board together
+            let mut stops: Vec<(Position, Vec<BuildingID>)> = Vec::new();
+            for b in students {
+                let pos = curb_pos(*b, BUS_LENGTH, map);
+                if let Some((last, bldgs)) = stops.last_mut() {
+                    if *last == pos {
+                        bldgs.push(*b);
+                        continue;
+                    }
+                }
+                stops.push((pos, vec![*b]));
+            }
+
+            let depot = curb_pos(school, BUS_LENGTH, map);
+            let mut routes = Vec::new();
+            let mut from = depot;
+            for to in stops
+                .iter()
+                .map(|(pos, _)| *pos)
+                .chain(std::iter::once(depot))
+            {
+                let req = PathRequest {
+                    start: from,
+                    end: to,
+                    constraints: PathConstraints::Car,
+                };
+                let path = map
+                    .pathfind(req.clone())
+                    .ok_or_else(|| format!("A school bus can't drive {}", req))?;
+                routes.push((req, Router::stop_at_curb(path, to.dist_along())));
+                from = to;
+            }
+            plans.push((stops, depot, routes));
+        }
+
+        let mut people = Vec::new();
+        for (stops, depot, routes) in plans {
+            let vehicle = VehicleSpec {
+                vehicle_type: VehicleType::SchoolBus,
+                length: BUS_LENGTH,
+                max_speed: None,
+            }
+            .make(CarID(self.trips.new_car_id(), VehicleType::SchoolBus), None);
+            let bus = vehicle.id;
+
+            let mut spawner = self.make_spawner();
+            let mut riders = Vec::new();
+            for (_, bldgs) in &stops {
+                let mut here = Vec::new();
+                for b in bldgs {
+                    let person = self
+                        .trips
+                        .random_person(Scenario::rand_ped_speed(rng), Vec::new());
+                    here.push(person.id);
+                    // Nobody lives at the school, which was checked above
+                    spawner
+                        .schedule_trip(
+                            person,
+                            at,
+                            TripSpec::UsingSchoolBus {
+                                start_bldg: *b,
+                                bus,
+                                school,
+                            },
+                            TripEndpoint::Bldg(*b),
+                            false,
+                            map,
+                        )
+                        .unwrap();
+                }
+                riders.push(here);
+            }
+            spawner.finalize(
+                map,
+                &mut self.trips,
+                &mut self.scheduler,
+                &mut Timer::throwaway(),
+            );
+
+            let mut routes = routes.into_iter();
+            let (req, router) = routes.next().unwrap();
+            let mut waiting = Vec::new();
+            for (here, (_, router)) in riders.into_iter().zip(routes) {
+                let trips = here
+                    .iter()
+                    .map(|p| *self.trips.get_person(*p).unwrap().trips.last().unwrap())
+                    .collect();
+                people.extend(here);
+                waiting.push((trips, router));
+            }
+            self.transit.school_bus_created(bus, school, waiting);
+            self.scheduler.push(
+                at,
+                Command::SpawnCar(
+                    CreateCar {
+                        start_dist: depot.dist_along(),
+                        vehicle,
+                        req,
+                        router,
+                        maybe_parked_car: None,
+                        trip_and_person: None,
+                        adaptive_routing: false,
+                    },
+                    true,
+                ),
+            );
+        }
+        Ok(people)
+    }
+
+    // Start a fleet of ride-hailing drivers, serving trips made with start_trip_using_ride_hail.
+    // Each driver starts waiting near a random building.
+    pub fn enable_ride_hail(
+        &mut self,
+        fleet_size: usize,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Result<Vec<CarID>, String> {
+        let drivers = self.place_curbside_fleet(fleet_size, Scenario::rand_car, map, rng)?;
+        let ids = drivers.iter().map(|(v, _)| v.id).collect();
+        self.transit.enable_ride_hail(drivers);
+        Ok(ids)
+    }
+
+    // Start a fleet of minibuses idling at the curb near random buildings. Riders request a trip
+    // with start_trip_using_drt, and the nearest idle minibus picks them up.
+    pub fn simulate_demand_responsive_transit(
+        &mut self,
+        fleet_size: usize,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Result<Vec<CarID>, String> {
+        let minibuses =
+            self.place_curbside_fleet(fleet_size, |_| Scenario::drt_minibus(), map, rng)?;
+        let ids = minibuses.iter().map(|(v, _)| v.id).collect();
+        self.transit.enable_drt(minibuses);
+        Ok(ids)
+    }
+
+    // Makes vehicles for a ride-hailing or DRT fleet, each waiting at the curb near a random
+    // building.
+    fn place_curbside_fleet<F: Fn(&mut XorShiftRng) -> VehicleSpec>(
+        &mut self,
+        fleet_size: usize,
+        make_spec: F,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Result<Vec<(Vehicle, Position)>, String> {
+        if map.all_buildings().is_empty() {
+            return Err(format!(
+                "{} has no buildings to start a fleet near",
+                map.get_name()
+            ));
+        }
+        let mut fleet = Vec::new();
+        for _ in 0..fleet_size {
+            let vehicle =
+                make_spec(rng).make(CarID(self.trips.new_car_id(), VehicleType::Car), None);
+            let b = map.all_buildings().choose(rng).unwrap().id;
+            let pos = curb_pos(b, vehicle.length, map);
+            fleet.push((vehicle, pos));
+        }
+        Ok(fleet)
+    }
+
+    // A car starting a turn within this gap of the car ahead joins its platoon. Platoons share
+    // braking, so followers keep a much shorter following distance, fitting more cars on a lane.
+    pub fn enable_platooning(&mut self, gap: Distance) {
+        self.driving.enable_platooning(gap);
+    }
+
+    pub fn set_transfer_penalty(&mut self, penalty: Duration) {
+        self.transfer_penalty = penalty;
+    }
+}
+
+// Drawing
+impl GetDrawAgents for Sim {
+    fn time(&self) -> Time {
+        self.time
+    }
+
+    fn step_count(&self) -> usize {
+        self.step_count
+    }
+
+    fn get_draw_car(&self, id: CarID, map: &Map) -> Option<DrawCarInput> {
+        self.parking.get_draw_car(id, map).or_else(|| {
+            self.driving
+                .get_single_draw_car(id, self.time, map, &self.transit)
+        })
+    }
+
+    fn get_draw_ped(&self, id: PedestrianID, map: &Map) -> Option<DrawPedestrianInput> {
+        self.walking.get_draw_ped(id, self.time, map)
+    }
+
+    fn get_draw_cars(&self, on: Traversable, map: &Map) -> Vec<DrawCarInput> {
+        let mut results = Vec::new();
+        if let Traversable::Lane(l) = on {
+            if map.get_l(l).is_parking() {
+                return self.parking.get_draw_cars(l, map);
+            }
+            results.extend(self.parking.get_draw_cars_in_lots(l, map));
+        }
+        results.extend(
+            self.driving
+                .get_draw_cars_on(self.time, on, map, &self.transit),
+        );
+        results
+    }
+
+    fn get_draw_peds(
+        &self,
+        on: Traversable,
+        map: &Map,
+    ) -> (Vec<DrawPedestrianInput>, Vec<DrawPedCrowdInput>) {
+        self.walking.get_draw_peds_on(self.time, on, map)
+    }
+
+    fn get_all_draw_cars(&self, map: &Map) -> Vec<DrawCarInput> {
+        let mut result = self
+            .driving
+            .get_all_draw_cars(self.time, map, &self.transit);
+        result.extend(self.parking.get_all_draw_cars(map));
+        result
+    }
+
+    fn get_all_draw_peds(&self, map: &Map) -> Vec<DrawPedestrianInput> {
+        self.walking.get_all_draw_peds(self.time, map)
+    }
+
+    fn get_unzoomed_agents(&self, map: &Map) -> Vec<UnzoomedAgent> {
+        let mut result = self.driving.get_unzoomed_agents(self.time, map);
+        result.extend(self.walking.get_unzoomed_agents(self.time, map));
+        result
+    }
+}
+
+// Running
+impl Sim {
+    // Advances time as minimally as possible, also limited by max_dt. Returns true if the callback
+    // said to halt the sim.
+    fn minimal_step(
+        &mut self,
+        map: &Map,
+        max_dt: Duration,
+        maybe_cb: &mut Option<Box<dyn SimCallback>>,
+        on_event: &mut dyn FnMut(&Event),
+    ) -> bool {
+        self.step_count += 1;
+
+        let max_time = if let Some(t) = self.scheduler.peek_next_time() {
+            if t > self.time + max_dt {
+                // Next event is after when we want to stop.
+                self.time += max_dt;
+                return false;
+            }
+            t
+        } else {
+            // No events left at all
+            self.time += max_dt;
+            return false;
+        };
+
+        let mut halt = false;
+        while let Some(time) = self.scheduler.peek_next_time() {
+            if time > max_time {
+                return false;
+            }
+            if let Some(cmd) = self.scheduler.get_next() {
+                if self.do_step(map, time, cmd, maybe_cb, on_event) {
+                    halt = true;
+                    break;
+                }
+            }
+        }
+
+        halt
+    }
+
+    // If true, halt simulation because the callback said so.
+    fn do_step(
+        &mut self,
+        map: &Map,
+        time: Time,
+        cmd: Command,
+        maybe_cb: &mut Option<Box<dyn SimCallback>>,
+        on_event: &mut dyn FnMut(&Event),
+    ) -> bool {
+        self.time = time;
+        let mut events = Vec::new();
+        let mut halt = false;
+        match cmd {
+            Command::StartTrip(id, trip_spec, maybe_req, maybe_path) => {
+                self.trips.start_trip(
+                    self.time,
+                    id,
+                    trip_spec,
+                    maybe_req,
+                    maybe_path,
+                    &mut self.parking,
+                    &mut self.scheduler,
+                    map,
+                );
+            }
+            Command::SpawnCar(create_car, retry_if_no_room) => {
+                if self.driving.start_car_on_lane(
+                    self.time,
+                    create_car.clone(),
+                    map,
+                    &self.intersections,
+                    &self.parking,
+                    &mut self.scheduler,
+                ) {
+                    if let Some((trip, _)) = create_car.trip_and_person {
+                        self.trips
+                            .agent_starting_trip_leg(AgentID::Car(create_car.vehicle.id), trip);
+                    }
+                    if let Some(parked_car) = create_car.maybe_parked_car {
+                        if let ParkingSpot::Offstreet(b, _) = parked_car.spot {
+                            // Buses don't start in parking garages, so trip must exist
+                            events.push(Event::PersonLeavesBuilding(
+                                create_car.trip_and_person.unwrap().1,
+                                b,
+                            ));
+                        }
+                        self.parking.remove_parked_car(parked_car);
+                    }
+                    if let Some((trip, person)) = create_car.trip_and_person {
+                        events.push(Event::TripPhaseStarting(
+                            trip,
+                            person,
+                            Some(create_car.req.clone()),
+                            if create_car.vehicle.id.1.is_bike() {
+                                TripPhaseType::Biking
+                            } else {
+                                TripPhaseType::Driving
+                            },
+                        ));
+                    }
+                    self.analytics
+                        .record_demand(create_car.router.get_path(), map);
+                    if let Some((trip, _)) = create_car.trip_and_person {
+                        self.trips.add_free_flow_time(
+                            trip,
+                            free_flow_time(
+                                &create_car.req,
+                                create_car.router.get_path(),
+                                create_car.vehicle.max_speed,
+                                map,
+                            ),
+                        );
+                    }
+                } else if retry_if_no_room {
+                    // TODO Record this in the trip log
+                    self.scheduler.push(
+                        self.time + BLIND_RETRY_TO_SPAWN,
+                        Command::SpawnCar(create_car, retry_if_no_room),
+                    );
+                } else {
+                    // School buses always retry and other buses don't use Command::SpawnCar, so
+                    // this must exist.
+                    let (trip, person) = create_car.trip_and_person.unwrap();
+                    println!(
+                        "No room to spawn car for {} by {}. Not retrying!",
+                        trip, person
+                    );
+                    self.trips.abort_trip(
+                        self.time,
+                        trip,
+                        Some(create_car.vehicle),
+                        &mut self.parking,
+                        &mut self.scheduler,
+                        map,
+                    );
+                }
+            }
+            Command::SpawnPed(create_ped) => {
+                // Do the order a bit backwards so we don't have to clone the
+                // CreatePedestrian. spawn_ped can't fail.
+                self.trips
+                    .agent_starting_trip_leg(AgentID::Pedestrian(create_ped.id), create_ped.trip);
+                events.push(Event::TripPhaseStarting(
+                    create_ped.trip,
+                    create_ped.person,
+                    Some(create_ped.req.clone()),
+                    TripPhaseType::Walking,
+                ));
+                self.analytics.record_demand(&create_ped.path, map);
+                self.trips.add_free_flow_time(
+                    create_ped.trip,
+                    free_flow_time(
+                        &create_ped.req,
+                        &create_ped.path,
+                        Some(create_ped.speed),
+                        map,
+                    ),
+                );
+
+                // Maybe there's actually no work to do!
+                match (&create_ped.start.connection, &create_ped.goal.connection) {
+                    (
+                        SidewalkPOI::Building(b1),
+                        SidewalkPOI::ParkingSpot(ParkingSpot::Offstreet(b2, idx)),
+                    ) if b1 == b2 => {
+                        self.trips.ped_reached_parking_spot(
+                            self.time,
+                            create_ped.id,
+                            ParkingSpot::Offstreet(*b2, *idx),
+                            Duration::ZERO,
+                            map,
+                            &mut self.parking,
+                            &mut self.scheduler,
+                        );
+                    }
+                    _ => {
+                        if let SidewalkPOI::Building(b) = &create_ped.start.connection {
+                            events.push(Event::PersonLeavesBuilding(create_ped.person, *b));
+                        }
+
+                        self.walking
+                            .spawn_ped(self.time, create_ped, map, &mut self.scheduler);
+                    }
+                }
+            }
+            Command::UpdateCar(car) => {
+                self.driving.update_car(
+                    car,
+                    self.time,
+                    map,
+                    &mut self.parking,
+                    &mut self.intersections,
+                    &mut self.trips,
+                    &mut self.scheduler,
+                    &mut self.transit,
+                    &mut self.walking,
+                );
+            }
+            Command::UpdateLaggyHead(car) => {
+                self.driving.update_laggy_head(
+                    car,
+                    self.time,
+                    map,
+                    &mut self.intersections,
+                    &mut self.scheduler,
+                );
+            }
+            Command::UpdatePed(ped) => {
+                self.walking.update_ped(
+                    ped,
+                    self.time,
+                    map,
+                    &mut self.intersections,
+                    &mut self.parking,
+                    &mut self.scheduler,
+                    &mut self.trips,
+                    &mut self.transit,
+                );
+            }
+            Command::UpdateIntersection(i) => {
+                self.intersections
+                    .update_intersection(self.time, i, map, &mut self.scheduler);
+            }
+            Command::Callback(frequency) => {
+                self.scheduler
+                    .push(self.time + frequency, Command::Callback(frequency));
+                if maybe_cb.as_mut().unwrap().run(self, map) {
+                    halt = true;
+                }
+            }
+            Command::Pandemic(cmd) => {
+                self.pandemic
+                    .as_mut()
+                    .unwrap()
+                    .handle_cmd(self.time, cmd, &mut self.scheduler);
+            }
+            Command::FinishRemoteTrip(trip) => {
+                self.trips.remote_trip_finished(
+                    self.time,
+                    trip,
+                    map,
+                    &mut self.parking,
+                    &mut self.scheduler,
+                );
+            }
+            Command::RequestRideHail(trip) => {
+                self.transit
+                    .request_ride_hail(trip, self.trips.uses_drt(trip));
+                self.dispatch_ride_hail(map);
+            }
+            Command::DispatchRideHail => {
+                self.dispatch_ride_hail(map);
+            }
+            Command::CheckBusStalled(car) => {
+                self.transit.check_bus_stalled(self.time, car);
+            }
+            Command::UpdateSchoolZone(idx) => {
+                let now = self.time;
+                let zone = &mut self.school_zones[idx];
+                let active = zone
+                    .periods
+                    .iter()
+                    .any(|(start, end)| *start <= now && now < *end);
+                if active != zone.active {
+                    zone.active = active;
+                    for l in &zone.affected_lanes {
+                        self.driving.lane_speed_override(
+                            *l,
+                            SpeedOverrideSource::SchoolZone(idx),
+                            if active { Some(zone.speed_limit) } else { None },
+                        );
+                    }
+                    events.push(if active {
+                        Event::SchoolZoneActive(zone.bldg)
+                    } else {
+                        Event::SchoolZoneInactive(zone.bldg)
+                    });
+                }
+                if let Some(next) = zone
+                    .periods
+                    .iter()
+                    .flat_map(|(start, end)| vec![*start, *end])
+                    .filter(|t| *t > now)
+                    .min()
+                {
+                    self.scheduler.push(next, Command::UpdateSchoolZone(idx));
+                }
+            }
+            Command::ClearIncident(id) => {
+                if let Some((l, _, _)) = self.active_incidents.remove(&id) {
+                    self.driving
+                        .lane_speed_override(l, SpeedOverrideSource::Incident(id), None);
+                    events.push(Event::IncidentCleared(id));
+                }
+            }
+        }
+
+        // Record events at precisely the time they occur.
+        self.dispatch_events(events, map, on_event);
+
+        halt
+    }
+
+    fn dispatch_events(
+        &mut self,
+        mut events: Vec<Event>,
+        map: &Map,
+        on_event: &mut dyn FnMut(&Event),
+    ) {
+        events.extend(self.trips.collect_events());
+        events.extend(self.transit.collect_events());
+        events.extend(self.driving.collect_events());
+        events.extend(self.walking.collect_events());
+        events.extend(self.intersections.collect_events());
+        events.extend(self.parking.collect_events());
+        for ev in events {
+            on_event(&ev);
+            self.event_archive.push((self.time, ev.clone()));
+            if let Some(ref mut m) = self.pandemic {
+                m.handle_event(self.time, &ev, &mut self.scheduler);
+            }
+            // Don't keep a driver reserved for somebody who's not coming
+            if let Event::TripAborted(trip) = ev {
+                self.transit.ride_hail_trip_aborted(trip);
+            }
+
+            self.analytics.event(ev, self.time, map);
+        }
+    }
+
+    // Greedily match the oldest ride-hailing request with the nearest idle driver, who appears
+    // where they were waiting and drives to the rider.
+    fn dispatch_ride_hail(&mut self, map: &Map) {
+        while let Some((trip, vehicle, start)) = self.transit.next_ride_hail_match(&self.trips, map)
+        {
+            let pickup = self.trips.ride_hail_endpoints(trip).0;
+            let end = curb_pos(pickup, vehicle.length, map);
+            let req = PathRequest {
+                start,
+                end,
+                constraints: PathConstraints::Car,
+            };
+            let path = if let Some(path) = map.pathfind(req.clone()) {
+                path
+            } else {
+                self.transit.ride_hail_dispatched(trip, None);
+                self.trips.abort_trip(
+                    self.time,
+                    trip,
+                    None,
+                    &mut self.parking,
+                    &mut self.scheduler,
+                    map,
+                );
+                continue;
+            };
+            let car = vehicle.id;
+            if self.driving.start_car_on_lane(
+                self.time,
+                CreateCar {
+                    start_dist: start.dist_along(),
+                    vehicle,
+                    req,
+                    router: Router::stop_at_curb(path, end.dist_along()),
+                    maybe_parked_car: None,
+                    trip_and_person: None,
+                    adaptive_routing: false,
+                },
+                map,
+                &self.intersections,
+                &self.parking,
+                &mut self.scheduler,
+            ) {
+                self.transit.ride_hail_dispatched(trip, Some(car));
+                self.trips.record_vehicle_trip(car, trip);
+            } else {
+                // No room for the driver to appear yet
+                self.scheduler
+                    .update(self.time + RIDE_HAIL_RETRY, Command::DispatchRideHail);
+                break;
+            }
+        }
+    }
+
+    pub fn timed_step(
+        &mut self,
+        map: &Map,
+        dt: Duration,
+        maybe_cb: &mut Option<Box<dyn SimCallback>>,
+        timer: &mut Timer,
+    ) {
+        let end_time = self.time + dt;
+        timer.start(format!("Advance sim to {}", end_time));
+        self.step_with_callbacks(map, dt, maybe_cb, &mut |_| {});
+        timer.stop(format!("Advance sim to {}", end_time));
+    }
+
+    // Like timed_step, but also calls on_event for every event as it happens, so the caller can
+    // keep whatever it needs.
+    pub fn step_with_callbacks(
+        &mut self,
+        map: &Map,
+        dt: Duration,
+        maybe_cb: &mut Option<Box<dyn SimCallback>>,
+        on_event: &mut dyn FnMut(&Event),
+    ) {
+        let end_time = self.time + dt;
+        let start = Instant::now();
+        let mut last_update = Instant::now();
+
+        while self.time < end_time {
+            if self.minimal_step(map, end_time - self.time, maybe_cb, on_event) {
+                break;
+            }
+            if !self.analytics.alerts.is_empty() {
+                match self.alerts {
+                    AlertHandler::Print => {
+                        for (t, loc, msg) in self.analytics.alerts.drain(..) {
+                            println!("Alert at {} ({:?}): {}", t, loc, msg);
+                        }
+                    }
+                    AlertHandler::Block => {
+                        for (t, loc, msg) in &self.analytics.alerts {
+                            println!("Alert at {} ({:?}): {}", t, loc, msg);
+                        }
+                        break;
+                    }
+                    AlertHandler::Silence => {
+                        self.analytics.alerts.clear();
+                    }
+                }
+            }
+            if Duration::realtime_elapsed(last_update) >= Duration::seconds(1.0) {
+                // TODO Not timer?
+                println!(
+                    "- After {}, the sim is at {}",
+                    Duration::realtime_elapsed(start),
+                    self.time
+                );
+                last_update = Instant::now();
+            }
+        }
+    }
+
+    pub fn tiny_step(&mut self, map: &Map, maybe_cb: &mut Option<Box<dyn SimCallback>>) {
+        self.timed_step(
+            map,
+            Duration::seconds(0.1),
+            maybe_cb,
+            &mut Timer::throwaway(),
+        );
+    }
+
+    pub fn time_limited_step(
+        &mut self,
+        map: &Map,
+        dt: Duration,
+        real_time_limit: Duration,
+        maybe_cb: &mut Option<Box<dyn SimCallback>>,
+    ) {
+        let started_at = Instant::now();
+        let end_time = self.time + dt;
+
+        while self.time < end_time && Duration::realtime_elapsed(started_at) < real_time_limit {
+            if self.minimal_step(map, end_time - self.time, maybe_cb, &mut |_| {}) {
+                break;
+            }
+            if !self.analytics.alerts.is_empty() {
+                match self.alerts {
+                    AlertHandler::Print => {
+                        for (t, loc, msg) in self.analytics.alerts.drain(..) {
+                            println!("Alert at {} ({:?}): {}", t, loc, msg);
+                        }
+                    }
+                    AlertHandler::Block => {
+                        for (t, loc, msg) in &self.analytics.alerts {
+                            println!("Alert at {} ({:?}): {}", t, loc, msg);
+                        }
+                        break;
+                    }
+                    AlertHandler::Silence => {
+                        self.analytics.alerts.clear();
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn dump_before_abort(&self) {
+        println!("At {}", self.time);
+        if let Some(path) = self.find_previous_savestate(self.time) {
+            println!("Debug from {}", path);
+        }
+    }
+}
+
+// Helpers to run the sim
+// TODO Old and gunky
+impl Sim {
+    pub fn run_until_done<F: Fn(&mut Sim, &Map)>(
+        &mut self,
+        map: &Map,
+        callback: F,
+        // Interpreted as a relative time
+        time_limit: Option<Duration>,
+    ) {
+        let mut last_print = Instant::now();
+        let mut last_sim_time = self.time();
+
+        loop {
+            // TODO Regular printing doesn't happen if we use a time_limit :\
+            let dt = time_limit.unwrap_or_else(|| Duration::seconds(30.0));
+
+            match panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                self.timed_step(map, dt, &mut None, &mut Timer::throwaway());
+            })) {
+                Ok(()) => {}
+                Err(err) => {
+                    println!(
+                        "*************************************************************************\
+                         *******"
+                    );
+                    println!("Sim broke:");
+                    self.dump_before_abort();
+                    panic::resume_unwind(err);
+                }
+            }
+
+            let dt_real = Duration::realtime_elapsed(last_print);
+            if dt_real >= Duration::seconds(1.0) {
+                let (finished, unfinished, _) = self.num_trips();
+                println!(
+                    "{}: {} trips finished, {} unfinished, speed = {:.2}x, {}",
+                    self.time(),
+                    abstutil::prettyprint_usize(finished),
+                    abstutil::prettyprint_usize(unfinished),
+                    (self.time() - last_sim_time) / dt_real,
+                    self.scheduler.describe_stats()
+                );
+                last_print = Instant::now();
+                last_sim_time = self.time();
+            }
+            callback(self, map);
+            if self.is_done() {
+                println!(
+                    "{}: speed = {:.2}x, {}",
+                    self.time(),
+                    (self.time() - last_sim_time) / dt_real,
+                    self.scheduler.describe_stats()
+                );
+                break;
+            }
+
+            if let Some(lim) = time_limit {
+                panic!("Time limit {} hit", lim);
+            }
+        }
+    }
+
+    // Run a scenario from scratch with different RNG seeds, then describe how long one trip took
+    // across the runs. The simulation itself is deterministic, so the variation only comes from
+    // what the seed affects when instantiating the scenario, like vehicles and walking speeds.
+    // None if the trip never finished in any run.
+    pub fn get_travel_time_reliability(
+        scenario: &Scenario,
+        opts: &SimOptions,
+        trip: TripID,
+        n_scenarios: usize,
+        map: &Map,
+        timer: &mut Timer,
+    ) -> Option<TravelTimeDistribution> {
+        let mut times = Vec::new();
+        timer.start_iter("run scenario with different seeds", n_scenarios);
+        for seed in 0..n_scenarios {
+            timer.next();
+            let mut sim = Sim::new(map, opts.clone(), &mut Timer::throwaway());
+            let mut rng = XorShiftRng::seed_from_u64(seed as u64);
+            scenario.instantiate(&mut sim, map, &mut rng, &mut Timer::throwaway());
+            sim.run_until_done(map, |_, _| {}, None);
+            if let Some((total, _)) = sim.finished_trip_time(trip) {
+                times.push(total);
+            }
+        }
+        if times.is_empty() {
+            return None;
+        }
+
+        let mut hgram = Histogram::new();
+        for t in &times {
+            hgram.add(*t);
+        }
+        let mean = hgram.select(Statistic::Mean);
+        let variance = times
+            .iter()
+            .map(|t| (*t - mean).inner_seconds().powi(2))
+            .sum::<f64>()
+            / (times.len() as f64);
+        Some(TravelTimeDistribution {
+            mean,
+            p50: hgram.select(Statistic::P50),
+            p95: hgram.percentile(95.0).unwrap(),
+            std_dev: variance.sqrt(),
+        })
+    }
+}
+
+// Savestating
+impl Sim {
+    pub fn save_dir(&self) -> String {
+        abstutil::path_all_saves(&self.map_name, &self.edits_name, &self.run_name)
+    }
+
+    fn save_path(&self, base_time: Time) -> String {
+        // If we wanted to be even more reproducible, we'd encode RNG seed, version of code, etc,
+        // but that's overkill right now.
+        abstutil::path_save(
+            &self.map_name,
+            &self.edits_name,
+            &self.run_name,
+            base_time.as_filename(),
+        )
+    }
+
+    pub fn save(&mut self) -> String {
+        let restore = self.scheduler.before_savestate();
+
+        if true {
+            println!("sim savestate breakdown:");
+            println!(
+                "- driving: {} bytes",
+                abstutil::prettyprint_usize(abstutil::serialized_size_bytes(&self.driving))
+            );
+            println!(
+                "- parking: {} bytes",
+                abstutil::prettyprint_usize(abstutil::serialized_size_bytes(&self.parking))
+            );
+            println!(
+                "- walking: {} bytes",
+                abstutil::prettyprint_usize(abstutil::serialized_size_bytes(&self.walking))
+            );
+            println!(
+                "- intersections: {} bytes",
+                abstutil::prettyprint_usize(abstutil::serialized_size_bytes(&self.intersections))
+            );
+            println!(
+                "- transit: {} bytes",
+                abstutil::prettyprint_usize(abstutil::serialized_size_bytes(&self.transit))
+            );
+            println!(
+                "- trips: {} bytes",
+                abstutil::prettyprint_usize(abstutil::serialized_size_bytes(&self.trips))
+            );
+            println!(
+                "- scheduler: {} bytes",
+                abstutil::prettyprint_usize(abstutil::serialized_size_bytes(&self.scheduler))
+            );
+        }
+
+        let path = self.save_path(self.time);
+        abstutil::write_binary(path.clone(), self);
+
+        self.scheduler.after_savestate(restore);
+
+        path
+    }
+
+    // Instead of a full savestate, just record what's changed since an earlier savestate at
+    // base_time. Each patch only depends on its base, not on other patches.
+    pub fn save_incremental(&mut self, base_time: Time) -> Result<String, std::io::Error> {
+        let base: Sim =
+            abstutil::maybe_read_binary(self.save_path(base_time), &mut Timer::throwaway())?;
+        let base_components = abstutil::to_binary_fields(&base)?;
+
+        let restore = self.scheduler.before_savestate();
+        let components = abstutil::to_binary_fields(self);
+        self.scheduler.after_savestate(restore);
+        let patch = SavestatePatch {
+            base_time,
+            components: base_components
+                .iter()
+                .zip(components?)
+                .map(|(old, new)| BytePatch::diff(old, &new))
+                .collect(),
+        };
+
+        let path = self.save_path(self.time).replace(".bin", ".patch");
+        std::fs::write(&path, abstutil::to_binary(&patch))?;
+        println!("Wrote {}", path);
+        Ok(path)
+    }
+
+    pub fn load_savestate_incremental(
+        base_path: String,
+        patch_path: String,
+        map: &Map,
+        timer: &mut Timer,
+    ) -> Result<Sim, std::io::Error> {
+        let base: Sim = abstutil::maybe_read_binary(base_path, timer)?;
+        let patch: SavestatePatch = abstutil::from_binary(&std::fs::read(patch_path)?)?;
+        let bad_patch = |msg: String| std::io::Error::new(std::io::ErrorKind::Other, msg);
+        if patch.base_time != base.time {
+            return Err(bad_patch(format!(
+                "Patch is against a savestate at {}, not {}",
+                patch.base_time, base.time
+            )));
+        }
+        let old_components = abstutil::to_binary_fields(&base)?;
+        if old_components.len() != patch.components.len() {
+            return Err(bad_patch(format!(
+                "Patch has {} components, but the savestate has {}",
+                patch.components.len(),
+                old_components.len()
+            )));
+        }
+        let mut bytes = Vec::new();
+        for (old, p) in old_components.iter().zip(patch.components) {
+            bytes.extend(p.apply(old).map_err(bad_patch)?);
+        }
+        let mut sim: Sim = abstutil::from_binary(&bytes)?;
+
+        sim.restore_paths(map, timer);
+        Ok(sim)
+    }
+
+    pub fn find_previous_savestate(&self, base_time: Time) -> Option<String> {
+        abstutil::find_prev_file(self.save_path(base_time))
+    }
+
+    pub fn find_next_savestate(&self, base_time: Time) -> Option<String> {
+        abstutil::find_next_file(self.save_path(base_time))
+    }
+
+    pub fn load_savestate(
+        path: String,
+        map: &Map,
+        timer: &mut Timer,
+    ) -> Result<Sim, std::io::Error> {
+        let mut sim: Sim = abstutil::maybe_read_binary(path, timer)?;
+        sim.restore_paths(map, timer);
+        Ok(sim)
+    }
+
+    pub fn restore_paths(&mut self, map: &Map, timer: &mut Timer) {
+        let paths = timer.parallelize(
+            "calculate paths",
+            self.scheduler.get_requests_for_savestate(),
+            |req| map.pathfind(req).unwrap(),
+        );
+        self.scheduler.after_savestate(paths);
+    }
+}
+
+// Queries of all sorts
+impl Sim {
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.trips.is_done()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.time == Time::START_OF_DAY && self.is_done()
+    }
+
+    // (number of finished trips, number of unfinished trips, number of active by mode)
+    pub fn num_trips(&self) -> (usize, usize, BTreeMap<TripMode, usize>) {
+        self.trips.num_trips()
+    }
+    // (total number of people, just in buildings, just off map)
+    pub fn num_ppl(&self) -> (usize, usize, usize) {
+        self.trips.num_ppl()
+    }
+
+    pub fn debug_ped(&self, id: PedestrianID) {
+        self.walking.debug_ped(id);
+        self.trips.debug_trip(AgentID::Pedestrian(id));
+    }
+
+    pub fn debug_car(&self, id: CarID) {
+        self.driving.debug_car(id);
+        self.trips.debug_trip(AgentID::Car(id));
+    }
+
+    pub fn debug_intersection(&self, id: IntersectionID, map: &Map) {
+        self.intersections.debug(id, map);
+    }
+
+    pub fn debug_lane(&self, id: LaneID) {
+        self.driving.debug_lane(id);
+    }
+
+    // Only call for active agents, will panic otherwise
+    pub fn agent_properties(&self, id: AgentID) -> AgentProperties {
+        match id {
+            AgentID::Pedestrian(id) => self.walking.agent_properties(id, self.time),
+            AgentID::Car(id) => self.driving.agent_properties(id, self.time),
+            // TODO Harder to measure some of this stuff
+            AgentID::BusPassenger(_, _) => AgentProperties {
+                total_time: Duration::ZERO,
+                waiting_here: Duration::ZERO,
+                total_waiting: Duration::ZERO,
+                dist_crossed: Distance::ZERO,
+                total_dist: Distance::meters(0.1),
+                lanes_crossed: 0,
+                total_lanes: 0,
+            },
+        }
+    }
+
+    // TODO Temporary until we figure out all the info to expose
+    pub fn bus_properties(&self, car: CarID, map: &Map) -> Vec<(String, String)> {
+        let passengers = self.transit.get_passengers(car);
+        vec![
+            (
+                "Route".to_string(),
+                map.get_br(self.transit.bus_route(car)).name.clone(),
+            ),
+            ("Passengers".to_string(), passengers.len().to_string()),
+        ]
+    }
+
+    pub fn bus_route_id(&self, maybe_bus: CarID) -> Option<BusRouteID> {
+        if maybe_bus.1 == VehicleType::Bus {
+            Some(self.transit.bus_route(maybe_bus))
+        } else {
+            None
+        }
+    }
+
+    pub fn active_agents(&self) -> Vec<AgentID> {
+        self.trips.active_agents()
+    }
+
+    pub fn agent_to_trip(&self, id: AgentID) -> Option<TripID> {
+        self.trips.agent_to_trip(id)
+    }
+
+    pub fn trip_to_agent(&self, id: TripID) -> TripResult<AgentID> {
+        self.trips.trip_to_agent(id)
+    }
+
+    // (start time, start position, end position, trip type)
+    pub fn trip_info(&self, id: TripID) -> (Time, TripEndpoint, TripEndpoint, TripMode) {
+        self.trips.trip_info(id)
+    }
+    // If trip is finished, returns (total time, total waiting time)
+    pub fn finished_trip_time(&self, id: TripID) -> Option<(Duration, Duration)> {
+        self.trips.finished_trip_time(id)
+    }
+
+    pub fn trip_to_person(&self, id: TripID) -> PersonID {
+        self.trips.trip_to_person(id)
+    }
+    // TODO This returns None for parked cars owned by people! That's confusing. Dedupe with
+    // get_owner_of_car.
+    pub fn agent_to_person(&self, id: AgentID) -> Option<PersonID> {
+        self.agent_to_trip(id).map(|t| self.trip_to_person(t))
+    }
+    pub fn get_owner_of_car(&self, id: CarID) -> Option<PersonID> {
+        self.driving
+            .get_owner_of_car(id)
+            .or_else(|| self.parking.get_owner_of_car(id))
+    }
+    pub fn lookup_parked_car(&self, id: CarID) -> Option<&ParkedCar> {
+        self.parking.lookup_parked_car(id)
+    }
+
+    pub fn lookup_person(&self, id: PersonID) -> Option<&Person> {
+        self.trips.get_person(id)
+    }
+    pub fn get_person(&self, id: PersonID) -> &Person {
+        self.trips.get_person(id).unwrap()
+    }
+    pub fn find_person_by_orig_id(&self, id: OrigPersonID) -> Option<PersonID> {
+        for p in self.get_all_people() {
+            if p.orig_id == Some(id) {
+                return Some(p.id);
+            }
+        }
+        None
+    }
+    pub fn get_all_people(&self) -> &Vec<Person> {
+        self.trips.get_all_people()
+    }
+
+    pub fn lookup_car_id(&self, idx: usize) -> Option<CarID> {
+        for vt in &[
+            VehicleType::Car,
+            VehicleType::Bike,
+            VehicleType::EBike,
+            VehicleType::Bus,
+            VehicleType::DeliveryTruck,
+            VehicleType::CargoBike,
+            VehicleType::SchoolBus,
+        ] {
+            let id = CarID(idx, *vt);
+            if self.driving.does_car_exist(id) {
+                return Some(id);
+            }
+        }
+
+        // Only cars and trucks can be parked.
+        for vt in &[VehicleType::Car, VehicleType::DeliveryTruck] {
+            let id = CarID(idx, *vt);
+            if self.parking.lookup_parked_car(id).is_some() {
+                return Some(id);
+            }
+        }
+
+        None
+    }
+
+    pub fn get_path(&self, id: AgentID) -> Option<&Path> {
+        match id {
+            AgentID::Car(car) => self.driving.get_path(car),
+            AgentID::Pedestrian(ped) => self.walking.get_path(ped),
+            AgentID::BusPassenger(_, _) => None,
+        }
+    }
+    pub fn get_all_driving_paths(&self) -> Vec<&Path> {
+        self.driving.get_all_driving_paths()
+    }
+
+    pub fn trace_route(
+        &self,
+        id: AgentID,
+        map: &Map,
+        dist_ahead: Option<Distance>,
+    ) -> Option<PolyLine> {
+        match id {
+            AgentID::Car(car) => self.driving.trace_route(self.time, car, map, dist_ahead),
+            AgentID::Pedestrian(ped) => self.walking.trace_route(self.time, ped, map, dist_ahead),
+            AgentID::BusPassenger(_, _) => None,
+        }
+    }
+
+    // The rest of the route for each agent, from where they currently are. Agents without a path
+    // are skipped.
+    pub fn visualize_paths(&self, ids: &[AgentID], map: &Map) -> Vec<PolyLine> {
+        ids.iter()
+            .filter_map(|id| self.trace_route(*id, map, None))
+            .collect()
+    }
+
+    pub fn get_canonical_pt_per_trip(&self, trip: TripID, map: &Map) -> TripResult<Pt2D> {
+        let agent = match self.trips.trip_to_agent(trip) {
+            TripResult::Ok(a) => a,
+            x => {
+                return x.propagate_error();
+            }
+        };
+        if let Some(pt) = self.canonical_pt_for_agent(agent, map) {
+            return TripResult::Ok(pt);
+        }
+        TripResult::ModeChange
+    }
+    pub fn get_canonical_pt_per_person(&self, p: PersonID, map: &Map) -> Option<Pt2D> {
+        match self.trips.get_person(p)?.state {
+            PersonState::Inside(b) => Some(map.get_b(b).polygon.center()),
+            PersonState::Trip(t) => self.get_canonical_pt_per_trip(t, map).ok(),
+            PersonState::OffMap => None,
+        }
+    }
+
+    pub fn canonical_pt_for_agent(&self, id: AgentID, map: &Map) -> Option<Pt2D> {
+        match id {
+            AgentID::Car(id) => self
+                .parking
+                .canonical_pt(id, map)
+                .or_else(|| Some(self.get_draw_car(id, map)?.body.last_pt())),
+            AgentID::Pedestrian(id) => Some(self.get_draw_ped(id, map)?.pos),
+            AgentID::BusPassenger(_, bus) => Some(self.get_draw_car(bus, map)?.body.last_pt()),
+        }
+    }
+
+    pub fn get_accepted_agents(&self, id: IntersectionID) -> HashSet<AgentID> {
+        self.intersections.get_accepted_agents(id)
+    }
+    pub fn get_blocked_by(&self, a: AgentID) -> HashSet<AgentID> {
+        self.intersections.get_blocked_by(a)
+    }
+
+    pub fn location_of_buses(&self, route: BusRouteID, map: &Map) -> Vec<(CarID, Pt2D)> {
+        let mut results = Vec::new();
+        for (car, _) in self.transit.buses_for_route(route) {
+            // TODO This is a slow, indirect method!
+            results.push((
+                car,
+                self.canonical_pt_for_agent(AgentID::Car(car), map).unwrap(),
+            ));
+        }
+        results
+    }
+
+    // (bus, stop index it's coming from, percent to next stop)
+    pub fn status_of_buses(&self, route: BusRouteID) -> Vec<(CarID, usize, f64)> {
+        let mut results = Vec::new();
+        for (bus, stop_idx) in self.transit.buses_for_route(route) {
+            results.push((bus, stop_idx, self.driving.percent_along_route(bus)));
+        }
+        results
+    }
+
+    // All archived events at or after the given time
+    pub fn get_all_events_since(&self, time: Time) -> Vec<(Time, Event)> {
+        self.event_archive[self.first_archived_event_at(time)..].to_vec()
+    }
+
+    pub fn get_stats(&self) -> SimStats {
+        let (finished_trips, unfinished_trips, _) = self.num_trips();
+        SimStats {
+            time: self.time,
+            step_count: self.step_count,
+            finished_trips,
+            unfinished_trips,
+            event_archive_size: self.event_archive.len(),
+        }
+    }
+
+    // The archive is sorted by time.
+    fn first_archived_event_at(&self, time: Time) -> usize {
+        self.event_archive
+            .iter()
+            .position(|(t, _)| *t >= time)
+            .unwrap_or_else(|| self.event_archive.len())
+    }
+
+    pub fn get_analytics(&self) -> &Analytics {
+        &self.analytics
+    }
+
+    pub fn find_blockage_front(&self, car: CarID, map: &Map) -> String {
+        self.driving
+            .find_blockage_front(car, map, &self.intersections)
+    }
+
+    // For intersections with an agent waiting beyond some threshold, return when they started
+    // waiting. Sorted by earliest waiting (likely the root cause of gridlock).
+    pub fn delayed_intersections(&self, threshold: Duration) -> Vec<(IntersectionID, Time)> {
+        self.intersections
+            .delayed_intersections(self.time, threshold)
+    }
+
+    pub fn bldg_to_people(&self, b: BuildingID) -> Vec<PersonID> {
+        self.trips.bldg_to_people(b)
+    }
+
+    pub fn worst_delay(
+        &self,
+        map: &Map,
+    ) -> (
+        BTreeMap<RoadID, Duration>,
+        BTreeMap<IntersectionID, Duration>,
+    ) {
+        self.intersections.worst_delay(self.time, map)
+    }
+
+    pub fn get_pandemic_model(&self) -> Option<&PandemicModel> {
+        self.pandemic.as_ref()
+    }
+
+    pub fn get_end_of_day(&self) -> Time {
+        // Always count at least 24 hours
+        self.scheduler
+            .get_last_time()
+            .max(Time::START_OF_DAY + Duration::hours(24))
+    }
+
+    pub fn current_phase_and_remaining_time(&self, i: IntersectionID) -> (usize, Duration) {
+        self.intersections
+            .current_phase_and_remaining_time(self.time, i)
+    }
+}
+
+// Invasive debugging
+impl Sim {
+    pub fn kill_stuck_car(&mut self, id: CarID, map: &Map) {
+        match self.despawn_agent(AgentID::Car(id), map) {
+            Ok(()) => println!("Forcibly killed {}", id),
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    // Remove an agent from the simulation and abort its trip.
+    // TODO Only cars on a trip can be removed so far.
+    pub fn despawn_agent(&mut self, id: AgentID, map: &Map) -> Result<(), String> {
+        let car = match id {
+            AgentID::Car(c) => c,
+            _ => {
+                return Err(format!("Can't despawn {} yet; only cars", id));
+            }
+        };
+        let trip = self
+            .agent_to_trip(id)
+            .ok_or_else(|| format!("{} has no trip?!", car))?;
+        let vehicle = self.driving.kill_stuck_car(
+            car,
+            self.time,
+            map,
+            &mut self.scheduler,
+            &mut self.intersections,
+        );
+        self.trips.abort_trip(
+            self.time,
+            trip,
+            Some(vehicle),
+            &mut self.parking,
+            &mut self.scheduler,
+            map,
+        );
+        Ok(())
+    }
+
+    pub fn clear_alerts(&mut self) -> Vec<(Time, AlertLocation, String)> {
+        std::mem::replace(&mut self.analytics.alerts, Vec::new())
+    }
+
+    // Drops all archived events that happened before the given time.
+    pub fn prune_event_archive_before(&mut self, time: Time) {
+        let idx = self.first_archived_event_at(time);
+        self.event_archive.drain(0..idx);
+    }
+}
+
+// Callbacks
+pub trait SimCallback: downcast_rs::Downcast {
+    // Run at some scheduled time. If this returns true, halt simulation.
+    fn run(&mut self, sim: &Sim, map: &Map) -> bool;
+}
+downcast_rs::impl_downcast!(SimCallback);
+
+impl Sim {
+    // Only one at a time supported.
+    pub fn set_periodic_callback(&mut self, frequency: Duration) {
+        // TODO Round up time nicely?
+        self.scheduler
+            .push(self.time + frequency, Command::Callback(frequency));
+    }
+    pub fn unset_periodic_callback(&mut self) {
+        // Frequency doesn't matter
+        self.scheduler
+            .cancel(Command::Callback(Duration::seconds(1.0)));
+    }
+}
+
+// One BytePatch per serialized field of Sim
+#[derive(Serialize, Deserialize)]
+struct SavestatePatch {
+    base_time: Time,
+    components: Vec<BytePatch>,
+}
+
+// Keeps the common prefix and suffix of the old bytes, replacing everything in between.
+#[derive(Serialize, Deserialize)]
+struct BytePatch {
+    prefix: usize,
+    suffix: usize,
+    middle: Vec<u8>,
+}
+
+impl BytePatch {
+    fn diff(old: &[u8], new: &[u8]) -> BytePatch {
+        let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        let max_suffix = old.len().min(new.len()) - prefix;
+        let suffix = old
+            .iter()
+            .rev()
+            .zip(new.iter().rev())
+            .take(max_suffix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        BytePatch {
+            prefix,
+            suffix,
+            middle: new[prefix..new.len() - suffix].to_vec(),
+        }
+    }
+
+    fn apply(&self, old: &[u8]) -> Result<Vec<u8>, String> {
+        if self.prefix + self.suffix > old.len() {
+            return Err(format!(
+                "Patch keeps {} bytes, but there are only {}",
+                self.prefix + self.suffix,
+                old.len()
+            ));
+        }
+        let mut result = old[0..self.prefix].to_vec();
+        result.extend(&self.middle);
+        result.extend(&old[old.len() - self.suffix..]);
+        Ok(result)
+    }
+}
+
+pub struct SimStats {
+    pub time: Time,
+    pub step_count: usize,
+    pub finished_trips: usize,
+    pub unfinished_trips: usize,
+    // How many events get_all_events_since could return. Use prune_event_archive_before to bound
+    // this.
+    pub event_archive_size: usize,
+}
+
+pub struct TravelTimeDistribution {
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    // In seconds
+    pub std_dev: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct SchoolZone {
+    pub bldg: BuildingID,
+    pub affected_lanes: Vec<LaneID>,
+    pub periods: Vec<(Time, Time)>,
+    pub speed_limit: Speed,
+    pub active: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IncidentSeverity {
+    Minor,
+    Major,
+    // The lane isn't physically blocked; traffic just crawls through.
+    Severe,
+}
+
+impl IncidentSeverity {
+    // Multiplies the lane's usual speed limit
+    fn speed_factor(self) -> f64 {
+        match self {
+            IncidentSeverity::Minor => 0.7,
+            IncidentSeverity::Major => 0.3,
+            IncidentSeverity::Severe => 0.05,
+        }
+    }
+}
+
+pub struct AgentProperties {
+    // TODO Of this leg of the trip only!
+    pub total_time: Duration,
+    pub waiting_here: Duration,
+    pub total_waiting: Duration,
+
+    // TODO More continuous on a single lane
+    pub dist_crossed: Distance,
+    pub total_dist: Distance,
+
+    pub lanes_crossed: usize,
+    pub total_lanes: usize,
+}
+
+const DEFAULT_TRANSFER_PENALTY: Duration = Duration::const_seconds(300.0);
+
+// Dollars per hour
+const DEFAULT_PARKING_PRICE: f64 = 2.0;
+const PARKING_PRICE_STEP: f64 = 0.25;
+// How far from the target occupancy a lane can be before its price changes
+const PARKING_UTILIZATION_TOLERANCE: f64 = 0.05;
+
+const PARK_AND_RIDE_FULL_UTILIZATION: f64 = 0.9;
+const PARK_AND_RIDE_MIN_BOARDINGS_PER_HOUR: usize = 20;
+const PARK_AND_RIDE_WALK_DISTANCE: Distance = Distance::const_meters(400.0);
+
+// How long to wait before trying to dispatch a driver again, when there was no room for them to
+// appear
+const RIDE_HAIL_RETRY: Duration = Duration::const_seconds(10.0);
+
+const SCHOOL_BUS_CAPACITY: usize = 40;
+
+const LANE_CAPACITY_PER_HOUR: f64 = 1800.0;
+
+// Vehicles per hour a road can carry in both directions.
+// TODO Rough per-lane saturation flow; ignores signals, speed limits, and turn lanes.
+fn road_capacity_per_hour(road: RoadID, map: &Map) -> f64 {
+    let r = map.get_r(road);
+    let lanes = r
+        .children_forwards
+        .iter()
+        .chain(r.children_backwards.iter())
+        .filter(|(_, lt)| lt.is_for_moving_vehicles())
+        .count();
+    (lanes as f64) * LANE_CAPACITY_PER_HOUR
+}
+
+// Paths include the entire first and last lane, so trim those to where the request starts and ends.
+fn path_distance(req: &PathRequest, path: &Path, map: &Map) -> Distance {
+    path.total_length()
+        - excess_dist(path.get_steps()[0], req.start.dist_along(), true, map)
+        - excess_dist(path.last_step(), req.end.dist_along(), false, map)
+}
+
+// How long the path takes at the speed limit (or the agent's top speed, if that's lower).
+fn free_flow_time(req: &PathRequest, path: &Path, max_speed: Option<Speed>, map: &Map) -> Duration {
+    let speed = |step: PathStep| {
+        let limit = step.as_traversable().speed_limit(map);
+        if let Some(s) = max_speed {
+            limit.min(s)
+        } else {
+            limit
+        }
+    };
+    let first = path.get_steps()[0];
+    let last = path.last_step();
+    let total: Duration = path
+        .get_steps()
+        .iter()
+        .map(|step| step.as_traversable().length(map) / speed(*step))
+        .sum();
+    total
+        - excess_dist(first, req.start.dist_along(), true, map) / speed(first)
+        - excess_dist(last, req.end.dist_along(), false, map) / speed(last)
+}
+
+// Paths include the entire first and last step. How much of those isn't actually crossed?
+fn excess_dist(step: PathStep, dist: Distance, at_start: bool, map: &Map) -> Distance {
+    let len = step.as_traversable().length(map);
+    match (step, at_start) {
+        (PathStep::ContraflowLane(_), true) | (PathStep::Lane(_), false) => len - dist,
+        _ => dist,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use map_model::raw::RawMap;
+    use map_model::{LaneType, RoadSpec};
+
+    fn grid_map() -> Map {
+        let spec = RoadSpec {
+            fwd: vec![LaneType::Driving, LaneType::Parking, LaneType::Sidewalk],
+            back: vec![LaneType::Driving, LaneType::Parking, LaneType::Sidewalk],
+        };
+        Map::create_from_raw(
+            RawMap::synthetic_grid(4, 4, &spec),
+            true,
+            &mut Timer::throwaway(),
+        )
+    }
+
+    #[test]
+    fn test_prune_event_archive() {
+        let map = grid_map();
+        let mut sim = Sim::new(&map, SimOptions::new("test"), &mut Timer::throwaway());
+        let mut rng = XorShiftRng::from_seed([0; 16]);
+        sim.simulate_demand_responsive_transit(2, &map, &mut rng)
+            .unwrap();
+        let bldgs: Vec<BuildingID> = map.all_buildings().iter().map(|b| b.id).collect();
+        for idx in 0..3 {
+            sim.start_trip_using_drt(
+                Time::START_OF_DAY + Duration::minutes(idx),
+                bldgs[idx],
+                bldgs[(idx + 4) % bldgs.len()],
+                &map,
+                &mut rng,
+            )
+            .unwrap();
+        }
+        sim.run_until_done(&map, |_, _| {}, Some(Duration::hours(1)));
+
+        let all_events = sim.get_all_events_since(Time::START_OF_DAY);
+        assert_eq!(sim.get_stats().event_archive_size, all_events.len());
+        let last = all_events.last().unwrap().0;
+        let midpoint = Time::START_OF_DAY + (last - Time::START_OF_DAY) / 2.0;
+        let expected: Vec<(Time, Event)> = all_events
+            .iter()
+            .filter(|(t, _)| *t >= midpoint)
+            .cloned()
+            .collect();
+        // Make sure the test means something
+        assert!(!expected.is_empty());
+        assert!(expected.len() < all_events.len());
+
+        sim.prune_event_archive_before(midpoint);
+        assert_eq!(sim.get_all_events_since(Time::START_OF_DAY), expected);
+        assert_eq!(sim.get_stats().event_archive_size, expected.len());
+        // Pruning again is a no-op
+        sim.prune_event_archive_before(midpoint);
+        assert_eq!(sim.get_all_events_since(Time::START_OF_DAY), expected);
+    }
+
+    #[test]
+    fn test_transfer_car_owner() {
+        let map = grid_map();
+        let mut sim = Sim::new(&map, SimOptions::new("test"), &mut Timer::throwaway());
+        let mut rng = XorShiftRng::from_seed([0; 16]);
+        let (old_owner, vehicle) = {
+            let person = sim.trips.random_person(
+                Scenario::rand_ped_speed(&mut rng),
+                vec![Scenario::rand_car(&mut rng)],
+            );
+            (person.id, person.vehicles[0].clone())
+        };
+        let new_owner = sim
+            .trips
+            .random_person(Scenario::rand_ped_speed(&mut rng), Vec::new())
+            .id;
+        let car = vehicle.id;
+
+        // Only parked cars can change hands
+        assert!(sim.transfer_car_owner(car, Some(new_owner)).is_err());
+
+        let parking_lane = map.all_lanes().iter().find(|l| l.is_parking()).unwrap().id;
+        let spot = sim.get_free_onstreet_spots(parking_lane)[0];
+        sim.seed_parked_car(vehicle, spot);
+        assert_eq!(sim.parking.get_owner_of_car(car), Some(old_owner));
+
+        sim.transfer_car_owner(car, Some(new_owner)).unwrap();
+        assert_eq!(sim.parking.get_owner_of_car(car), Some(new_owner));
+        assert!(sim.trips.get_person(old_owner).unwrap().vehicles.is_empty());
+        let new_vehicles = &sim.trips.get_person(new_owner).unwrap().vehicles;
+        assert_eq!(new_vehicles.len(), 1);
+        assert_eq!(new_vehicles[0].id, car);
+        assert_eq!(new_vehicles[0].owner, Some(new_owner));
+    }
+
+    #[test]
+    fn test_ride_hail_trips_finish() {
+        let map = grid_map();
+        let mut sim = Sim::new(&map, SimOptions::new("test"), &mut Timer::throwaway());
+        let mut rng = XorShiftRng::from_seed([0; 16]);
+        let bldgs: Vec<BuildingID> = map.all_buildings().iter().map(|b| b.id).collect();
+        assert!(sim
+            .start_trip_using_ride_hail(Time::START_OF_DAY, bldgs[0], bldgs[1], &map, &mut rng)
+            .is_err());
+
+        let drivers = sim.enable_ride_hail(1, &map, &mut rng).unwrap();
+        let mut people = Vec::new();
+        for (idx, goal) in vec![bldgs[8], bldgs[3]].into_iter().enumerate() {
+            people.push(
+                sim.start_trip_using_ride_hail(
+                    Time::START_OF_DAY + Duration::seconds(30.0 * idx as f64),
+                    bldgs[idx],
+                    goal,
+                    &map,
+                    &mut rng,
+                )
+                .unwrap(),
+            );
+        }
+        sim.run_until_done(&map, |_, _| {}, Some(Duration::hours(1)));
+
+        assert_eq!(sim.num_trips().1, 0);
+        let events = sim.get_all_events_since(Time::START_OF_DAY);
+        for person in people {
+            // The only driver served everybody
+            assert!(events
+                .iter()
+                .any(|(_, ev)| ev == &Event::RideHailPickup(person, drivers[0])));
+            assert!(events
+                .iter()
+                .any(|(_, ev)| ev == &Event::RideHailDropoff(person, drivers[0])));
+        }
+    }
+
+    #[test]
+    fn test_fleet_needs_buildings() {
+        let mut raw = RawMap::synthetic_grid(
+            2,
+            2,
+            &RoadSpec {
+                fwd: vec![LaneType::Driving, LaneType::Sidewalk],
+                back: vec![LaneType::Driving, LaneType::Sidewalk],
+            },
+        );
+        raw.buildings.clear();
+        let map = Map::create_from_raw(raw, true, &mut Timer::throwaway());
+        let mut sim = Sim::new(&map, SimOptions::new("test"), &mut Timer::throwaway());
+        let mut rng = XorShiftRng::from_seed([0; 16]);
+        assert!(sim.enable_ride_hail(3, &map, &mut rng).is_err());
+        assert!(sim
+            .simulate_demand_responsive_transit(3, &map, &mut rng)
+            .is_err());
+    }
+
+    #[test]
+    fn test_school_bus() {
+        let map = grid_map();
+        let mut sim = Sim::new(&map, SimOptions::new("test"), &mut Timer::throwaway());
+        let mut rng = XorShiftRng::from_seed([0; 16]);
+        let bldgs: Vec<BuildingID> = map.all_buildings().iter().map(|b| b.id).collect();
+        let school = bldgs[0];
+
+        // One student lives at the school. Nobody gets created.
+        assert!(sim
+            .start_trip_school_bus(Time::START_OF_DAY, school, bldgs.clone(), &map, &mut rng)
+            .is_err());
+        assert_eq!(sim.num_ppl().0, 0);
+        let (finished, unfinished, _) = sim.num_trips();
+        assert_eq!(finished + unfinished, 0);
+
+        let students = sim
+            .start_trip_school_bus(
+                Time::START_OF_DAY,
+                school,
+                bldgs[1..].to_vec(),
+                &map,
+                &mut rng,
+            )
+            .unwrap();
+        assert_eq!(students.len(), bldgs.len() - 1);
+        sim.run_until_done(&map, |_, _| {}, Some(Duration::hours(1)));
+        assert_eq!(sim.num_trips().0, students.len());
+        assert_eq!(sim.num_trips().1, 0);
+    }
+
+    #[test]
+    fn test_drt_serves_all_requests() {
+        let map = grid_map();
+        let mut sim = Sim::new(&map, SimOptions::new("test"), &mut Timer::throwaway());
+        let mut rng = XorShiftRng::from_seed([0; 16]);
+
+        // Not enabled yet
+        let bldgs: Vec<BuildingID> = map.all_buildings().iter().map(|b| b.id).collect();
+        assert!(sim
+            .start_trip_using_drt(Time::START_OF_DAY, bldgs[0], bldgs[1], &map, &mut rng)
+            .is_err());
+
+        // Fewer minibuses than requests, so some riders have to wait for a minibus to free up
+        sim.simulate_demand_responsive_transit(2, &map, &mut rng)
+            .unwrap();
+        let num_requests = 5;
+        for idx in 0..num_requests {
+            sim.start_trip_using_drt(
+                Time::START_OF_DAY + Duration::seconds(10.0 * idx as f64),
+                bldgs[idx],
+                bldgs[(idx + 4) % bldgs.len()],
+                &map,
+                &mut rng,
+            )
+            .unwrap();
+        }
+        sim.run_until_done(&map, |_, _| {}, Some(Duration::hours(1)));
+
+        let (finished, unfinished, _) = sim.num_trips();
+        assert_eq!(finished, num_requests);
+        assert_eq!(unfinished, 0);
+        let events = sim.get_all_events_since(Time::START_OF_DAY);
+        let pickups = events
+            .iter()
+            .filter(|(_, ev)| matches!(ev, Event::DRTPickup(_, _)))
+            .count();
+        let dropoffs = events
+            .iter()
+            .filter(|(_, ev)| matches!(ev, Event::DRTDropoff(_, _)))
+            .count();
+        assert_eq!(pickups, num_requests);
+        assert_eq!(dropoffs, num_requests);
+        assert!(!events
+            .iter()
+            .any(|(_, ev)| matches!(ev, Event::RideHailPickup(_, _))));
+    }
+}
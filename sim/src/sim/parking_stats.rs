@@ -0,0 +1,158 @@
+use super::Sim;
+use geom::{Duration, Histogram, Statistic, Time};
+use map_model::{BuildingID, LaneID, Map, RoadID};
+use std::collections::BTreeMap;
+
+// Parking and curbside usage
+impl Sim {
+    // The fraction of a garage lane's spots filled at the start of each bucket, from the start of
+    // the day until now. Cars parked before the simulation started never show up in the parking
+    // log, so occupancy is worked out backwards from the cars parked right now. Empty if the lane
+    // isn't part of a garage.
+    pub fn get_parking_garage_occupancy_timeline(
+        &self,
+        lane: LaneID,
+        bucket_size: Duration,
+    ) -> Vec<(Time, f64)> {
+        assert!(bucket_size > Duration::ZERO);
+        let capacity = match self.parking.get_parking_garage_capacity(lane) {
+            Some(cap) if cap > 0 => cap as f64,
+            _ => {
+                return Vec::new();
+            }
+        };
+        let no_changes = Vec::new();
+        let changes = self
+            .analytics
+            .parking_lane_changes
+            .get(&lane)
+            .unwrap_or(&no_changes);
+        let net_change: isize = changes
+            .iter()
+            .map(|(_, filled)| if *filled { 1 } else { -1 })
+            .sum();
+        let mut occupied = self.parking.num_parked_onstreet(lane) as isize - net_change;
+
+        let mut results = Vec::new();
+        let mut idx = 0;
+        let mut t = Time::START_OF_DAY;
+        while t <= self.time {
+            while idx < changes.len() && changes[idx].0 <= t {
+                occupied += if changes[idx].1 { 1 } else { -1 };
+                idx += 1;
+            }
+            results.push((t, (occupied as f64) / capacity));
+            t += bucket_size;
+        }
+        results
+    }
+
+    // If cars keep arriving at this garage lane at the given net rate (arrivals minus departures
+    // per hour), when will it fill up? None if it's not a garage or it's not filling up.
+    pub fn predict_garage_full_time(&self, lane: LaneID, arrivals_per_hour: f64) -> Option<Time> {
+        let capacity = self.parking.get_parking_garage_capacity(lane)?;
+        let free = capacity.saturating_sub(self.parking.num_parked_onstreet(lane));
+        if free == 0 {
+            return Some(self.time);
+        }
+        if arrivals_per_hour <= 0.0 {
+            return None;
+        }
+        Some(self.time + Duration::seconds(3600.0 * (free as f64) / arrivals_per_hour))
+    }
+
+    // How many cars parked for a trip to this fuel or charging station during the last window?
+    pub fn get_fuel_station_demand(&self, station: BuildingID, window: Duration) -> usize {
+        self.analytics
+            .fuel_station_visits
+            .get(&station)
+            .map(|times| {
+                times
+                    .iter()
+                    .filter(|t| **t <= self.time && self.time - **t <= window)
+                    .count()
+            })
+            .unwrap_or(0)
+    }
+
+    // Every fuel and charging station on the map, even unvisited ones
+    pub fn fuel_demand_by_station(
+        &self,
+        window: Duration,
+        map: &Map,
+    ) -> BTreeMap<BuildingID, usize> {
+        map.all_buildings()
+            .iter()
+            .filter(|b| b.is_fuel_station())
+            .map(|b| (b.id, self.get_fuel_station_demand(b.id, window)))
+            .collect()
+    }
+
+    // How busy is the curb along a parking lane or a lane with bus stops, over the last window of
+    // time? Only stays that ended during the window count.
+    pub fn get_curbside_activity(&self, lane: LaneID, window: Duration) -> CurbsideStats {
+        let recent = |t: &Time| *t <= self.time && self.time - *t <= window;
+
+        let mut hgram = Histogram::new();
+        if let Some(dwells) = self.analytics.curbside_dwell_times.get(&lane) {
+            for (t, dwell) in dwells {
+                if recent(t) {
+                    hgram.add(*dwell);
+                }
+            }
+        }
+        let conflicts = self
+            .analytics
+            .curbside_conflicts
+            .get(&lane)
+            .map(|times| times.iter().filter(|t| recent(t)).count())
+            .unwrap_or(0);
+
+        let (dwell_time_mean, dwell_time_p95) = if hgram.count() == 0 {
+            (Duration::ZERO, Duration::ZERO)
+        } else {
+            (
+                hgram.select(Statistic::Mean),
+                hgram.percentile(95.0).unwrap(),
+            )
+        };
+        CurbsideStats {
+            dwell_time_mean,
+            dwell_time_p95,
+            turnover_rate: if window == Duration::ZERO {
+                0.0
+            } else {
+                (hgram.count() as f64) / (window.inner_seconds() / 3600.0)
+            },
+            conflicts,
+        }
+    }
+
+    // Per road with parking, (cars parked + cars searching for parking near a building on the road)
+    // / spots. Over 1 means there's a shortage.
+    pub fn get_parking_pressure_map(&self, map: &Map) -> BTreeMap<RoadID, f64> {
+        let mut demand: BTreeMap<RoadID, usize> = BTreeMap::new();
+        for b in self.driving.parking_search_targets() {
+            *demand
+                .entry(map.get_l(map.get_b(b).sidewalk()).parent)
+                .or_insert(0) += 1;
+        }
+        self.parking
+            .utilization_by_road(map)
+            .into_iter()
+            .map(|(r, (filled, total))| {
+                let searching = demand.get(&r).cloned().unwrap_or(0);
+                (r, ((filled + searching) as f64) / (total as f64))
+            })
+            .collect()
+    }
+}
+
+pub struct CurbsideStats {
+    pub dwell_time_mean: Duration,
+    pub dwell_time_p95: Duration,
+    // Vehicles leaving the curb per hour
+    pub turnover_rate: f64,
+    // How many times a car reached the curb to find its spot taken
+    pub conflicts: usize,
+}
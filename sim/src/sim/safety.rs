@@ -0,0 +1,151 @@
+use super::Sim;
+use crate::{AgentID, CarID, Event, PedestrianLOS, TripMode};
+use geom::{Distance, Duration, Pt2D, Time};
+use map_model::{IntersectionID, LaneID, Map, Position, TurnID, TurnType};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+const PEDESTRIAN_SAFETY_WINDOW: Duration = Duration::const_seconds(100.0);
+const SHARED_STREET_CONFLICT_DIST: Distance = Distance::const_meters(2.0);
+
+// Collisions, conflicts, and pedestrian safety
+impl Sim {
+    // How exposed are pedestrians crossing here to conflicting vehicle turns, based on turning
+    // movement counts over the last PEDESTRIAN_SAFETY_WINDOW? Every pair of a pedestrian and a
+    // vehicle whose turns conflict counts against the score; 1.0 is safest.
+    // TODO Pairs in the same window didn't necessarily cross at the same moment.
+    pub fn get_pedestrian_safety_score(&self, i: IntersectionID, map: &Map) -> f64 {
+        let counts = self.get_turning_movement_counts(i, PEDESTRIAN_SAFETY_WINDOW);
+        let (crossings, vehicles): (Vec<(TurnID, usize)>, Vec<(TurnID, usize)>) = counts
+            .into_iter()
+            .filter(|(t, cnt)| {
+                *cnt > 0 && map.get_t(*t).turn_type != TurnType::SharedSidewalkCorner
+            })
+            .partition(|(t, _)| map.get_t(*t).between_sidewalks());
+        let total_peds: usize = crossings.iter().map(|(_, cnt)| cnt).sum();
+        let total_vehicles: usize = vehicles.iter().map(|(_, cnt)| cnt).sum();
+        if total_peds == 0 || total_vehicles == 0 {
+            return 1.0;
+        }
+
+        let mut conflicts = 0;
+        for (crosswalk, peds) in &crossings {
+            for (turn, cars) in &vehicles {
+                if map.get_t(*crosswalk).conflicts_with(map.get_t(*turn)) {
+                    conflicts += peds * cars;
+                }
+            }
+        }
+        1.0 - (conflicts as f64) / ((total_peds * total_vehicles) as f64)
+    }
+
+    pub fn pedestrian_safety_scores(&self, map: &Map) -> BTreeMap<IntersectionID, f64> {
+        map.all_intersections()
+            .iter()
+            .map(|i| (i.id, self.get_pedestrian_safety_score(i.id, map)))
+            .collect()
+    }
+
+    // Cars never crash in the simulation, but collisions from elsewhere (like a crash model or
+    // real-world reports) can be recorded for analysis.
+    pub fn record_collision(&mut self, car1: CarID, car2: CarID, pos: Position, map: &Map) {
+        self.dispatch_events(
+            vec![Event::CollisionDetected(car1, car2, pos)],
+            map,
+            &mut |_| {},
+        );
+    }
+
+    // How many pairs of agents using different modes are right now within a few meters of each
+    // other on this lane?
+    pub fn get_shared_street_conflicts(&self, lane: LaneID, map: &Map) -> usize {
+        let mut agents: Vec<(AgentID, Distance)> = self
+            .driving
+            .get_cars_on_lane(lane, self.time)
+            .into_iter()
+            .map(|(id, dist)| (AgentID::Car(id), dist))
+            .collect();
+        agents.extend(
+            self.walking
+                .get_peds_on_lane(lane, self.time, map)
+                .into_iter()
+                .map(|(id, dist)| (AgentID::Pedestrian(id), dist)),
+        );
+
+        let mut cnt = 0;
+        for (idx, (a1, dist1)) in agents.iter().enumerate() {
+            for (a2, dist2) in &agents[idx + 1..] {
+                if TripMode::from_agent(*a1) != TripMode::from_agent(*a2)
+                    && (*dist1 - *dist2).abs() <= SHARED_STREET_CONFLICT_DIST
+                {
+                    cnt += 1;
+                }
+            }
+        }
+        cnt
+    }
+
+    // Conflicts between pedestrians and vehicles at the intersection's crosswalks per hour since
+    // the start of the day
+    pub fn shared_street_stress(&self, i: IntersectionID) -> f64 {
+        let hours = (self.time - Time::START_OF_DAY) / Duration::hours(1);
+        if hours == 0.0 {
+            return 0.0;
+        }
+        let cnt = self
+            .analytics
+            .crosswalk_conflicts
+            .iter()
+            .filter(|(_, t, _, _)| t.parent == i)
+            .count();
+        (cnt as f64) / hours
+    }
+
+    // Groups collisions into square cells radius_m wide. Returns the average position and count
+    // of collisions in each cell with at least min_count, most collisions first.
+    pub fn get_collision_hotspots(
+        &self,
+        radius_m: f64,
+        min_count: usize,
+        map: &Map,
+    ) -> Vec<(Pt2D, usize)> {
+        let mut cells: BTreeMap<(i64, i64), Vec<Pt2D>> = BTreeMap::new();
+        for (_, _, _, pos) in &self.analytics.collisions {
+            let pt = pos.pt(map);
+            cells
+                .entry((
+                    (pt.x() / radius_m).floor() as i64,
+                    (pt.y() / radius_m).floor() as i64,
+                ))
+                .or_insert_with(Vec::new)
+                .push(pt);
+        }
+        let mut results: Vec<(Pt2D, usize)> = cells
+            .into_iter()
+            .filter(|(_, pts)| pts.len() >= min_count)
+            .map(|(_, pts)| (Pt2D::center(&pts), pts.len()))
+            .collect();
+        results.sort_by_key(|(_, cnt)| Reverse(*cnt));
+        results
+    }
+
+    // If anybody is waiting to use a crosswalk starting from this sidewalk, how long has the
+    // first of them been waiting?
+    pub fn get_pedestrian_wait_at_crossing(&self, sidewalk: LaneID, map: &Map) -> Option<Duration> {
+        self.intersections
+            .pedestrian_wait_at_crossing(self.time, sidewalk, map)
+    }
+
+    pub fn get_pedestrian_level_of_service(&self, sidewalk: LaneID, map: &Map) -> PedestrianLOS {
+        assert!(map.get_l(sidewalk).is_sidewalk());
+        PedestrianLOS::from_flow(self.walking.pedestrian_flow(sidewalk, map))
+    }
+
+    pub fn ped_los_by_lane(&self, map: &Map) -> BTreeMap<LaneID, PedestrianLOS> {
+        map.all_lanes()
+            .iter()
+            .filter(|l| l.is_sidewalk())
+            .map(|l| (l.id, self.get_pedestrian_level_of_service(l.id, map)))
+            .collect()
+    }
+}
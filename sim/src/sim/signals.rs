@@ -0,0 +1,441 @@
+use super::Sim;
+use crate::IntersectionLOS;
+use abstutil::Timer;
+use geom::{Distance, Duration, Speed, Time};
+use map_model::{EditCmd, EditIntersection, IntersectionID, LaneID, Map, RoadID, TurnID};
+use std::collections::BTreeMap;
+
+// Time between queued cars crossing the stop line once the light turns green, and the extra delay
+// before the first one starts moving
+const SATURATION_HEADWAY: Duration = Duration::const_seconds(2.0);
+const STARTUP_LOST_TIME: Duration = Duration::const_seconds(2.0);
+
+// Traffic signal and corridor performance
+impl Sim {
+    // Green time allocated by the signal, divided by the green time needed to clear the cars
+    // queued right now for each phase's protected movements. Below 1 means the signal can't keep
+    // up with demand. Intersections without a signal are 1.
+    // TODO Uses the current queues, not the queue at the start of each phase.
+    pub fn get_signal_efficiency(&self, id: IntersectionID, map: &Map) -> f64 {
+        let signal = if let Some(ts) = map.maybe_get_traffic_signal(id) {
+            ts
+        } else {
+            return 1.0;
+        };
+        let mut allocated = Duration::ZERO;
+        let mut needed = Duration::ZERO;
+        for phase in &signal.phases {
+            allocated += phase.phase_type.simple_duration();
+            // Lanes discharge in parallel, so the longest queue determines the time needed.
+            let mut per_lane: BTreeMap<LaneID, usize> = BTreeMap::new();
+            for tg in &phase.protected_groups {
+                for t in &signal.turn_groups[tg].members {
+                    *per_lane.entry(t.src).or_insert(0) += self.get_turn_queue_length(*t);
+                }
+            }
+            let longest = per_lane.values().max().cloned().unwrap_or(0);
+            needed += STARTUP_LOST_TIME + (longest as f64) * SATURATION_HEADWAY;
+        }
+        allocated / needed
+    }
+
+    // Theoretical vehicles per hour a traffic signal can serve, using Webster's formula for each
+    // incoming lane and adding them up. The observed volume is the average over the simulation so
+    // far. None for intersections without a signal.
+    // TODO Yield turns don't count towards green time.
+    pub fn get_intersection_vehicle_capacity(&self, id: IntersectionID, map: &Map) -> Option<f64> {
+        let signal = map.maybe_get_traffic_signal(id)?;
+        let cycle_length: Duration = signal
+            .phases
+            .iter()
+            .map(|p| p.phase_type.simple_duration())
+            .sum();
+        let hours = (self.time - Time::START_OF_DAY) / Duration::hours(1);
+        let saturation_flow = Duration::hours(1) / SATURATION_HEADWAY;
+
+        let mut capacity = 0.0;
+        for l in &map.get_i(id).incoming_lanes {
+            if !map.get_l(*l).is_driving() {
+                continue;
+            }
+            let mut green = Duration::ZERO;
+            for phase in &signal.phases {
+                if phase
+                    .protected_groups
+                    .iter()
+                    .any(|tg| signal.turn_groups[tg].members.iter().any(|t| t.src == *l))
+                {
+                    green += phase.phase_type.simple_duration();
+                }
+            }
+            let green_ratio = green / cycle_length;
+            let turns: Vec<TurnID> = map.get_turns_from_lane(*l).iter().map(|t| t.id).collect();
+            let volume = if hours == 0.0 {
+                0.0
+            } else {
+                (self.intersections.turns_without_stopping(&turns).1 as f64) / hours
+            };
+
+            let denominator = 1.0 - green_ratio * volume / saturation_flow;
+            // An oversaturated lane just discharges at the saturation flow while it's green
+            capacity += if denominator > 0.0 {
+                saturation_flow * green_ratio / denominator
+            } else {
+                saturation_flow * green_ratio
+            };
+        }
+        Some(capacity)
+    }
+
+    pub fn signal_efficiency(&self, map: &Map) -> BTreeMap<IntersectionID, f64> {
+        map.all_intersections()
+            .iter()
+            .filter(|i| i.is_traffic_signal())
+            .map(|i| (i.id, self.get_signal_efficiency(i.id, map)))
+            .collect()
+    }
+
+    // How is traffic moving along a corridor, in the order of the roads given? Travel time uses how
+    // fast cars are moving on each road right now, or the speed limit if the road is empty. The
+    // bottleneck is the road with the highest volume to capacity ratio during any hour so far.
+    // Fails if no roads are given or consecutive roads don't meet.
+    pub fn get_corridor_performance(
+        &self,
+        roads: &[RoadID],
+        map: &Map,
+    ) -> Result<CorridorStats, String> {
+        if roads.is_empty() {
+            return Err("a corridor needs at least one road".to_string());
+        }
+        let turns = arterial_turns(roads, map)?;
+        let mut total_length = Distance::ZERO;
+        let mut travel_time = Duration::ZERO;
+        let mut bottleneck: Option<(RoadID, f64)> = None;
+        for r in roads {
+            let length = map.get_r(*r).center_pts.length();
+            let speed = self
+                .get_effective_speed(*r, map)
+                .unwrap_or_else(|| map.get_r(*r).speed_limit);
+            total_length += length;
+            // Stopped traffic doesn't move at all; don't divide by zero.
+            travel_time +=
+                length / Speed::meters_per_second(speed.inner_meters_per_second().max(0.1));
+
+            let vc_ratio = self
+                .congestion_timeline(*r, Duration::hours(1), map)
+                .into_iter()
+                .map(|(_, ratio)| ratio)
+                .fold(0.0, f64::max);
+            if bottleneck
+                .map(|(_, worst)| vc_ratio > worst)
+                .unwrap_or(true)
+            {
+                bottleneck = Some((*r, vc_ratio));
+            }
+        }
+
+        Ok(CorridorStats {
+            total_length,
+            mean_travel_time: travel_time,
+            mean_speed: Speed::meters_per_second(
+                total_length.inner_meters() / travel_time.inner_seconds(),
+            ),
+            worst_bottleneck_road: bottleneck.unwrap().0,
+            signal_efficiency: turns
+                .into_iter()
+                .filter(|(i, _)| map.get_i(*i).is_traffic_signal())
+                .map(|(i, _)| (i, self.get_signal_efficiency(i, map)))
+                .collect(),
+        })
+    }
+
+    // Driving along the arterial in the order of the roads given, what fraction of vehicles make it
+    // through each traffic signal without stopping? Returns the product over all signals, so 1.0 is
+    // a perfect green wave. Signals nobody's gone through yet are ignored. Fails if consecutive
+    // roads don't meet.
+    pub fn get_green_wave_quality(&self, arterial: &[RoadID], map: &Map) -> Result<f64, String> {
+        let mut quality = 1.0;
+        for (i, turns) in arterial_turns(arterial, map)? {
+            if !map.get_i(i).is_traffic_signal() {
+                continue;
+            }
+            let (nonstop, total) = self.intersections.turns_without_stopping(&turns);
+            if total > 0 {
+                quality *= (nonstop as f64) / (total as f64);
+            }
+        }
+        Ok(quality)
+    }
+
+    // Changes signal offsets so that a vehicle driving along the arterial at this speed arrives
+    // at each signal at the same point in its cycle as it left the first one, then applies them
+    // through map edits. Since live map edits aren't supported, this only works before the
+    // simulation starts. Returns the offsets that changed.
+    pub fn optimize_green_wave(
+        &mut self,
+        arterial: &[RoadID],
+        speed: Speed,
+        map: &mut Map,
+        timer: &mut Timer,
+    ) -> Result<BTreeMap<IntersectionID, Duration>, String> {
+        if self.time != Time::START_OF_DAY {
+            return Err(format!(
+                "the simulation has already started at {}; live map edits aren't supported",
+                self.time
+            ));
+        }
+
+        let turns = arterial_turns(arterial, map)?;
+        let mut edits = map.get_edits().clone();
+        let mut offsets = BTreeMap::new();
+        let mut travel_time = Duration::ZERO;
+        // When the vehicle leaves the first signal, and how far into its cycle that is
+        let mut first: Option<(Duration, Duration)> = None;
+        for (idx, (i, _)) in turns.into_iter().enumerate() {
+            // Time to drive the road leading up to this intersection
+            travel_time += map.get_r(arterial[idx]).center_pts.length() / speed;
+            if let Some(orig) = map.maybe_get_traffic_signal(i) {
+                let cycle_length: Duration = orig
+                    .phases
+                    .iter()
+                    .map(|p| p.phase_type.simple_duration())
+                    .sum();
+                let (start_time, start_offset) = match first {
+                    Some(pair) => pair,
+                    None => {
+                        first = Some((travel_time, orig.offset));
+                        continue;
+                    }
+                };
+                let behind = (travel_time - start_time) % cycle_length;
+                let offset = (start_offset % cycle_length + cycle_length - behind) % cycle_length;
+                if offset != orig.offset {
+                    let mut signal = orig.clone();
+                    signal.offset = offset;
+                    edits.commands.push(EditCmd::ChangeIntersection {
+                        i,
+                        old: map.get_i_edit(i),
+                        new: EditIntersection::TrafficSignal(signal),
+                    });
+                    offsets.insert(i, offset);
+                }
+            }
+        }
+        if offsets.is_empty() {
+            return Ok(offsets);
+        }
+
+        map.apply_edits(edits, timer);
+        map.recalculate_pathfinding_after_edits(timer);
+        for i in offsets.keys() {
+            self.intersections
+                .restart_signal(*i, map, &mut self.scheduler);
+        }
+        Ok(offsets)
+    }
+
+    // The mean delay of every turn, weighted by how many agents did it. Delay is only measured at
+    // traffic signals, so anything else (or a signal nobody's used yet) grades A.
+    pub fn get_intersection_los(&self, id: IntersectionID) -> IntersectionLOS {
+        let mut total = Duration::ZERO;
+        let mut count = 0;
+        if let Some(turns) = self.analytics.turning_movements.counts.get(&id) {
+            for (t, times) in turns {
+                if let Some(delay) = self.get_turn_delay(*t) {
+                    total += delay * (times.len() as f64);
+                    count += times.len();
+                }
+            }
+        }
+        if count == 0 {
+            return IntersectionLOS::A;
+        }
+        IntersectionLOS::from_delay(total / (count as f64))
+    }
+
+    pub fn intersection_los(&self, map: &Map) -> BTreeMap<IntersectionID, IntersectionLOS> {
+        map.all_intersections()
+            .iter()
+            .filter(|i| i.is_traffic_signal())
+            .map(|i| (i.id, self.get_intersection_los(i.id)))
+            .collect()
+    }
+
+    // For every pair of turns in the intersection, do their paths physically cross? Symmetric.
+    // Purely geometric; doesn't depend on the simulation at all.
+    pub fn get_turn_conflict_matrix(
+        &self,
+        i: IntersectionID,
+        map: &Map,
+    ) -> BTreeMap<(TurnID, TurnID), bool> {
+        let conflicts = map.get_turn_conflicts(i);
+        let mut matrix = BTreeMap::new();
+        for t1 in &map.get_i(i).turns {
+            for t2 in &map.get_i(i).turns {
+                if t1 != t2 {
+                    matrix.insert((*t1, *t2), conflicts.contains(&(*t1, *t2)));
+                }
+            }
+        }
+        matrix
+    }
+}
+
+pub struct CorridorStats {
+    pub total_length: Distance,
+    pub mean_travel_time: Duration,
+    pub mean_speed: Speed,
+    pub worst_bottleneck_road: RoadID,
+    // See get_signal_efficiency
+    pub signal_efficiency: Vec<(IntersectionID, f64)>,
+}
+
+// For each consecutive pair of roads, the intersection between them and the vehicle turns going
+// from the first to the second. Fails if two consecutive roads don't meet.
+fn arterial_turns(
+    arterial: &[RoadID],
+    map: &Map,
+) -> Result<Vec<(IntersectionID, Vec<TurnID>)>, String> {
+    let mut results = Vec::new();
+    for pair in arterial.windows(2) {
+        let (r1, r2) = (map.get_r(pair[0]), map.get_r(pair[1]));
+        let i = if r1.dst_i == r2.src_i || r1.dst_i == r2.dst_i {
+            r1.dst_i
+        } else if r1.src_i == r2.src_i || r1.src_i == r2.dst_i {
+            r1.src_i
+        } else {
+            return Err(format!("{} and {} don't meet", r1.id, r2.id));
+        };
+        let turns = map
+            .get_i(i)
+            .turns
+            .iter()
+            .filter(|t| {
+                !map.get_t(**t).between_sidewalks()
+                    && map.get_l(t.src).parent == r1.id
+                    && map.get_l(t.dst).parent == r2.id
+            })
+            .cloned()
+            .collect();
+        results.push((i, turns));
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DrivingGoal, SimOptions, TripEndpoint, MAX_CAR_LENGTH};
+    use map_model::raw::{OriginalIntersection, OriginalRoad, RawMap};
+    use map_model::{IntersectionType, LaneType, Position, RoadSpec};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    // A straight arterial through 4 traffic signals, with a side street at each one. The arterial
+    // starts and ends at borders.
+    fn arterial_map() -> (Map, Vec<RoadID>) {
+        let spec = RoadSpec {
+            fwd: vec![LaneType::Driving, LaneType::Sidewalk],
+            back: vec![LaneType::Driving, LaneType::Sidewalk],
+        };
+        let mut raw = RawMap::synthetic_grid(6, 2, &spec);
+        // Trim the bottom corners, so both ends of the arterial only have one road
+        for (id, i1, i2) in vec![(2, 1, 7), (3, 7, 8), (15, 11, 12), (16, 6, 12)] {
+            raw.roads.remove(&OriginalRoad {
+                osm_way_id: id,
+                i1: OriginalIntersection { osm_node_id: i1 },
+                i2: OriginalIntersection { osm_node_id: i2 },
+            });
+        }
+        for id in vec![7, 12] {
+            raw.intersections
+                .remove(&OriginalIntersection { osm_node_id: id });
+        }
+        for (id, intersection_type) in vec![
+            (1, IntersectionType::Border),
+            (2, IntersectionType::TrafficSignal),
+            (3, IntersectionType::TrafficSignal),
+            (4, IntersectionType::TrafficSignal),
+            (5, IntersectionType::TrafficSignal),
+            (6, IntersectionType::Border),
+        ] {
+            raw.intersections
+                .get_mut(&OriginalIntersection { osm_node_id: id })
+                .unwrap()
+                .intersection_type = intersection_type;
+        }
+        let map = Map::create_from_raw(raw, true, &mut Timer::throwaway());
+        let arterial = vec![1, 4, 7, 10, 13]
+            .into_iter()
+            .map(|id| {
+                map.all_roads()
+                    .iter()
+                    .find(|r| r.orig_id.osm_way_id == id)
+                    .unwrap()
+                    .id
+            })
+            .collect();
+        (map, arterial)
+    }
+
+    // Send cars down the whole arterial every so often, and measure the green wave.
+    fn drive_arterial(optimize: bool) -> f64 {
+        let (mut map, arterial) = arterial_map();
+        let mut sim = Sim::new(&map, SimOptions::new("test"), &mut Timer::throwaway());
+        if optimize {
+            let offsets = sim
+                .optimize_green_wave(
+                    &arterial,
+                    map.get_r(arterial[0]).speed_limit,
+                    &mut map,
+                    &mut Timer::throwaway(),
+                )
+                .unwrap();
+            assert!(!offsets.is_empty());
+        }
+
+        let first = map.get_r(arterial[0]);
+        let last = map.get_r(*arterial.last().unwrap());
+        let start_lane = first.children_forwards[0].0;
+        let end_lane = last.children_forwards[0].0;
+        let mut rng = XorShiftRng::from_seed([0; 16]);
+        for idx in 0..30 {
+            sim.start_trip_using_adaptive_routing(
+                Time::START_OF_DAY + Duration::seconds(17.0 * (idx as f64)),
+                TripEndpoint::Border(first.src_i, None),
+                Position::new(start_lane, MAX_CAR_LENGTH),
+                DrivingGoal::Border(last.dst_i, end_lane, None),
+                &map,
+                &mut rng,
+            )
+            .unwrap();
+        }
+        sim.run_until_done(&map, |_, _| {}, Some(Duration::hours(1)));
+        sim.get_green_wave_quality(&arterial, &map).unwrap()
+    }
+
+    #[test]
+    fn test_optimizing_offsets_improves_green_wave() {
+        let before = drive_arterial(false);
+        let after = drive_arterial(true);
+        assert!(after > before, "before {}, after {}", before, after);
+    }
+
+    #[test]
+    fn test_arterial_roads_must_meet() {
+        let (mut map, arterial) = arterial_map();
+        let mut sim = Sim::new(&map, SimOptions::new("test"), &mut Timer::throwaway());
+        let broken = vec![arterial[0], arterial[2]];
+        assert!(sim.get_green_wave_quality(&broken, &map).is_err());
+        assert!(sim.get_corridor_performance(&broken, &map).is_err());
+        assert!(sim.get_corridor_performance(&[], &map).is_err());
+        assert!(sim
+            .optimize_green_wave(
+                &broken,
+                Speed::miles_per_hour(25.0),
+                &mut map,
+                &mut Timer::throwaway()
+            )
+            .is_err());
+    }
+}
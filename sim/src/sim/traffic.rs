@@ -0,0 +1,445 @@
+use super::{path_distance, road_capacity_per_hour, Sim};
+use crate::{AgentID, CarID, TripEndpoint, TripID, TripMode, TripPhaseType, VehicleType};
+use geom::{Distance, Duration, Speed, Time};
+use map_model::{
+    connectivity, BuildingID, IntersectionID, LaneID, LaneType, Map, PathRequest, RoadID, TurnID,
+};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+// A common policy target for how long trips should take
+const TRAVEL_TIME_BUDGET: Duration = Duration::const_seconds(1800.0);
+
+// Congestion and network flow
+impl Sim {
+    // From front to back, including this car
+    pub fn get_platoon(&self, car: CarID) -> Vec<CarID> {
+        self.driving.get_platoon(car)
+    }
+
+    // Which sidewalks did people walking between two buildings take, and how many people took
+    // each route? Most common routes first. Unless SimOptions::pedestrian_route_choices is set,
+    // everybody takes the shortest path, so expect one route.
+    pub fn get_pedestrian_route_choice_stats(
+        &self,
+        origin: BuildingID,
+        dest: BuildingID,
+    ) -> RouteChoiceStats {
+        let mut routes: Vec<(Vec<LaneID>, usize)> = Vec::new();
+        for (lanes, trips) in &self.analytics.walking_routes {
+            let cnt = trips
+                .iter()
+                .filter(|t| {
+                    let (_, start, end, _) = self.trips.trip_info(**t);
+                    start == TripEndpoint::Bldg(origin) && end == TripEndpoint::Bldg(dest)
+                })
+                .count();
+            if cnt > 0 {
+                routes.push((lanes.clone(), cnt));
+            }
+        }
+        routes.sort_by_key(|(_, cnt)| Reverse(*cnt));
+        RouteChoiceStats { routes }
+    }
+
+    pub fn get_density_wave_profile(&self, lane: LaneID, map: &Map) -> Vec<(f64, f64)> {
+        self.driving.get_density_wave_profile(lane, self.time, map)
+    }
+
+    // Finds the biggest jump in density (cars per meter) between neighboring segments of a lane.
+    // If it exceeds the threshold, returns the percent along the lane where it happens.
+    pub fn detect_shockwave(&self, lane: LaneID, threshold: f64, map: &Map) -> Option<f64> {
+        let profile = self.get_density_wave_profile(lane, map);
+        let mut best: Option<(f64, f64)> = None;
+        for pair in profile.windows(2) {
+            let jump = (pair[1].1 - pair[0].1).abs();
+            if jump > threshold && best.map(|(_, j)| jump > j).unwrap_or(true) {
+                best = Some(((pair[0].0 + pair[1].0) / 2.0, jump));
+            }
+        }
+        best.map(|(pct, _)| pct)
+    }
+
+    // How many times did each agent find a new path during the last window? Agents that didn't
+    // aren't included.
+    pub fn get_rerouting_frequency(&self, window: Duration) -> BTreeMap<AgentID, usize> {
+        let mut results = BTreeMap::new();
+        for (agent, times) in &self.analytics.reroutes {
+            let cnt = times
+                .iter()
+                .filter(|t| **t <= self.time && self.time - **t <= window)
+                .count();
+            if cnt > 0 {
+                results.insert(*agent, cnt);
+            }
+        }
+        results
+    }
+
+    // Agents that rerouted more than threshold times in the last window, probably oscillating
+    // between routes
+    pub fn hyperactive_rerouters(&self, window: Duration, threshold: usize) -> Vec<AgentID> {
+        self.get_rerouting_frequency(window)
+            .into_iter()
+            .filter(|(_, cnt)| *cnt > threshold)
+            .map(|(agent, _)| agent)
+            .collect()
+    }
+
+    // How many times did the car switch to a less crowded lane during the last window?
+    pub fn get_lane_change_frequency(&self, car: CarID, window: Duration) -> usize {
+        self.analytics
+            .lane_changes
+            .get(&car)
+            .map(|times| {
+                times
+                    .iter()
+                    .filter(|t| **t <= self.time && self.time - **t <= window)
+                    .count()
+            })
+            .unwrap_or(0)
+    }
+
+    // The n cars changing lanes the most during the last window, most first
+    pub fn most_aggressive_lane_changers(&self, window: Duration, n: usize) -> Vec<(CarID, usize)> {
+        let mut results: Vec<(CarID, usize)> = self
+            .analytics
+            .lane_changes
+            .keys()
+            .map(|car| (*car, self.get_lane_change_frequency(*car, window)))
+            .filter(|(_, cnt)| *cnt > 0)
+            .collect();
+        results.sort_by_key(|(_, cnt)| Reverse(*cnt));
+        results.truncate(n);
+        results
+    }
+
+    // Every time a vehicle made a turn it isn't allowed to. These usually mean pathfinding is
+    // routing through prohibited turns.
+    pub fn get_turn_prohibition_violations(&self) -> Vec<(CarID, TurnID, Time)> {
+        self.analytics
+            .turn_violations
+            .iter()
+            .map(|(time, car, t)| (*car, *t, *time))
+            .collect()
+    }
+
+    // Volume to capacity ratio of vehicles entering a road during each bucket, from the start of
+    // the day until now. Throughput is only counted per hour, so each hour's count is assumed to
+    // be spread evenly over that hour.
+    pub fn congestion_timeline(
+        &self,
+        road: RoadID,
+        bucket_size: Duration,
+        map: &Map,
+    ) -> Vec<(Time, f64)> {
+        assert!(bucket_size > Duration::ZERO);
+        let capacity = road_capacity_per_hour(road, map);
+        let mut per_hour: BTreeMap<usize, usize> = BTreeMap::new();
+        for ((r, mode, hour), cnt) in &self.analytics.road_thruput.counts {
+            if *r == road && *mode != TripMode::Walk {
+                *per_hour.entry(*hour).or_insert(0) += *cnt;
+            }
+        }
+
+        let mut results = Vec::new();
+        let mut start = Time::START_OF_DAY;
+        while start < self.time {
+            let end = (start + bucket_size).min(self.time);
+            let mut volume = 0.0;
+            for (hour, cnt) in &per_hour {
+                let hour_start = Time::START_OF_DAY + Duration::hours(*hour);
+                let hour_end = hour_start + Duration::hours(1);
+                let overlap = end.min(hour_end) - start.max(hour_start);
+                if overlap > Duration::ZERO {
+                    volume += (*cnt as f64) * (overlap / Duration::hours(1));
+                }
+            }
+            let ratio = if capacity == 0.0 {
+                0.0
+            } else {
+                volume / (capacity * ((end - start) / Duration::hours(1)))
+            };
+            results.push((start, ratio));
+            start += bucket_size;
+        }
+        results
+    }
+
+    // The hour with the highest volume to capacity ratio on a road, or None if no vehicles have
+    // used it yet. Since throughput is spread evenly over each hour, the best hour-long window
+    // always lines up with an hour boundary.
+    pub fn get_max_congestion_window(&self, road: RoadID, map: &Map) -> Option<(Time, Time)> {
+        let mut best: Option<(Time, f64)> = None;
+        for (t, ratio) in self.congestion_timeline(road, Duration::hours(1), map) {
+            if ratio > 0.0 && best.map(|(_, r)| ratio > r).unwrap_or(true) {
+                best = Some((t, ratio));
+            }
+        }
+        best.map(|(t, _)| (t, t + Duration::hours(1)))
+    }
+
+    // How many agents finished each turn in the last window of time?
+    pub fn get_turning_movement_counts(
+        &self,
+        i: IntersectionID,
+        window: Duration,
+    ) -> BTreeMap<TurnID, usize> {
+        self.analytics
+            .turning_movements
+            .counts_in_window(i, self.time, window)
+    }
+
+    // The turning movement counts during the intersection's busiest hour so far
+    pub fn peak_hour_tmcs(&self, i: IntersectionID) -> BTreeMap<TurnID, usize> {
+        self.analytics.turning_movements.peak_hour(i)
+    }
+
+    // System-wide totals over every driving and bus riding phase that's finished so far. Like
+    // get_detour_factor, this recalculates paths, so it's expensive.
+    pub fn get_network_flow_stats(&self, map: &Map) -> NetworkFlowStats {
+        let mut vehicle_dist = Distance::ZERO;
+        let mut passenger_dist = Distance::ZERO;
+        let mut driving_time = Duration::ZERO;
+        let mut delay = Duration::ZERO;
+        let mut vehicles = 0;
+
+        // When the current phase of each trip started
+        let mut current: BTreeMap<TripID, (Time, Option<PathRequest>, TripPhaseType)> =
+            BTreeMap::new();
+        for (t, id, maybe_req, phase_type) in &self.analytics.trip_log {
+            if let Some((start, Some(req), prev_type)) =
+                current.insert(*id, (*t, maybe_req.clone(), *phase_type))
+            {
+                let driving = prev_type == TripPhaseType::Driving;
+                if !driving && !matches!(prev_type, TripPhaseType::RidingBus(_, _, _)) {
+                    continue;
+                }
+                let path = if let Some(p) = map.pathfind(req.clone()) {
+                    p
+                } else {
+                    continue;
+                };
+                let dist = path_distance(&req, &path, map);
+                passenger_dist += dist;
+                if driving {
+                    vehicle_dist += dist;
+                    driving_time += *t - start;
+                    vehicles += 1;
+                    let free_flow: Duration = path
+                        .get_steps()
+                        .iter()
+                        .map(|step| {
+                            let on = step.as_traversable();
+                            on.length(map) / on.speed_limit(map)
+                        })
+                        .sum();
+                    // Paths include entire lanes, so the free-flow time is a bit too long.
+                    delay += (*t - start - free_flow).max(Duration::ZERO);
+                }
+            }
+        }
+
+        let hours = (self.time - Time::START_OF_DAY).inner_seconds() / 3600.0;
+        NetworkFlowStats {
+            total_vkt: vehicle_dist.inner_meters() / 1000.0,
+            total_pkt: passenger_dist.inner_meters() / 1000.0,
+            mean_speed_ms: if driving_time == Duration::ZERO {
+                0.0
+            } else {
+                vehicle_dist.inner_meters() / driving_time.inner_seconds()
+            },
+            total_delay_s: delay.inner_seconds(),
+            throughput_veh_per_hour: if hours == 0.0 {
+                0.0
+            } else {
+                (vehicles as f64) / hours
+            },
+            network_delay_index: self.get_network_delay_index(),
+            vehicle_hours_delay: self.get_vehicle_hours_of_delay(TripMode::Drive),
+            vkt_by_vehicle_type: self
+                .get_network_vmt_by_vehicle_type()
+                .into_iter()
+                .map(|(vt, dist)| (vt, dist.inner_meters() / 1000.0))
+                .collect(),
+            mean_idle_time_by_vehicle_type: self
+                .driving
+                .mean_idle_time_by_type(self.time)
+                .into_iter()
+                .map(|(vt, dt)| (vt, dt.inner_seconds()))
+                .collect(),
+            mean_parking_egress_s: self
+                .mean_parking_egress_time()
+                .map(|dt| dt.inner_seconds())
+                .unwrap_or(0.0),
+            effective_speed_by_road: self
+                .get_effective_speed_by_road(map)
+                .into_iter()
+                .map(|(r, speed)| (r, speed.inner_meters_per_second()))
+                .collect(),
+            compliance_by_mode: TripMode::all()
+                .into_iter()
+                .map(|mode| {
+                    (
+                        mode,
+                        self.get_travel_time_budget_compliance(TRAVEL_TIME_BUDGET, mode),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    // The average current speed of cars on each road's driving lanes. Roads without any cars aren't
+    // included.
+    pub fn get_effective_speed_by_road(&self, map: &Map) -> BTreeMap<RoadID, Speed> {
+        let mut result = BTreeMap::new();
+        for r in map.all_roads() {
+            if let Some(speed) = self.get_effective_speed(r.id, map) {
+                result.insert(r.id, speed);
+            }
+        }
+        result
+    }
+
+    pub(super) fn get_effective_speed(&self, road: RoadID, map: &Map) -> Option<Speed> {
+        let r = map.get_r(road);
+        let mut total = 0.0;
+        let mut count = 0;
+        for (l, lt) in r
+            .children_forwards
+            .iter()
+            .chain(r.children_backwards.iter())
+        {
+            if *lt != LaneType::Driving {
+                continue;
+            }
+            for speed in self.driving.get_speeds_on_lane(*l) {
+                total += speed.inner_meters_per_second();
+                count += 1;
+            }
+        }
+        if count == 0 {
+            return None;
+        }
+        Some(Speed::meters_per_second(total / (count as f64)))
+    }
+
+    // The effective speed of each road divided by its speed limit. Below 1 means congestion.
+    pub fn speed_ratio_by_road(&self, map: &Map) -> BTreeMap<RoadID, f64> {
+        self.get_effective_speed_by_road(map)
+            .into_iter()
+            .map(|(r, speed)| {
+                (
+                    r,
+                    speed.inner_meters_per_second()
+                        / map.get_r(r).speed_limit.inner_meters_per_second(),
+                )
+            })
+            .collect()
+    }
+
+    // Total time of every finished trip, divided by how long they'd take with no congestion. 1.0
+    // means no delay at all.
+    // TODO Riding transit isn't counted in the free-flow time, so transit trips are skipped.
+    pub fn get_network_delay_index(&self) -> f64 {
+        let mut actual = Duration::ZERO;
+        let mut free_flow = Duration::ZERO;
+        for (_, trip, mode, dt) in &self.analytics.finished_trips {
+            if mode.is_none() || *mode == Some(TripMode::Transit) {
+                continue;
+            }
+            actual += *dt;
+            free_flow += self.trips.free_flow_time(*trip);
+        }
+        if free_flow == Duration::ZERO {
+            return 1.0;
+        }
+        actual / free_flow
+    }
+
+    // Mean delay for agents doing this turn. Only measured at traffic signals.
+    pub fn get_turn_delay(&self, turn: TurnID) -> Option<Duration> {
+        self.intersections.turn_delay(turn)
+    }
+
+    pub fn worst_turn_delays(&self, n: usize) -> Vec<(TurnID, Duration)> {
+        self.intersections.worst_turn_delays(n)
+    }
+
+    // Roads that nothing entering the map can ever reach
+    pub fn get_dead_end_roads(&self, map: &Map) -> Vec<RoadID> {
+        connectivity::roads_unreachable_from_borders(map)
+    }
+
+    // For every lane leading to the intersection, how much of it is filled by the queue of stopped
+    // cars? 1.0 means the queue reaches back to the previous intersection.
+    pub fn get_queue_spillback(&self, i: IntersectionID, map: &Map) -> Vec<(LaneID, f64)> {
+        map.get_i(i)
+            .incoming_lanes
+            .iter()
+            .filter(|l| map.get_l(**l).lane_type.is_for_moving_vehicles())
+            .map(|l| {
+                let len = map.get_l(*l).length();
+                let tail = self.driving.queue_tail_position(*l, self.time);
+                (*l, ((len - tail) / len).max(0.0).min(1.0))
+            })
+            .collect()
+    }
+
+    pub fn get_turn_queue_length(&self, turn: TurnID) -> usize {
+        self.driving.turn_queue_length(turn)
+    }
+
+    // Total distance driven by a vehicle so far, including all of its previous trips. None if the
+    // vehicle has never driven (a car that's only ever been parked, for instance).
+    pub fn get_car_miles_traveled(&self, car: CarID) -> Option<Distance> {
+        self.driving.get_cumulative_distance(car)
+    }
+
+    // How long the vehicle has spent stopped in traffic over the last window of time. Parked cars
+    // aren't idling. Idling is only remembered in detail for the last hour, so windows that start
+    // earlier than that, but after the beginning of the day, may be None.
+    pub fn get_vehicle_idle_time(&self, car: CarID, window: Duration) -> Option<Duration> {
+        self.driving.get_idle_time(car, self.time, window)
+    }
+
+    pub fn total_vehicle_miles_traveled(&self) -> Distance {
+        self.driving.total_cumulative_distance()
+    }
+
+    // Like get_car_miles_traveled, summed over every vehicle of each type that's ever driven
+    pub fn get_network_vmt_by_vehicle_type(&self) -> BTreeMap<VehicleType, Distance> {
+        self.driving.cumulative_distance_by_type()
+    }
+}
+
+pub struct RouteChoiceStats {
+    // Each distinct sequence of sidewalks, with the number of trips that took it
+    pub routes: Vec<(Vec<LaneID>, usize)>,
+}
+
+pub struct NetworkFlowStats {
+    // Vehicle-km traveled by cars and trucks. Buses aren't included.
+    pub total_vkt: f64,
+    // Passenger-km: one person per vehicle, plus bus riders
+    pub total_pkt: f64,
+    pub mean_speed_ms: f64,
+    // Compared to driving everything at the speed limit
+    pub total_delay_s: f64,
+    // Driving phases finished per hour of simulation so far
+    pub throughput_veh_per_hour: f64,
+    // See get_network_delay_index
+    pub network_delay_index: f64,
+    // For driving trips; see get_vehicle_hours_of_delay
+    pub vehicle_hours_delay: f64,
+    // Unlike total_vkt, this counts every vehicle's actual movement, including buses
+    pub vkt_by_vehicle_type: BTreeMap<VehicleType, f64>,
+    // Seconds spent stopped in traffic, averaged over every vehicle that's driven
+    pub mean_idle_time_by_vehicle_type: BTreeMap<VehicleType, f64>,
+    // Walking from the parked car to the destination. 0 if no trips have done this yet.
+    pub mean_parking_egress_s: f64,
+    // See get_effective_speed_by_road, in meters per second
+    pub effective_speed_by_road: BTreeMap<RoadID, f64>,
+    // The fraction of finished trips taking at most TRAVEL_TIME_BUDGET
+    pub compliance_by_mode: BTreeMap<TripMode, f64>,
+}
@@ -0,0 +1,254 @@
+use super::Sim;
+use crate::{CarID, PedestrianID, TripEndpoint};
+use geom::{Distance, Duration, Time};
+use map_model::{
+    connectivity, BuildingID, BuildingType, BusRouteID, BusStopID, Map, PathConstraints,
+    PathRequest,
+};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+
+// How close to a bus stop somebody has to be to count as being there
+const BUS_STOP_TOLERANCE: Distance = Distance::const_meters(5.0);
+
+// Transit ridership and stops
+impl Sim {
+    pub fn get_stalled_buses(&self, threshold: Duration) -> Vec<(CarID, BusRouteID, Duration)> {
+        self.transit.stalled_buses(self.time, threshold)
+    }
+
+    // (route, stop, time of the second arrival)
+    pub fn get_bus_bunching_events(&self, window: Duration) -> Vec<(BusRouteID, BusStopID, Time)> {
+        self.analytics.bus_bunching(self.time, window)
+    }
+
+    // Every building within walking distance of some stop on the route, sorted by ID
+    pub fn get_service_area_coverage(
+        &self,
+        route: BusRouteID,
+        walk_distance_m: f64,
+        map: &Map,
+    ) -> Vec<BuildingID> {
+        let mut bldgs = BTreeSet::new();
+        for stop in &map.get_br(route).stops {
+            bldgs.extend(connectivity::walking_isochrone(
+                map,
+                map.get_bs(*stop).sidewalk_pos,
+                Distance::meters(walk_distance_m),
+            ));
+        }
+        bldgs.into_iter().collect()
+    }
+
+    // Buildings not within walking distance of any bus stop
+    pub fn get_transit_coverage_gap(&self, map: &Map, walk_distance_m: f64) -> Vec<BuildingID> {
+        let mut covered = BTreeSet::new();
+        for route in map.get_all_bus_routes() {
+            covered.extend(self.get_service_area_coverage(route.id, walk_distance_m, map));
+        }
+        map.all_buildings()
+            .iter()
+            .map(|b| b.id)
+            .filter(|b| !covered.contains(b))
+            .collect()
+    }
+
+    pub fn coverage_gap_by_building_type(
+        &self,
+        map: &Map,
+        walk_distance_m: f64,
+    ) -> BTreeMap<BuildingType, usize> {
+        let mut results = BTreeMap::new();
+        for b in self.get_transit_coverage_gap(map, walk_distance_m) {
+            *results.entry(map.get_b(b).bldg_type()).or_insert(0) += 1;
+        }
+        results
+    }
+
+    // How many people live in the route's service area? Somebody's home is where their first trip
+    // starts.
+    pub fn service_area_population_coverage(
+        &self,
+        route: BusRouteID,
+        walk_distance_m: f64,
+        map: &Map,
+    ) -> usize {
+        let bldgs: BTreeSet<BuildingID> = self
+            .get_service_area_coverage(route, walk_distance_m, map)
+            .into_iter()
+            .collect();
+        self.trips
+            .get_all_people()
+            .iter()
+            .filter(|p| {
+                p.trips
+                    .get(0)
+                    .map_or(false, |t| match self.trips.trip_info(*t).1 {
+                        TripEndpoint::Bldg(b) => bldgs.contains(&b),
+                        TripEndpoint::Border(_, _) => false,
+                    })
+            })
+            .count()
+    }
+
+    // The coefficient of variation of the route's headways at all stops. 0 means perfectly regular
+    // service; anything much over 1 is chaotic. Also 0 if there aren't any headways yet.
+    pub fn get_bus_reliability_index(&self, route: BusRouteID) -> f64 {
+        let headways: Vec<f64> = self
+            .analytics
+            .bus_headways(self.time, route)
+            .into_iter()
+            .map(|d| d.inner_seconds())
+            .collect();
+        if headways.is_empty() {
+            return 0.0;
+        }
+        let n = headways.len() as f64;
+        let mean = headways.iter().sum::<f64>() / n;
+        if mean == 0.0 {
+            return 0.0;
+        }
+        let variance = headways.iter().map(|h| (h - mean).powi(2)).sum::<f64>() / n;
+        variance.sqrt() / mean
+    }
+
+    pub fn bus_reliability(&self, map: &Map) -> BTreeMap<BusRouteID, f64> {
+        map.get_all_bus_routes()
+            .iter()
+            .map(|r| (r.id, self.get_bus_reliability_index(r.id)))
+            .collect()
+    }
+
+    // How far apart are consecutive stops along a route, measured along the bus's path? For each
+    // pair closer than min_spacing (300m is typical), recommends removing whichever stop has had
+    // fewer boardings and alightings so far. None if the route has fewer than two stops or the bus
+    // can't get between them.
+    pub fn get_stop_spacing_analysis(
+        &self,
+        route: BusRouteID,
+        min_spacing: Distance,
+        map: &Map,
+    ) -> Option<StopSpacingStats> {
+        let stops = &map.get_br(route).stops;
+        let mut spacings = Vec::new();
+        for pair in stops.windows(2) {
+            let path = map.pathfind(PathRequest {
+                start: map.get_bs(pair[0]).driving_pos,
+                end: map.get_bs(pair[1]).driving_pos,
+                constraints: PathConstraints::Bus,
+            })?;
+            spacings.push((pair[0], pair[1], path.total_length()));
+        }
+        if spacings.is_empty() {
+            return None;
+        }
+
+        let window = self.time - Time::START_OF_DAY;
+        let usage = |stop: BusStopID| {
+            let demand = self.get_bus_stop_demand(stop, window);
+            demand.boardings + demand.alightings
+        };
+        let mut recommended_removals = Vec::new();
+        for (stop1, stop2, dist) in &spacings {
+            if *dist >= min_spacing {
+                continue;
+            }
+            let remove = if usage(*stop1) <= usage(*stop2) {
+                *stop1
+            } else {
+                *stop2
+            };
+            if !recommended_removals.contains(&remove) {
+                recommended_removals.push(remove);
+            }
+        }
+
+        let dists: Vec<Distance> = spacings.into_iter().map(|(_, _, dist)| dist).collect();
+        Some(StopSpacingStats {
+            min_spacing: dists.iter().min().cloned().unwrap(),
+            max_spacing: dists.iter().max().cloned().unwrap(),
+            mean_spacing: dists.iter().fold(Distance::ZERO, |sum, d| sum + *d)
+                / (dists.len() as f64),
+            recommended_removals,
+        })
+    }
+
+    // Everybody waiting for a bus at the stop, plus anybody else on the sidewalk right there
+    pub fn get_pedestrians_at_transit_stop(&self, stop: BusStopID, map: &Map) -> Vec<PedestrianID> {
+        let mut peds: BTreeSet<PedestrianID> = self
+            .transit
+            .get_peds_waiting_at_stop(stop)
+            .into_iter()
+            .collect();
+        peds.extend(self.walking.get_peds_near(
+            map.get_bs(stop).sidewalk_pos,
+            BUS_STOP_TOLERANCE,
+            self.time,
+            map,
+        ));
+        peds.into_iter().collect()
+    }
+
+    // Boardings and alightings at a stop over the last window of time
+    pub fn get_bus_stop_demand(&self, stop: BusStopID, window: Duration) -> BusStopDemand {
+        let recent = |t: &Time| *t <= self.time && self.time - *t <= window;
+
+        let mut boardings_per_time: BTreeMap<Time, usize> = BTreeMap::new();
+        let mut total_wait = Duration::ZERO;
+        if let Some(boardings) = self.analytics.bus_boardings.get(&stop) {
+            for (t, wait) in boardings {
+                if recent(t) {
+                    *boardings_per_time.entry(*t).or_insert(0) += 1;
+                    total_wait += *wait;
+                }
+            }
+        }
+        let boardings: usize = boardings_per_time.values().sum();
+        let alightings = self
+            .analytics
+            .bus_alightings
+            .get(&stop)
+            .map(|times| times.iter().filter(|t| recent(t)).count())
+            .unwrap_or(0);
+
+        BusStopDemand {
+            boardings,
+            alightings,
+            // Ties go to the earliest time
+            peak_boarding_time: boardings_per_time
+                .into_iter()
+                .max_by_key(|(t, cnt)| (*cnt, Reverse(*t)))
+                .map(|(t, _)| t),
+            mean_wait: if boardings == 0 {
+                Duration::ZERO
+            } else {
+                total_wait / (boardings as f64)
+            },
+        }
+    }
+
+    // Which buses were too full to let somebody board within the last window of time? (bus,
+    // route, most recent time)
+    pub fn get_bus_overcrowding_events(&self, window: Duration) -> Vec<(CarID, BusRouteID, Time)> {
+        self.analytics
+            .bus_overcrowding(self.time, window)
+            .into_iter()
+            .map(|(bus, t)| (bus, self.transit.bus_route(bus), t))
+            .collect()
+    }
+}
+
+pub struct BusStopDemand {
+    pub boardings: usize,
+    pub alightings: usize,
+    // When the most people boarded at once
+    pub peak_boarding_time: Option<Time>,
+    pub mean_wait: Duration,
+}
+
+pub struct StopSpacingStats {
+    pub min_spacing: Distance,
+    pub max_spacing: Distance,
+    pub mean_spacing: Distance,
+    pub recommended_removals: Vec<BusStopID>,
+}
@@ -0,0 +1,442 @@
+use super::{path_distance, Sim};
+use crate::{
+    AgentID, CarID, CarSharingStats, PedestrianID, TripEndpoint, TripID, TripMode, TripPhaseType,
+};
+use geom::{Distance, Duration, Time};
+use map_model::{Map, PathRequest};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Trip times, delays, and vehicle utilization
+impl Sim {
+    // Every trip a vehicle has been used for so far, including ride-hailing passengers
+    pub fn get_trip_by_vehicle(&self, car: CarID) -> Vec<TripID> {
+        self.trips.vehicle_trip_history(car)
+    }
+
+    // (departure, end, trip) for every trip a vehicle has served. Unfinished trips end now.
+    pub fn vehicle_utilization_timeline(&self, car: CarID) -> Vec<(Time, Time, TripID)> {
+        self.trips
+            .vehicle_trip_history(car)
+            .into_iter()
+            .map(|t| {
+                let (departure, _, _, _) = self.trips.trip_info(t);
+                let end = self
+                    .trips
+                    .finished_trip_time(t)
+                    .map(|(total, _)| departure + total)
+                    .unwrap_or(self.time);
+                (departure, end, t)
+            })
+            .collect()
+    }
+
+    // How many finished trips used each mode, grouped by when they departed. Every bucket from the
+    // start of the day until now is included, even if it's empty.
+    pub fn get_modal_split_over_time(
+        &self,
+        bucket_size: Duration,
+    ) -> Vec<(Time, BTreeMap<TripMode, usize>)> {
+        assert!(bucket_size > Duration::ZERO);
+        let bucket_idx = |t: Time| ((t - Time::START_OF_DAY) / bucket_size).floor() as usize;
+        let mut results: Vec<(Time, BTreeMap<TripMode, usize>)> = (0..=bucket_idx(self.time))
+            .map(|i| {
+                (
+                    Time::START_OF_DAY + bucket_size * (i as f64),
+                    BTreeMap::new(),
+                )
+            })
+            .collect();
+        for (_, trip, mode, _) in &self.analytics.finished_trips {
+            // Aborted trips have no mode
+            if let Some(mode) = mode {
+                let (departure, _, _, _) = self.trips.trip_info(*trip);
+                *results[bucket_idx(departure)].1.entry(*mode).or_insert(0) += 1;
+            }
+        }
+        results
+    }
+
+    // Shared cars are the ones without an owner.
+    pub fn get_car_sharing_utilization(&self, car: CarID) -> Option<CarSharingStats> {
+        if self.get_owner_of_car(car).is_some() {
+            return None;
+        }
+        self.analytics
+            .car_usage
+            .get(&car)
+            .map(|stats| stats.as_of(self.time))
+    }
+
+    pub fn fleet_utilization_report(&self) -> BTreeMap<CarID, CarSharingStats> {
+        self.analytics
+            .car_usage
+            .keys()
+            .filter_map(|car| {
+                self.get_car_sharing_utilization(*car)
+                    .map(|stats| (*car, stats))
+            })
+            .collect()
+    }
+
+    // How long a trip's car spent looking for parking, from reaching the last lane of its path to
+    // starting to park. None if it hasn't parked yet.
+    pub fn get_parking_search_time(&self, trip: TripID) -> Option<Duration> {
+        self.trips.parking_search_time(trip)
+    }
+
+    // Splits a finished trip's total time into what the person was doing. The components add up
+    // to the trip's duration. Walking before the first vehicle, or between vehicles, is access
+    // time. Time spent blocked in vehicles is split out from time spent waiting for the bus.
+    // TODO Time pedestrians spend blocked is also taken out of the in-vehicle time.
+    pub fn get_trip_time_components(&self, trip: TripID) -> Option<TripTimeComponents> {
+        let (total, blocked) = self.finished_trip_time(trip)?;
+        let phases: Vec<(Time, TripPhaseType)> = self
+            .analytics
+            .trip_log
+            .iter()
+            .filter(|(_, id, _, _)| *id == trip)
+            .map(|(t, _, _, phase_type)| (*t, *phase_type))
+            .collect();
+        let last_vehicle_phase = phases.iter().rposition(|(_, phase_type)| match phase_type {
+            TripPhaseType::Driving
+            | TripPhaseType::Biking
+            | TripPhaseType::Parking
+            | TripPhaseType::RidingBus(_, _, _) => true,
+            _ => false,
+        });
+
+        let mut components = TripTimeComponents {
+            access: Duration::ZERO,
+            in_vehicle: Duration::ZERO,
+            egress: Duration::ZERO,
+            blocked: Duration::ZERO,
+            waiting: Duration::ZERO,
+            parking_search: Duration::ZERO,
+            transfer_penalty: self.transfer_penalty * (self.num_transfers(trip) as f64),
+        };
+        for (idx, pair) in phases.windows(2).enumerate() {
+            let dt = pair[1].0 - pair[0].0;
+            match pair[0].1 {
+                TripPhaseType::Driving
+                | TripPhaseType::Biking
+                | TripPhaseType::Parking
+                | TripPhaseType::RidingBus(_, _, _)
+                | TripPhaseType::Remote => {
+                    components.in_vehicle += dt;
+                }
+                TripPhaseType::WaitingForBus(_, _) | TripPhaseType::DelayedStart => {
+                    components.waiting += dt;
+                }
+                TripPhaseType::Walking => {
+                    if last_vehicle_phase.map(|v| idx > v).unwrap_or(false) {
+                        components.egress += dt;
+                    }
+                }
+                TripPhaseType::Aborted | TripPhaseType::Finished => {}
+            }
+        }
+
+        components.parking_search = self
+            .get_parking_search_time(trip)
+            .unwrap_or(Duration::ZERO)
+            .min(components.in_vehicle);
+        components.in_vehicle -= components.parking_search;
+        components.blocked = blocked.min(components.in_vehicle);
+        components.in_vehicle -= components.blocked;
+        components.access = (total
+            - components.in_vehicle
+            - components.egress
+            - components.blocked
+            - components.waiting
+            - components.parking_search)
+            .max(Duration::ZERO);
+        Some(components)
+    }
+
+    // The perceived cost of this pedestrian's trip changing between vehicles, like getting off a
+    // bus and onto another. Parking a car and walking to a bus counts as one transfer. None if the
+    // pedestrian isn't on a trip.
+    pub fn get_transfer_penalty(&self, ped: PedestrianID) -> Option<Duration> {
+        let trip = self.agent_to_trip(AgentID::Pedestrian(ped))?;
+        Some(self.transfer_penalty * (self.num_transfers(trip) as f64))
+    }
+
+    // Counts each time the trip boards a different vehicle, after the first one
+    fn num_transfers(&self, trip: TripID) -> usize {
+        let mut vehicles = 0;
+        let mut in_vehicle = false;
+        for (_, id, _, phase_type) in &self.analytics.trip_log {
+            if *id != trip {
+                continue;
+            }
+            match phase_type {
+                TripPhaseType::Driving
+                | TripPhaseType::Biking
+                | TripPhaseType::Parking
+                | TripPhaseType::RidingBus(_, _, _) => {
+                    if !in_vehicle {
+                        vehicles += 1;
+                    }
+                    in_vehicle = true;
+                }
+                _ => {
+                    in_vehicle = false;
+                }
+            }
+        }
+        vehicles.max(1) - 1
+    }
+
+    // How long a finished trip spent walking from where it parked to the destination. None if the
+    // trip hasn't finished or didn't end by parking a car.
+    pub fn get_parking_egress_time(&self, trip: TripID) -> Option<Duration> {
+        let phases: Vec<(Time, TripPhaseType)> = self
+            .analytics
+            .trip_log
+            .iter()
+            .filter(|(_, id, _, _)| *id == trip)
+            .map(|(t, _, _, phase_type)| (*t, *phase_type))
+            .collect();
+        parking_egress_time(&phases)
+    }
+
+    pub fn mean_parking_egress_time(&self) -> Option<Duration> {
+        let mut phases_per_trip: BTreeMap<TripID, Vec<(Time, TripPhaseType)>> = BTreeMap::new();
+        for (t, id, _, phase_type) in &self.analytics.trip_log {
+            phases_per_trip
+                .entry(*id)
+                .or_insert_with(Vec::new)
+                .push((*t, *phase_type));
+        }
+        let times: Vec<Duration> = phases_per_trip
+            .values()
+            .filter_map(|phases| parking_egress_time(phases))
+            .collect();
+        if times.is_empty() {
+            return None;
+        }
+        Some(times.iter().fold(Duration::ZERO, |sum, dt| sum + *dt) / (times.len() as f64))
+    }
+
+    pub fn mean_parking_search_time(&self) -> Option<Duration> {
+        let times = self.trips.all_parking_search_times();
+        if times.is_empty() {
+            return None;
+        }
+        let total: Duration = times.iter().cloned().sum();
+        Some(total / (times.len() as f64))
+    }
+
+    // How much longer is the distance actually traveled so far than the straight line from start
+    // to end?
+    pub fn get_detour_factor(&self, trip: TripID, map: &Map) -> Option<f64> {
+        let (_, start, end, _) = self.trips.trip_info(trip);
+        let pt = |endpt: TripEndpoint| match endpt {
+            TripEndpoint::Bldg(b) => map.get_b(b).polygon.center(),
+            TripEndpoint::Border(i, _) => map.get_i(i).polygon.center(),
+        };
+        let euclidean = pt(start).dist_to(pt(end));
+        if euclidean == Distance::ZERO {
+            return None;
+        }
+
+        let actual = *self.analytics.trip_distances.get(&trip)?;
+        if actual == Distance::ZERO {
+            return None;
+        }
+        Some(actual / euclidean)
+    }
+
+    // Averaged over every finished trip of this mode
+    pub fn mean_detour_factor(&self, mode: TripMode, map: &Map) -> Option<f64> {
+        let factors: Vec<f64> = self
+            .analytics
+            .finished_trips
+            .iter()
+            .filter(|(_, _, m, _)| *m == Some(mode))
+            .filter_map(|(_, trip, _, _)| self.get_detour_factor(*trip, map))
+            .collect();
+        if factors.is_empty() {
+            return None;
+        }
+        Some(factors.iter().sum::<f64>() / (factors.len() as f64))
+    }
+
+    // For the pedestrian's current or most recent walking phase, how much longer is the route than
+    // a straight line between its start and end?
+    pub fn get_pedestrian_route_directness(&self, ped: PedestrianID, map: &Map) -> Option<f64> {
+        let person = self.trips.get_all_people().iter().find(|p| p.ped == ped)?;
+        let req =
+            self.analytics
+                .trip_log
+                .iter()
+                .rev()
+                .find_map(|(_, trip, maybe_req, phase)| {
+                    if *phase == TripPhaseType::Walking && person.trips.contains(trip) {
+                        maybe_req.clone()
+                    } else {
+                        None
+                    }
+                })?;
+        let euclidean = req.start.pt(map).dist_to(req.end.pt(map));
+        if euclidean == Distance::ZERO {
+            return None;
+        }
+        let actual = path_distance(&req, &map.pathfind(req.clone())?, map);
+        Some(actual / euclidean)
+    }
+
+    // The fraction of finished trips of this mode that took at most budget, from 0 to 1. If no
+    // trips have finished, this is 1.
+    pub fn get_travel_time_budget_compliance(&self, budget: Duration, mode: TripMode) -> f64 {
+        let mut total = 0;
+        let mut within = 0;
+        for (_, _, trip_mode, dt) in &self.analytics.finished_trips {
+            if *trip_mode == Some(mode) {
+                total += 1;
+                if *dt <= budget {
+                    within += 1;
+                }
+            }
+        }
+        if total == 0 {
+            return 1.0;
+        }
+        (within as f64) / (total as f64)
+    }
+
+    pub fn trips_exceeding_budget(&self, budget: Duration, mode: TripMode) -> Vec<TripID> {
+        self.analytics
+            .finished_trips
+            .iter()
+            .filter(|(_, _, trip_mode, dt)| *trip_mode == Some(mode) && *dt > budget)
+            .map(|(_, trip, _, _)| *trip)
+            .collect()
+    }
+
+    // Hours that finished trips of this mode took beyond their free-flow time, summed over every
+    // trip. Since every trip is one vehicle (or person walking), this is vehicle-hours of delay.
+    // TODO Riding transit isn't counted in the free-flow time; use person_hours_of_delay instead.
+    pub fn get_vehicle_hours_of_delay(&self, mode: TripMode) -> f64 {
+        let mut delay = Duration::ZERO;
+        for (_, trip, trip_mode, dt) in &self.analytics.finished_trips {
+            if *trip_mode == Some(mode) && mode != TripMode::Transit {
+                delay += (*dt - self.trips.free_flow_time(*trip)).max(Duration::ZERO);
+            }
+        }
+        delay / Duration::hours(1)
+    }
+
+    // Like get_vehicle_hours_of_delay, but transit riders each count separately, so a delayed bus
+    // counts once per passenger. For transit, waiting for the bus is all delay, and riding is
+    // compared to driving the bus's path at the speed limit.
+    pub fn person_hours_of_delay(&self, mode: TripMode, map: &Map) -> f64 {
+        if mode != TripMode::Transit {
+            return self.get_vehicle_hours_of_delay(mode);
+        }
+        let transit_trips: BTreeSet<TripID> = self
+            .analytics
+            .finished_trips
+            .iter()
+            .filter(|(_, _, m, _)| *m == Some(TripMode::Transit))
+            .map(|(_, trip, _, _)| *trip)
+            .collect();
+
+        let mut delay = Duration::ZERO;
+        let mut current: BTreeMap<TripID, (Time, Option<PathRequest>, TripPhaseType)> =
+            BTreeMap::new();
+        for (t, id, maybe_req, phase_type) in &self.analytics.trip_log {
+            if !transit_trips.contains(id) {
+                continue;
+            }
+            match current.insert(*id, (*t, maybe_req.clone(), *phase_type)) {
+                Some((start, _, TripPhaseType::WaitingForBus(_, _))) => {
+                    delay += *t - start;
+                }
+                Some((start, Some(req), TripPhaseType::RidingBus(_, _, _))) => {
+                    if let Some(path) = map.pathfind(req) {
+                        let free_flow: Duration = path
+                            .get_steps()
+                            .iter()
+                            .map(|step| {
+                                let on = step.as_traversable();
+                                on.length(map) / on.speed_limit(map)
+                            })
+                            .sum();
+                        delay += (*t - start - free_flow).max(Duration::ZERO);
+                    }
+                }
+                _ => {}
+            }
+        }
+        delay / Duration::hours(1)
+    }
+}
+
+pub struct TripTimeComponents {
+    // Walking to the vehicle, or the entire trip when walking
+    pub access: Duration,
+    pub in_vehicle: Duration,
+    // Walking from the vehicle to the destination
+    pub egress: Duration,
+    // Stuck in a vehicle at intersections or in queues
+    pub blocked: Duration,
+    // Waiting for the bus, or for the trip to start after it was delayed
+    pub waiting: Duration,
+    pub parking_search: Duration,
+    // Not actual time, so it isn't part of the trip's duration. See get_transfer_penalty.
+    pub transfer_penalty: Duration,
+}
+
+// The phases of a trip, in order. Only finished trips that walk after parking count.
+fn parking_egress_time(phases: &[(Time, TripPhaseType)]) -> Option<Duration> {
+    if phases.len() < 3 {
+        return None;
+    }
+    let n = phases.len();
+    match (phases[n - 3].1, phases[n - 2].1, phases[n - 1].1) {
+        (TripPhaseType::Driving, TripPhaseType::Walking, TripPhaseType::Finished)
+        | (TripPhaseType::Parking, TripPhaseType::Walking, TripPhaseType::Finished) => {
+            Some(phases[n - 1].0 - phases[n - 2].0)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parking_egress_time() {
+        let t = |secs: f64| Time::START_OF_DAY + Duration::seconds(secs);
+        assert_eq!(
+            parking_egress_time(&vec![
+                (t(0.0), TripPhaseType::Walking),
+                (t(60.0), TripPhaseType::Driving),
+                (t(600.0), TripPhaseType::Parking),
+                (t(700.0), TripPhaseType::Walking),
+                (t(790.0), TripPhaseType::Finished),
+            ]),
+            Some(Duration::seconds(90.0))
+        );
+        // Nobody parked at the end of a bike trip
+        assert_eq!(
+            parking_egress_time(&vec![
+                (t(0.0), TripPhaseType::Walking),
+                (t(60.0), TripPhaseType::Biking),
+                (t(600.0), TripPhaseType::Walking),
+                (t(650.0), TripPhaseType::Finished),
+            ]),
+            None
+        );
+        // Still walking
+        assert_eq!(
+            parking_egress_time(&vec![
+                (t(0.0), TripPhaseType::Driving),
+                (t(600.0), TripPhaseType::Walking),
+            ]),
+            None
+        );
+    }
+}
@@ -1,18 +1,26 @@
 use crate::{
-    CarID, Event, PedestrianID, PersonID, Router, Scheduler, TripID, TripManager, TripPhaseType,
-    WalkingSimState,
+    AlertLocation, CarID, Command, Event, ParkingSimState, PedestrianID, PersonID, Router,
+    Scheduler, SidewalkSpot, TripID, TripManager, TripPhaseType, Vehicle, WalkingSimState,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::{Distance, Time};
+use geom::{Distance, Duration, Time};
 use map_model::{
-    BusRoute, BusRouteID, BusStopID, Map, Path, PathConstraints, PathRequest, Position,
+    BuildingID, BusRoute, BusRouteID, BusStopID, LaneType, Map, Path, PathConstraints, PathRequest,
+    Position,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 
 // These index stops along a route, not stops along a single sidewalk.
 type StopIdx = usize;
 
+// Two buses on the same route arriving at a stop closer together than this are bunched.
+const BUS_BUNCHING_THRESHOLD: Duration = Duration::const_seconds(120.0);
+// A bus still at a stop this long after arriving is stalled.
+const BUS_STALL_THRESHOLD: Duration = Duration::const_seconds(60.0);
+// Standing room included, how many passengers fit per meter of bus?
+const BUS_PASSENGERS_PER_METER: f64 = 5.0;
+
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 struct StopForRoute {
     id: BusStopID,
@@ -20,6 +28,7 @@ struct StopForRoute {
     req: PathRequest,
     path_to_next_stop: Path,
     next_stop_idx: StopIdx,
+    last_arrival: Option<Time>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
@@ -40,7 +49,8 @@ struct Bus {
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 enum BusState {
     DrivingToStop(StopIdx),
-    AtStop(StopIdx),
+    // When did the bus arrive?
+    AtStop(StopIdx, Time),
 }
 
 // This kind of acts like TripManager, managing transitions... but a bit more statefully.
@@ -62,16 +72,83 @@ pub struct TransitSimState {
         deserialize_with = "deserialize_btreemap"
     )]
     peds_waiting: BTreeMap<BusStopID, Vec<(PedestrianID, BusRouteID, BusStopID, Time)>>,
+    // How many passengers each bus can hold
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    bus_capacity: BTreeMap<CarID, usize>,
+    ride_hail: Option<RideHailFleet>,
+    // Demand-responsive transit works just like ride-hailing, but with minibuses from a separate
+    // fleet
+    drt: Option<RideHailFleet>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    school_buses: BTreeMap<CarID, SchoolBusRun>,
 
     events: Vec<Event>,
 }
 
+// Ride-hailing drivers or DRT minibuses. Riders are picked up and dropped off at the curb near
+// buildings. Drivers without a job wait off the road near their last dropoff, and reappear there
+// when dispatched.
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+struct RideHailFleet {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    vehicles: BTreeMap<CarID, Vehicle>,
+    // Where each idle driver is waiting
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    idle: BTreeMap<CarID, Position>,
+    // Served in order of when they were requested
+    unserved: VecDeque<TripID>,
+    // What each driver on the road is doing. Drivers whose rider cancelled aren't here, and go idle
+    // once they reach the curb.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    busy: BTreeMap<CarID, RideHailJob>,
+}
+
+// A school bus starts at the school, stops at the curb near each student's building in order, and
+// then drops everybody off at the curb near the school.
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+struct SchoolBusRun {
+    // For each stop left, the students waiting there and the route to the next stop. Reaching the
+    // curb after the last stop means the bus is back at the school.
+    stops: VecDeque<(Vec<TripID>, Router)>,
+    school: BuildingID,
+    riders: Vec<TripID>,
+    // Set while the bus waits at a stop
+    departing: Option<Router>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+enum RideHailJob {
+    Pickup(TripID),
+    // Waiting for the rider to get in, then following the route to their destination
+    Boarding(TripID, Router),
+    Dropoff(TripID),
+}
+
 impl TransitSimState {
     pub fn new() -> TransitSimState {
         TransitSimState {
             buses: BTreeMap::new(),
             routes: BTreeMap::new(),
             peds_waiting: BTreeMap::new(),
+            bus_capacity: BTreeMap::new(),
+            ride_hail: None,
+            drt: None,
+            school_buses: BTreeMap::new(),
             events: Vec::new(),
         }
     }
@@ -113,6 +190,7 @@ impl TransitSimState {
                         req,
                         path_to_next_stop: path,
                         next_stop_idx: stop2_idx,
+                        last_arrival: None,
                     }
                 })
                 .collect(),
@@ -134,8 +212,18 @@ impl TransitSimState {
         stops
     }
 
-    pub fn bus_created(&mut self, bus: CarID, route: BusRouteID, next_stop_idx: StopIdx) {
+    pub fn bus_created(
+        &mut self,
+        bus: CarID,
+        length: Distance,
+        route: BusRouteID,
+        next_stop_idx: StopIdx,
+    ) {
         self.routes.get_mut(&route).unwrap().buses.push(bus);
+        self.bus_capacity.insert(
+            bus,
+            (length.inner_meters() * BUS_PASSENGERS_PER_METER) as usize,
+        );
         self.buses.insert(
             bus,
             Bus {
@@ -156,18 +244,29 @@ impl TransitSimState {
         scheduler: &mut Scheduler,
         map: &Map,
     ) {
+        let capacity = self.bus_capacity[&id];
         let mut bus = self.buses.get_mut(&id).unwrap();
         match bus.state {
             BusState::DrivingToStop(stop_idx) => {
-                bus.state = BusState::AtStop(stop_idx);
-                let stop1 = self.routes[&bus.route].stops[stop_idx].id;
+                bus.state = BusState::AtStop(stop_idx, now);
+                scheduler.push(now + BUS_STALL_THRESHOLD, Command::CheckBusStalled(id));
+                let stop = &mut self.routes.get_mut(&bus.route).unwrap().stops[stop_idx];
+                let stop1 = stop.id;
                 self.events
                     .push(Event::BusArrivedAtStop(id, bus.route, stop1));
+                if let Some(prev) = stop.last_arrival.replace(now) {
+                    if now - prev <= BUS_BUNCHING_THRESHOLD {
+                        self.events
+                            .push(Event::BusBunchingDetected(bus.route, stop1));
+                    }
+                }
 
                 // Deboard existing passengers.
                 let mut still_riding = Vec::new();
                 for (person, stop2) in bus.passengers.drain(..) {
                     if stop1 == stop2 {
+                        self.events
+                            .push(Event::PassengerAlighted(person, bus.car, stop1));
                         trips.person_left_bus(now, person, bus.car, map, scheduler);
                     } else {
                         still_riding.push((person, stop2));
@@ -180,7 +279,10 @@ impl TransitSimState {
                 for (ped, route, stop2, started_waiting) in
                     self.peds_waiting.remove(&stop1).unwrap_or_else(Vec::new)
                 {
-                    if bus.route == route {
+                    if bus.route == route && bus.passengers.len() >= capacity {
+                        self.events.push(Event::BusPassedFullCapacity(bus.car, ped));
+                        still_waiting.push((ped, route, stop2, started_waiting));
+                    } else if bus.route == route {
                         let (trip, person) = trips.ped_boarded_bus(
                             now,
                             ped,
@@ -205,15 +307,26 @@ impl TransitSimState {
                 }
                 self.peds_waiting.insert(stop1, still_waiting);
             }
-            BusState::AtStop(_) => unreachable!(),
+            BusState::AtStop(_, _) => unreachable!(),
         };
     }
 
-    pub fn bus_departed_from_stop(&mut self, id: CarID) -> Router {
+    // Also handles ride-hailing drivers leaving the curb.
+    pub fn bus_departed_from_stop(&mut self, id: CarID, scheduler: &mut Scheduler) -> Router {
+        for fleet in self.ride_hail.iter_mut().chain(self.drt.iter_mut()) {
+            if let Some(RideHailJob::Boarding(trip, router)) = fleet.busy.remove(&id) {
+                fleet.busy.insert(id, RideHailJob::Dropoff(trip));
+                return router;
+            }
+        }
+        if let Some(run) = self.school_buses.get_mut(&id) {
+            return run.departing.take().unwrap();
+        }
+        scheduler.cancel(Command::CheckBusStalled(id));
         let mut bus = self.buses.get_mut(&id).unwrap();
         match bus.state {
             BusState::DrivingToStop(_) => unreachable!(),
-            BusState::AtStop(stop_idx) => {
+            BusState::AtStop(stop_idx, _) => {
                 let route = &self.routes[&bus.route];
                 let stop = &route.stops[stop_idx];
 
@@ -243,8 +356,12 @@ impl TransitSimState {
         assert!(stop1 != stop2);
         if let Some(route) = self.routes.get(&route_id) {
             for bus in &route.buses {
-                if let BusState::AtStop(idx) = self.buses[bus].state {
+                if let BusState::AtStop(idx, _) = self.buses[bus].state {
                     if route.stops[idx].id == stop1 {
+                        if self.buses[bus].passengers.len() >= self.bus_capacity[bus] {
+                            self.events.push(Event::BusPassedFullCapacity(*bus, ped));
+                            continue;
+                        }
                         self.buses
                             .get_mut(bus)
                             .unwrap()
@@ -278,6 +395,41 @@ impl TransitSimState {
         None
     }
 
+    pub fn get_peds_waiting_at_stop(&self, stop: BusStopID) -> Vec<PedestrianID> {
+        self.peds_waiting
+            .get(&stop)
+            .map(|peds| peds.iter().map(|(ped, _, _, _)| *ped).collect())
+            .unwrap_or_else(Vec::new)
+    }
+
+    // Called once a bus has been at its stop for BUS_STALL_THRESHOLD.
+    pub fn check_bus_stalled(&mut self, now: Time, id: CarID) {
+        if let Some(bus) = self.buses.get(&id) {
+            if let BusState::AtStop(idx, arrived) = bus.state {
+                let stop = self.routes[&bus.route].stops[idx].id;
+                self.events.push(Event::BusStalled(id, stop, now - arrived));
+            }
+        }
+    }
+
+    // Buses that've been at their current stop for longer than the threshold, with how long
+    // they've been waiting
+    pub fn stalled_buses(
+        &self,
+        now: Time,
+        threshold: Duration,
+    ) -> Vec<(CarID, BusRouteID, Duration)> {
+        let mut results = Vec::new();
+        for bus in self.buses.values() {
+            if let BusState::AtStop(_, arrived) = bus.state {
+                if now - arrived > threshold {
+                    results.push((bus.car, bus.route, now - arrived));
+                }
+            }
+        }
+        results
+    }
+
     pub fn collect_events(&mut self) -> Vec<Event> {
         self.events.drain(..).collect()
     }
@@ -286,6 +438,10 @@ impl TransitSimState {
         &self.buses[&bus].passengers
     }
 
+    pub fn bus_capacity(&self, bus: CarID) -> usize {
+        self.bus_capacity[&bus]
+    }
+
     pub fn bus_route(&self, bus: CarID) -> BusRouteID {
         self.buses[&bus].route
     }
@@ -304,7 +460,7 @@ impl TransitSimState {
                                 idx - 1
                             }
                         }
-                        BusState::AtStop(idx) => idx,
+                        BusState::AtStop(idx, _) => idx,
                     };
                     (*bus, stop)
                 })
@@ -314,3 +470,274 @@ impl TransitSimState {
         }
     }
 }
+
+impl RideHailFleet {
+    fn new(drivers: Vec<(Vehicle, Position)>) -> RideHailFleet {
+        RideHailFleet {
+            vehicles: drivers.iter().map(|(v, _)| (v.id, v.clone())).collect(),
+            idle: drivers.into_iter().map(|(v, pos)| (v.id, pos)).collect(),
+            unserved: VecDeque::new(),
+            busy: BTreeMap::new(),
+        }
+    }
+}
+
+// Ride-hailing and demand-responsive transit. Most methods work for either fleet.
+impl TransitSimState {
+    pub fn enable_ride_hail(&mut self, drivers: Vec<(Vehicle, Position)>) {
+        assert!(self.ride_hail.is_none());
+        self.ride_hail = Some(RideHailFleet::new(drivers));
+    }
+
+    pub fn enable_drt(&mut self, minibuses: Vec<(Vehicle, Position)>) {
+        assert!(self.drt.is_none());
+        self.drt = Some(RideHailFleet::new(minibuses));
+    }
+
+    pub fn ride_hail_enabled(&self) -> bool {
+        self.ride_hail.is_some()
+    }
+
+    pub fn drt_enabled(&self) -> bool {
+        self.drt.is_some()
+    }
+
+    pub fn request_ride_hail(&mut self, trip: TripID, drt: bool) {
+        let fleet = if drt {
+            self.drt
+                .as_mut()
+                .expect("Demand-responsive transit isn't enabled")
+        } else {
+            self.ride_hail.as_mut().expect("Ride-hailing isn't enabled")
+        };
+        fleet.unserved.push_back(trip);
+    }
+
+    // The oldest request of either fleet with an idle vehicle, the closest such vehicle, and where
+    // it's waiting
+    pub fn next_ride_hail_match(
+        &self,
+        trips: &TripManager,
+        map: &Map,
+    ) -> Option<(TripID, Vehicle, Position)> {
+        for fleet in self.ride_hail.iter().chain(self.drt.iter()) {
+            let trip = if let Some(t) = fleet.unserved.front() {
+                *t
+            } else {
+                continue;
+            };
+            let pickup = map
+                .get_b(trips.ride_hail_endpoints(trip).0)
+                .polygon
+                .center();
+            if let Some((car, pos)) = fleet
+                .idle
+                .iter()
+                .min_by_key(|(_, pos)| pos.pt(map).dist_to(pickup))
+            {
+                return Some((trip, fleet.vehicles[car].clone(), *pos));
+            }
+        }
+        None
+    }
+
+    // The oldest request of a fleet was handled. If there's a driver, they're headed to the rider
+    // now.
+    pub fn ride_hail_dispatched(&mut self, trip: TripID, driver: Option<CarID>) {
+        let fleet = self
+            .ride_hail
+            .iter_mut()
+            .chain(self.drt.iter_mut())
+            .find(|f| f.unserved.front() == Some(&trip))
+            .unwrap();
+        fleet.unserved.pop_front();
+        if let Some(car) = driver {
+            fleet.idle.remove(&car).unwrap();
+            fleet.busy.insert(car, RideHailJob::Pickup(trip));
+        }
+    }
+
+    // Riders already in a car are still driven to their destination.
+    pub fn ride_hail_trip_aborted(&mut self, trip: TripID) {
+        for fleet in self.ride_hail.iter_mut().chain(self.drt.iter_mut()) {
+            fleet.unserved.retain(|t| *t != trip);
+            fleet
+                .busy
+                .retain(|_, job| *job != RideHailJob::Pickup(trip));
+        }
+    }
+
+    // A ride-hailing driver or DRT minibus reached the curb they were headed for. Returns true if
+    // they should wait there for somebody to get in, or false if they should leave the road.
+    pub fn ride_hail_reached_curb(
+        &mut self,
+        now: Time,
+        car: CarID,
+        pos: Position,
+        trips: &mut TripManager,
+        parking: &mut ParkingSimState,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) -> bool {
+        let fleet = self
+            .ride_hail
+            .iter_mut()
+            .chain(self.drt.iter_mut())
+            .find(|f| f.vehicles.contains_key(&car))
+            .unwrap();
+        match fleet.busy.remove(&car) {
+            Some(RideHailJob::Pickup(trip)) => {
+                let end = curb_pos(
+                    trips.ride_hail_endpoints(trip).1,
+                    fleet.vehicles[&car].length,
+                    map,
+                );
+                let req = PathRequest {
+                    start: pos,
+                    end,
+                    constraints: PathConstraints::Car,
+                };
+                if let Some(path) = map.pathfind(req.clone()) {
+                    trips.ride_hail_picked_up(trip, car);
+                    fleet.busy.insert(
+                        car,
+                        RideHailJob::Boarding(trip, Router::stop_at_curb(path, end.dist_along())),
+                    );
+                    return true;
+                }
+                self.events.push(Event::Alert(
+                    AlertLocation::Nil,
+                    format!(
+                        "{} can't drive the rider of {} anywhere: {}",
+                        car, trip, req
+                    ),
+                ));
+                trips.abort_trip(now, trip, None, parking, scheduler, map);
+            }
+            Some(RideHailJob::Dropoff(trip)) => {
+                trips.ride_hail_finished(now, trip, parking, scheduler, map);
+            }
+            Some(RideHailJob::Boarding(_, _)) => unreachable!(),
+            None => {}
+        }
+        fleet.idle.insert(car, pos);
+        // The car has to leave the road before it can be dispatched again
+        scheduler.update(now, Command::DispatchRideHail);
+        false
+    }
+}
+
+// School buses
+impl TransitSimState {
+    // The bus hasn't started driving yet. stops lists the students waiting at each stop, and how
+    // to drive from there to the next stop or back to the school.
+    pub fn school_bus_created(
+        &mut self,
+        car: CarID,
+        school: BuildingID,
+        stops: Vec<(Vec<TripID>, Router)>,
+    ) {
+        self.school_buses.insert(
+            car,
+            SchoolBusRun {
+                stops: stops.into_iter().collect(),
+                school,
+                riders: Vec::new(),
+                departing: None,
+            },
+        );
+    }
+
+    // A school bus reached the curb it was headed for. Returns true if it should wait there for
+    // students to get in, or false if it's back at the school and should leave the road.
+    pub fn school_bus_reached_curb(
+        &mut self,
+        now: Time,
+        car: CarID,
+        pos: Position,
+        trips: &mut TripManager,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) -> bool {
+        let run = self.school_buses.get_mut(&car).unwrap();
+        if let Some((waiting, router)) = run.stops.pop_front() {
+            for trip in waiting {
+                if trips.school_bus_picked_up(trip, car) {
+                    trips.record_vehicle_trip(car, trip);
+                    run.riders.push(trip);
+                } else {
+                    self.events.push(Event::Alert(
+                        AlertLocation::Person(trips.trip_to_person(trip)),
+                        format!("{} missed school bus {}", trip, car),
+                    ));
+                    trips.school_bus_missed(trip);
+                }
+            }
+            run.departing = Some(router);
+            return true;
+        }
+
+        let run = self.school_buses.remove(&car).unwrap();
+        let start = sidewalk_at_curb(pos, run.school, map);
+        for trip in run.riders {
+            trips.school_bus_dropped_off(now, trip, start.clone(), map, scheduler);
+        }
+        false
+    }
+}
+
+// Where a vehicle stops at the curb to pick up or drop off somebody at a building
+pub(crate) fn curb_pos(b: BuildingID, vehicle_len: Distance, map: &Map) -> Position {
+    let lane = map.find_driving_lane_near_building(b);
+    let sidewalk_pos = map.get_b(b).front_path.sidewalk;
+    let len = map.get_l(lane).length();
+    let dist = if map.get_l(lane).parent == map.get_l(sidewalk_pos.lane()).parent {
+        sidewalk_pos.equiv_pos(lane, vehicle_len, map).dist_along()
+    } else {
+        // TODO The building isn't on this road, so anywhere is as good as anywhere else
+        len / 2.0
+    };
+    Position::new(lane, dist.max(vehicle_len).min(len))
+}
+
+// Where somebody getting out of a vehicle stopped at the curb near a building starts walking
+fn sidewalk_at_curb(curb: Position, b: BuildingID, map: &Map) -> SidewalkSpot {
+    let pos = match map.find_closest_lane(curb.lane(), vec![LaneType::Sidewalk]) {
+        Ok(sidewalk) => curb.equiv_pos(sidewalk, Distance::ZERO, map),
+        // TODO No sidewalk along this road, so just start in front of the building
+        Err(_) => map.get_b(b).front_path.sidewalk,
+    };
+    SidewalkSpot::suddenly_appear(pos.lane(), pos.dist_along(), map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{VehicleSpec, VehicleType, MIN_CAR_LENGTH};
+    use map_model::LaneID;
+
+    #[test]
+    fn test_ride_hail_abort_frees_driver() {
+        let mut transit = TransitSimState::new();
+        let car = CarID(0, VehicleType::Car);
+        let vehicle = VehicleSpec {
+            vehicle_type: VehicleType::Car,
+            length: MIN_CAR_LENGTH,
+            max_speed: None,
+        }
+        .make(car, None);
+        transit.enable_ride_hail(vec![(vehicle, Position::new(LaneID(0), MIN_CAR_LENGTH))]);
+
+        // Cancelling before a driver is assigned
+        transit.request_ride_hail(TripID(0), false);
+        transit.ride_hail_trip_aborted(TripID(0));
+        assert!(transit.ride_hail.as_ref().unwrap().unserved.is_empty());
+
+        // Cancelling while the driver is on the way
+        transit.request_ride_hail(TripID(1), false);
+        transit.ride_hail_dispatched(TripID(1), Some(car));
+        assert!(transit.ride_hail.as_ref().unwrap().idle.is_empty());
+        transit.ride_hail_trip_aborted(TripID(1));
+        assert!(transit.ride_hail.as_ref().unwrap().busy.is_empty());
+    }
+}
@@ -23,8 +23,15 @@ pub struct TripManager {
         deserialize_with = "deserialize_btreemap"
     )]
     active_trip_mode: BTreeMap<AgentID, TripID>,
+    // Every trip each vehicle has been used for, in order
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    vehicle_trip_history: BTreeMap<CarID, Vec<TripID>>,
     unfinished_trips: usize,
     pub pathfinding_upfront: bool,
+    pedestrian_route_choices: usize,
 
     car_id_counter: usize,
 
@@ -32,15 +39,17 @@ pub struct TripManager {
 }
 
 impl TripManager {
-    pub fn new(pathfinding_upfront: bool) -> TripManager {
+    pub fn new(pathfinding_upfront: bool, pedestrian_route_choices: usize) -> TripManager {
         TripManager {
             trips: Vec::new(),
             people: Vec::new(),
             active_trip_mode: BTreeMap::new(),
+            vehicle_trip_history: BTreeMap::new(),
             unfinished_trips: 0,
             car_id_counter: 0,
             events: Vec::new(),
             pathfinding_upfront,
+            pedestrian_route_choices,
         }
     }
 
@@ -111,8 +120,13 @@ impl TripManager {
             Some(TripLeg::Remote(ref to)) => {
                 TripEndpoint::Border(map.all_incoming_borders()[0].id, Some(to.clone()))
             }
+            Some(TripLeg::RideHail(b)) | Some(TripLeg::DemandResponsive(b)) => {
+                TripEndpoint::Bldg(*b)
+            }
             _ => unreachable!(),
         };
+        let park_and_ride = legs.iter().any(|l| matches!(l, TripLeg::Drive(_, _)))
+            && legs.iter().any(|l| matches!(l, TripLeg::RideBus(_, _)));
         let trip = Trip {
             id,
             person,
@@ -125,6 +139,10 @@ impl TripManager {
             legs: VecDeque::from(legs),
             start,
             end,
+            park_and_ride,
+            parking_search_time: None,
+            adaptive_routing: false,
+            free_flow_time: Duration::ZERO,
         };
         self.unfinished_trips += 1;
         let person = &mut self.people[trip.person.0];
@@ -163,6 +181,27 @@ impl TripManager {
             panic!("{} is doing both {} and {}?", agent, t, other);
         }
         self.active_trip_mode.insert(agent, t);
+        if let AgentID::Car(car) = agent {
+            self.record_vehicle_trip(car, t);
+        }
+    }
+
+    pub fn record_vehicle_trip(&mut self, car: CarID, t: TripID) {
+        let history = self
+            .vehicle_trip_history
+            .entry(car)
+            .or_insert_with(Vec::new);
+        // A trip might use the same vehicle for several legs
+        if history.last() != Some(&t) {
+            history.push(t);
+        }
+    }
+
+    pub fn vehicle_trip_history(&self, car: CarID) -> Vec<TripID> {
+        self.vehicle_trip_history
+            .get(&car)
+            .cloned()
+            .unwrap_or_else(Vec::new)
     }
 
     pub fn car_reached_parking_spot(
@@ -171,16 +210,28 @@ impl TripManager {
         car: CarID,
         spot: ParkingSpot,
         blocked_time: Duration,
+        parking_search_time: Option<Duration>,
         map: &Map,
         parking: &mut ParkingSimState,
         scheduler: &mut Scheduler,
     ) {
         let trip = &mut self.trips[self.active_trip_mode.remove(&AgentID::Car(car)).unwrap().0];
         trip.total_blocked_time += blocked_time;
+        if let Some(dt) = parking_search_time {
+            // Multi-stop trips search more than once
+            trip.parking_search_time =
+                Some(trip.parking_search_time.unwrap_or(Duration::ZERO) + dt);
+        }
 
         match trip.legs.pop_front() {
-            Some(TripLeg::Drive(c, DrivingGoal::ParkNear(_))) => {
+            Some(TripLeg::Drive(c, DrivingGoal::ParkNear(b))) => {
                 assert_eq!(car, c);
+                if car.1 == VehicleType::DeliveryTruck {
+                    self.events.push(Event::DeliveryCompleted(car, b));
+                }
+                if map.get_b(b).is_fuel_station() {
+                    self.events.push(Event::FuelStationVisited(car, b));
+                }
             }
             _ => unreachable!(),
         };
@@ -289,20 +340,16 @@ impl TripManager {
         let router = drive_to
             .make_router(path, map, parked_car.vehicle.vehicle_type)
             .unwrap();
-        scheduler.push(
-            now,
-            Command::SpawnCar(
-                CreateCar::for_parked_car(
-                    parked_car,
-                    router,
-                    req,
-                    start.dist_along(),
-                    trip.id,
-                    trip.person,
-                ),
-                true,
-            ),
+        let mut create_car = CreateCar::for_parked_car(
+            parked_car,
+            router,
+            req,
+            start.dist_along(),
+            trip.id,
+            trip.person,
         );
+        create_car.adaptive_routing = trip.adaptive_routing;
+        scheduler.push(now, Command::SpawnCar(create_car, true));
     }
 
     pub fn ped_ready_to_bike(
@@ -340,7 +387,7 @@ impl TripManager {
         };
         if let Some(router) = map
             .pathfind(req.clone())
-            .and_then(|path| drive_to.make_router(path, map, VehicleType::Bike))
+            .and_then(|path| drive_to.make_router(path, map, bike.1))
         {
             scheduler.push(
                 now,
@@ -423,7 +470,25 @@ impl TripManager {
         trip.total_blocked_time += blocked_time;
 
         trip.assert_walking_leg(SidewalkSpot::building(bldg, map));
-        assert!(trip.legs.is_empty());
+        if !trip.legs.is_empty() {
+            // A multi-stop trip, like a delivery run or a chain of modes. Walk on to the next leg.
+            let (id, person) = (trip.id, trip.person);
+            self.events.push(Event::PersonEntersBuilding(person, bldg));
+            if let Some(TripLeg::Drive(car, _)) = trip.legs.get(1) {
+                if car.1 == VehicleType::CargoBike {
+                    self.events.push(Event::CargoDelivered(*car, bldg));
+                }
+            }
+            self.walk_to_next_leg(
+                now,
+                id,
+                SidewalkSpot::building(bldg, map),
+                parking,
+                scheduler,
+                map,
+            );
+            return;
+        }
         assert!(!trip.finished_at.is_some());
         trip.finished_at = Some(now);
         self.unfinished_trips -= 1;
@@ -433,12 +498,83 @@ impl TripManager {
             total_time: now - trip.departure,
             blocked_time: trip.total_blocked_time,
         });
+        if trip.park_and_ride {
+            self.events.push(Event::ParkAndRideCompleted(trip.id));
+        }
         let person = trip.person;
         self.people[person.0].state = PersonState::Inside(bldg);
         self.events.push(Event::PersonEntersBuilding(person, bldg));
         self.person_finished_trip(now, person, parking, scheduler, map);
     }
 
+    // The person is at start, and the next leg of the trip is walking somewhere. Walking to a
+    // parked car goes wherever the car is right now. Aborts the trip if there's no way there.
+    fn walk_to_next_leg(
+        &mut self,
+        now: Time,
+        id: TripID,
+        start: SidewalkSpot,
+        parking: &mut ParkingSimState,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) {
+        let trip = &self.trips[id.0];
+        let person = &self.people[trip.person.0];
+        if trip.legs[0] != TripLeg::Walk(SidewalkSpot::deferred_parking_spot()) {
+            if !trip.spawn_ped(now, start, person, map, scheduler, &mut self.events) {
+                self.abort_trip(now, id, None, parking, scheduler, map);
+            }
+            return;
+        }
+
+        let car = match trip.legs.get(1) {
+            Some(TripLeg::Drive(c, _)) => *c,
+            _ => unreachable!(),
+        };
+        if let Some(parked_car) = parking.lookup_parked_car(car).cloned() {
+            let walking_goal = SidewalkSpot::parking_spot(parked_car.spot, map, parking);
+            let req = PathRequest {
+                start: start.sidewalk_pos,
+                end: walking_goal.sidewalk_pos,
+                constraints: PathConstraints::Pedestrian,
+            };
+            if let Some(path) = map.pathfind(req.clone()) {
+                scheduler.push(
+                    now,
+                    Command::SpawnPed(CreatePedestrian {
+                        id: person.ped,
+                        speed: person.ped_speed,
+                        start,
+                        goal: walking_goal,
+                        path,
+                        req,
+                        trip: id,
+                        person: person.id,
+                    }),
+                );
+            } else {
+                self.events.push(Event::Alert(
+                    AlertLocation::Person(person.id),
+                    format!("{} couldn't find the walking path to {}: {}", id, car, req),
+                ));
+                // Move the car to the destination
+                parking.remove_parked_car(parked_car.clone());
+                self.abort_trip(now, id, Some(parked_car.vehicle), parking, scheduler, map);
+            }
+        } else {
+            // This should only happen when a driving trip has been aborted and there was
+            // absolutely no room to warp the car.
+            self.events.push(Event::Alert(
+                AlertLocation::Person(person.id),
+                format!(
+                    "{} should have {} parked somewhere, but it's unavailable, so aborting {}",
+                    person.id, car, id
+                ),
+            ));
+            self.abort_trip(now, id, None, parking, scheduler, map);
+        }
+    }
+
     // If no route is returned, the pedestrian boarded a bus immediately.
     pub fn ped_reached_bus_stop(
         &mut self,
@@ -492,6 +628,150 @@ impl TripManager {
         }
     }
 
+    // (pickup, dropoff)
+    pub fn ride_hail_endpoints(&self, id: TripID) -> (BuildingID, BuildingID) {
+        let trip = &self.trips[id.0];
+        match (&trip.start, &trip.legs[0]) {
+            (TripEndpoint::Bldg(b1), TripLeg::RideHail(b2))
+            | (TripEndpoint::Bldg(b1), TripLeg::DemandResponsive(b2)) => (*b1, *b2),
+            _ => unreachable!(),
+        }
+    }
+
+    // Is this trip waiting for or riding in a DRT minibus, instead of a ride-hailing car?
+    pub fn uses_drt(&self, id: TripID) -> bool {
+        matches!(self.trips[id.0].legs[0], TripLeg::DemandResponsive(_))
+    }
+
+    pub fn ride_hail_picked_up(&mut self, id: TripID, car: CarID) {
+        let person = self.trips[id.0].person;
+        self.picked_up_at_bldg(id, car);
+        if self.uses_drt(id) {
+            self.events
+                .push(Event::DRTPickup(self.people[person.0].ped, car));
+        } else {
+            self.events.push(Event::RideHailPickup(person, car));
+        }
+    }
+
+    pub fn ride_hail_finished(
+        &mut self,
+        now: Time,
+        id: TripID,
+        parking: &mut ParkingSimState,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) {
+        let bldg = self.ride_hail_endpoints(id).1;
+        let person = self.trips[id.0].person;
+        let car = self.people[person.0].on_bus.unwrap();
+        if self.uses_drt(id) {
+            self.events
+                .push(Event::DRTDropoff(self.people[person.0].ped, car));
+        } else {
+            self.events.push(Event::RideHailDropoff(person, car));
+        }
+        self.dropped_off_at_bldg(now, id, bldg, parking, scheduler, map);
+    }
+
+    // False if the student hasn't started their trip yet, so they miss the bus. Then use
+    // school_bus_missed.
+    pub fn school_bus_picked_up(&mut self, id: TripID, car: CarID) -> bool {
+        let person = self.trips[id.0].person;
+        if self.people[person.0].state != PersonState::Trip(id) {
+            return false;
+        }
+        self.picked_up_at_bldg(id, car);
+        self.events
+            .push(Event::StudentBoarded(self.people[person.0].ped, car));
+        true
+    }
+
+    // The student is still busy with an earlier trip when the bus comes by, so they don't go to
+    // school at all.
+    pub fn school_bus_missed(&mut self, id: TripID) {
+        if self.trips[id.0].aborted {
+            return;
+        }
+        let person = self.trips[id.0].person;
+        self.people[person.0]
+            .delayed_trips
+            .retain(|(trip, _, _, _)| *trip != id);
+        self.cancel_trip(id);
+    }
+
+    // The student gets out at the curb near the school, then walks inside.
+    pub fn school_bus_dropped_off(
+        &mut self,
+        now: Time,
+        id: TripID,
+        start: SidewalkSpot,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        let trip = &mut self.trips[id.0];
+        let person = &mut self.people[trip.person.0];
+        let car = person.on_bus.take().unwrap();
+        self.active_trip_mode
+            .remove(&AgentID::BusPassenger(person.id, car))
+            .unwrap();
+        let school = match trip.legs.pop_front().unwrap() {
+            TripLeg::SchoolBusDrive(_, b) => b,
+            _ => unreachable!(),
+        };
+        self.events.push(Event::StudentArrived(person.ped, school));
+
+        if !trip.spawn_ped(now, start, person, map, scheduler, &mut self.events) {
+            self.unfinished_trips -= 1;
+        }
+    }
+
+    // The person gets into a vehicle waiting outside the building where the trip starts.
+    fn picked_up_at_bldg(&mut self, id: TripID, car: CarID) {
+        let trip = &self.trips[id.0];
+        let person = trip.person;
+        let bldg = match trip.start {
+            TripEndpoint::Bldg(b) => b,
+            _ => unreachable!(),
+        };
+        self.active_trip_mode
+            .insert(AgentID::BusPassenger(person, car), id);
+        self.people[person.0].on_bus = Some(car);
+        self.events.push(Event::PersonLeavesBuilding(person, bldg));
+    }
+
+    // The person gets out of a vehicle at the building where the trip ends.
+    fn dropped_off_at_bldg(
+        &mut self,
+        now: Time,
+        id: TripID,
+        bldg: BuildingID,
+        parking: &mut ParkingSimState,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) {
+        let trip = &mut self.trips[id.0];
+        let person = trip.person;
+        let car = self.people[person.0].on_bus.take().unwrap();
+        self.active_trip_mode
+            .remove(&AgentID::BusPassenger(person, car))
+            .unwrap();
+
+        trip.legs.pop_front();
+        assert!(trip.legs.is_empty());
+        trip.finished_at = Some(now);
+        self.unfinished_trips -= 1;
+        self.events.push(Event::TripFinished {
+            trip: trip.id,
+            mode: trip.mode,
+            total_time: now - trip.departure,
+            blocked_time: trip.total_blocked_time,
+        });
+        self.people[person.0].state = PersonState::Inside(bldg);
+        self.events.push(Event::PersonEntersBuilding(person, bldg));
+        self.person_finished_trip(now, person, parking, scheduler, map);
+    }
+
     pub fn ped_boarded_bus(
         &mut self,
         now: Time,
@@ -712,7 +992,9 @@ impl TripManager {
         };
         // Don't forget the car!
         if let Some(vehicle) = abandoned_vehicle {
-            if vehicle.vehicle_type == VehicleType::Car {
+            if vehicle.vehicle_type == VehicleType::Car
+                || vehicle.vehicle_type == VehicleType::DeliveryTruck
+            {
                 if let TripEndpoint::Bldg(b) = trip.end {
                     let driving_lane = map.find_driving_lane_near_building(b);
                     if let Some(spot) = parking
@@ -793,6 +1075,15 @@ impl TripManager {
             TripLeg::Walk(_) => AgentID::Pedestrian(person.ped),
             TripLeg::Drive(c, _) => AgentID::Car(*c),
             TripLeg::RideBus(_, _) => AgentID::BusPassenger(person.id, person.on_bus.unwrap()),
+            TripLeg::RideHail(_) | TripLeg::DemandResponsive(_) | TripLeg::SchoolBusDrive(_, _) => {
+                match person.on_bus {
+                    Some(car) => AgentID::BusPassenger(person.id, car),
+                    // Still waiting for a driver
+                    None => {
+                        return TripResult::ModeChange;
+                    }
+                }
+            }
             TripLeg::Remote(_) => {
                 return TripResult::RemoteTrip;
             }
@@ -860,6 +1151,29 @@ impl TripManager {
         std::mem::replace(&mut self.events, Vec::new())
     }
 
+    pub fn enable_adaptive_routing(&mut self, id: TripID) {
+        self.trips[id.0].adaptive_routing = true;
+    }
+
+    pub fn add_free_flow_time(&mut self, id: TripID, dt: Duration) {
+        self.trips[id.0].free_flow_time += dt;
+    }
+
+    pub fn free_flow_time(&self, id: TripID) -> Duration {
+        self.trips[id.0].free_flow_time
+    }
+
+    pub fn parking_search_time(&self, id: TripID) -> Option<Duration> {
+        self.trips[id.0].parking_search_time
+    }
+
+    pub fn all_parking_search_times(&self) -> Vec<Duration> {
+        self.trips
+            .iter()
+            .filter_map(|t| t.parking_search_time)
+            .collect()
+    }
+
     pub fn trip_info(&self, id: TripID) -> (Time, TripEndpoint, TripEndpoint, TripMode) {
         let t = &self.trips[id.0];
         (t.departure, t.start.clone(), t.end.clone(), t.mode)
@@ -879,6 +1193,17 @@ impl TripManager {
         people
     }
 
+    // Move a vehicle from its old owner to whoever it now belongs to.
+    // TODO The old owner's future trips may still expect to use it.
+    pub fn transfer_vehicle(&mut self, old_owner: Option<PersonID>, vehicle: Vehicle) {
+        if let Some(p) = old_owner {
+            self.people[p.0].vehicles.retain(|v| v.id != vehicle.id);
+        }
+        if let Some(p) = vehicle.owner {
+            self.people[p.0].vehicles.push(vehicle);
+        }
+    }
+
     pub fn get_person(&self, p: PersonID) -> Option<&Person> {
         self.people.get(p.0)
     }
@@ -977,15 +1302,10 @@ impl TripManager {
                 if let Some(router) =
                     maybe_path.and_then(|path| goal.make_router(path, map, vehicle.vehicle_type))
                 {
-                    scheduler.push(
-                        now,
-                        Command::SpawnCar(
-                            CreateCar::for_appearing(
-                                vehicle, start_pos, router, req, trip, person.id,
-                            ),
-                            retry_if_no_room,
-                        ),
-                    );
+                    let mut create_car =
+                        CreateCar::for_appearing(vehicle, start_pos, router, req, trip, person.id);
+                    create_car.adaptive_routing = self.trips[trip.0].adaptive_routing;
+                    scheduler.push(now, Command::SpawnCar(create_car, retry_if_no_room));
                 } else {
                     self.events.push(Event::Alert(
                         AlertLocation::Person(person.id),
@@ -1009,66 +1329,19 @@ impl TripManager {
                 let vehicle = person.get_vehicle(use_vehicle);
                 self.abort_trip(now, trip, Some(vehicle), parking, scheduler, map);
             }
-            TripSpec::UsingParkedCar {
-                car, start_bldg, ..
-            } => {
+            TripSpec::UsingParkedCar { start_bldg, .. }
+            | TripSpec::UsingDeliveryTruck { start_bldg, .. }
+            | TripSpec::MultimodalChain { start_bldg, .. } => {
                 assert_eq!(person.state, PersonState::Inside(start_bldg));
                 person.state = PersonState::Trip(trip);
-
-                // TODO For now, use the car we decided to statically. That makes sense in most
-                // cases.
-
-                if let Some(parked_car) = parking.lookup_parked_car(car).cloned() {
-                    let start = SidewalkSpot::building(start_bldg, map);
-                    let walking_goal = SidewalkSpot::parking_spot(parked_car.spot, map, parking);
-                    let req = PathRequest {
-                        start: start.sidewalk_pos,
-                        end: walking_goal.sidewalk_pos,
-                        constraints: PathConstraints::Pedestrian,
-                    };
-                    if let Some(path) = map.pathfind(req.clone()) {
-                        scheduler.push(
-                            now,
-                            Command::SpawnPed(CreatePedestrian {
-                                id: person.ped,
-                                speed: person.ped_speed,
-                                start,
-                                goal: walking_goal,
-                                path,
-                                req,
-                                trip,
-                                person: person.id,
-                            }),
-                        );
-                    } else {
-                        self.events.push(Event::Alert(
-                            AlertLocation::Person(person.id),
-                            format!("UsingParkedCar trip couldn't find the walking path {}", req),
-                        ));
-                        // Move the car to the destination
-                        parking.remove_parked_car(parked_car.clone());
-                        self.abort_trip(
-                            now,
-                            trip,
-                            Some(parked_car.vehicle),
-                            parking,
-                            scheduler,
-                            map,
-                        );
-                    }
-                } else {
-                    // This should only happen when a driving trip has been aborted and there was
-                    // absolutely no room to warp the car.
-                    self.events.push(Event::Alert(
-                        AlertLocation::Person(person.id),
-                        format!(
-                            "{} should have {} parked somewhere, but it's unavailable, so \
-                             aborting {}",
-                            person.id, car, trip
-                        ),
-                    ));
-                    self.abort_trip(now, trip, None, parking, scheduler, map);
-                }
+                self.walk_to_next_leg(
+                    now,
+                    trip,
+                    SidewalkSpot::building(start_bldg, map),
+                    parking,
+                    scheduler,
+                    map,
+                );
             }
             TripSpec::JustWalking { start, goal } => {
                 assert_eq!(
@@ -1101,7 +1374,15 @@ impl TripManager {
                 person.state = PersonState::Trip(trip);
 
                 let req = maybe_req.unwrap();
-                if let Some(path) = maybe_path {
+                if let Some(mut path) = maybe_path {
+                    if self.pedestrian_route_choices > 1 {
+                        // Spread people out deterministically over the alternatives
+                        let mut choices =
+                            map.pathfind_walking_alternatives(&req, self.pedestrian_route_choices);
+                        if !choices.is_empty() {
+                            path = choices.remove(trip.0 % choices.len());
+                        }
+                    }
                     scheduler.push(
                         now,
                         Command::SpawnPed(CreatePedestrian {
@@ -1178,6 +1459,36 @@ impl TripManager {
                     self.abort_trip(now, trip, None, parking, scheduler, map);
                 }
             }
+            TripSpec::UsingCargoBike { start_bldg, .. } => {
+                assert_eq!(person.state, PersonState::Inside(start_bldg));
+                person.state = PersonState::Trip(trip);
+
+                let start = SidewalkSpot::building(start_bldg, map);
+                let walk_to =
+                    SidewalkSpot::bike_from_bike_rack(start.sidewalk_pos.lane(), map).unwrap();
+                let req = maybe_req.unwrap();
+                if let Some(path) = maybe_path {
+                    scheduler.push(
+                        now,
+                        Command::SpawnPed(CreatePedestrian {
+                            id: person.ped,
+                            speed: person.ped_speed,
+                            start,
+                            goal: walk_to,
+                            path,
+                            req,
+                            trip,
+                            person: person.id,
+                        }),
+                    );
+                } else {
+                    self.events.push(Event::Alert(
+                        AlertLocation::Person(person.id),
+                        format!("UsingCargoBike trip couldn't find the first path {}", req),
+                    ));
+                    self.abort_trip(now, trip, None, parking, scheduler, map);
+                }
+            }
             TripSpec::UsingTransit { start, stop1, .. } => {
                 assert_eq!(
                     person.state,
@@ -1232,6 +1543,17 @@ impl TripManager {
                     self.abort_trip(now, trip, None, parking, scheduler, map);
                 }
             }
+            TripSpec::UsingRideHail { start_bldg, .. }
+            | TripSpec::UsingDemandResponsiveTransit { start_bldg, .. } => {
+                assert_eq!(person.state, PersonState::Inside(start_bldg));
+                person.state = PersonState::Trip(trip);
+                scheduler.push(now, Command::RequestRideHail(trip));
+            }
+            TripSpec::UsingSchoolBus { start_bldg, .. } => {
+                // Wait inside for the bus to show up
+                assert_eq!(person.state, PersonState::Inside(start_bldg));
+                person.state = PersonState::Trip(trip);
+            }
             TripSpec::Remote {
                 trip_time, from, ..
             } => {
@@ -1264,6 +1586,14 @@ struct Trip {
     start: TripEndpoint,
     end: TripEndpoint,
     person: PersonID,
+    // Drove to a bus stop, then rode transit
+    park_and_ride: bool,
+    // Only set once a car has parked
+    parking_search_time: Option<Duration>,
+    // Any car driven during the trip replans around congestion
+    adaptive_routing: bool,
+    // How long every leg started so far would take with no congestion at all
+    free_flow_time: Duration,
 }
 
 impl Trip {
@@ -1334,6 +1664,12 @@ pub enum TripLeg {
     // A person may own many vehicles, so specify which they use
     Drive(CarID, DrivingGoal),
     RideBus(BusRouteID, BusStopID),
+    // To this building
+    RideHail(BuildingID),
+    // Like RideHail, but in a minibus from the DRT fleet
+    DemandResponsive(BuildingID),
+    // Ride this school bus to the curb near the school
+    SchoolBusDrive(CarID, BuildingID),
     Remote(OffMapLocation),
 }
 
@@ -1359,10 +1695,10 @@ impl TripMode {
         match id {
             AgentID::Pedestrian(_) => TripMode::Walk,
             AgentID::Car(id) => match id.1 {
-                VehicleType::Car => TripMode::Drive,
-                VehicleType::Bike => TripMode::Bike,
+                VehicleType::Car | VehicleType::DeliveryTruck => TripMode::Drive,
+                VehicleType::Bike | VehicleType::EBike | VehicleType::CargoBike => TripMode::Bike,
                 // TODO Little confusing; this means buses, not bus riders.
-                VehicleType::Bus => TripMode::Transit,
+                VehicleType::Bus | VehicleType::SchoolBus => TripMode::Transit,
             },
             // TODO Now we can detangle this, right?
             AgentID::BusPassenger(_, _) => TripMode::Transit,